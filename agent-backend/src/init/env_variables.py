@@ -31,6 +31,7 @@ UPLOADS_BASE_PATH = os.getenv("UPLOADS_BASE_PATH")
 PINECONE_API_KEY = os.getenv("PINECONE_API_KEY")
 VECTOR_DATABASE = os.getenv("VECTOR_DATABASE", VectorDatabase.Qdrant)
 HOSTED_PINECONE_API_KEY = os.getenv("HOSTED_PINECONE_API_KEY")
+VECTOR_DB_ENCRYPTION_KEYS = os.getenv("VECTOR_DB_ENCRYPTION_KEYS", "")
 
 
 def _set_max_threads() -> int:
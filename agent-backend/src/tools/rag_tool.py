@@ -11,6 +11,7 @@ from .global_tools import GlobalBaseTool
 from models.mongo import Model, Tool, Datasource
 
 from vectorstores.factory import vectorstore_factory
+from utils.credentials import decrypt_credential
 
 from langchain_community.vectorstores.qdrant import Qdrant #TODO: remove
 from langchain_community.vectorstores.pinecone import Pinecone #TODO: remove
@@ -70,7 +71,7 @@ class RagTool(GlobalBaseTool):
 
         vector_db = datasource.vector_db if datasource.byoVectorDb else None
         type = vector_db.type if vector_db else None
-        api_key = vector_db.apiKey if vector_db else None
+        api_key = decrypt_credential(vector_db.apiKey) if vector_db else None
         url = vector_db.url if vector_db else None
         namespace = datasource.namespace
         collection = datasource.collectionName if datasource.byoVectorDb else datasource.region
@@ -0,0 +1,33 @@
+import base64
+
+from Crypto.Cipher import AES
+
+from init.env_variables import VECTOR_DB_ENCRYPTION_KEYS
+
+# Written by the webapp as enc:v1:<key id>:<base64 of nonce, ciphertext and tag>
+ENCRYPTED_PREFIX = "enc:v1:"
+NONCE_LENGTH = 12
+TAG_LENGTH = 16
+
+
+def _encryption_keys() -> dict:
+    keys = {}
+    for key in VECTOR_DB_ENCRYPTION_KEYS.split(","):
+        key_id, _, encoded_key = key.strip().partition(":")
+        if key_id and encoded_key:
+            keys[key_id] = base64.b64decode(encoded_key)
+    return keys
+
+
+def decrypt_credential(credential: str | None) -> str | None:
+    """Decrypts a vector DB credential. Credentials stored before encryption was configured are returned as they are."""
+    if not credential or not credential.startswith(ENCRYPTED_PREFIX):
+        return credential
+    key_id, _, encoded_payload = credential[len(ENCRYPTED_PREFIX):].partition(":")
+    key = _encryption_keys().get(key_id)
+    if key is None:
+        raise ValueError(f"Credential is encrypted with key `{key_id}`, which is not configured")
+    payload = base64.b64decode(encoded_payload)
+    nonce, ciphertext, tag = payload[:NONCE_LENGTH], payload[NONCE_LENGTH:-TAG_LENGTH], payload[-TAG_LENGTH:]
+    cipher = AES.new(key, AES.MODE_GCM, nonce=nonce)
+    return cipher.decrypt_and_verify(ciphertext, tag).decode("utf-8")
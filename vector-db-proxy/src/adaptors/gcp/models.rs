@@ -1,14 +1,21 @@
-use crate::adaptors::gcp::pubsub::subscribe_to_topic;
+use crate::adaptors::gcp::pubsub::{publish_to_topic, subscribe_to_topic};
 use crate::adaptors::mongo::models::DataSources;
 use crate::init::env_variables::GLOBAL_DATA;
-use crate::messages::models::{MessageQueueConnection, QueueConnectionTypes};
+use crate::messages::models::{MessageAck, MessageQueueConnection, QueueConnectionTypes};
 use crate::messages::tasks::process_message;
+use crate::utils::health::set_consumer_attached;
+use crate::utils::metrics::MESSAGES_CONSUMED;
+use crate::utils::shutdown::shutdown_requested;
+use crate::utils::telemetry::set_parent_from_headers;
 use crossbeam::channel::Sender;
 use futures::StreamExt;
+use google_cloud_googleapis::pubsub::v1::PubsubMessage;
 use google_cloud_pubsub::subscription::MessageStream;
 use mongodb::Database;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::{Mutex, RwLock};
+use tracing::{Instrument, Span};
 
 pub struct PubSubConnect {
     pub topic: String,
@@ -43,10 +50,27 @@ pub async fn pubsub_consume(
     stream: &Arc<Mutex<MessageStream>>,
     //vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
     mongo_client: Arc<RwLock<Database>>,
-    sender: Sender<(DataSources, Option<String>, String)>,
+    sender: Sender<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
 ) {
     if let Ok(mut stream) = stream.try_lock() {
-        while let Some(message) = stream.next().await {
+        set_consumer_attached(true);
+        loop {
+            // Messages pulled once shutdown has been requested are left unacknowledged, so that
+            // PubSub redelivers them
+            let message = tokio::select! {
+                message = stream.next() => message,
+                _ = shutdown_requested() => None,
+            };
+            let message = match message {
+                Some(message) => message,
+                None => break,
+            };
             let cloned_message = message.message.clone();
             let message_attributes = cloned_message.attributes;
             log::debug!("Message attributes: {:?}", message_attributes);
@@ -74,6 +98,14 @@ pub async fn pubsub_consume(
                         let mongo_client = Arc::clone(&mongo_client);
                         let sender = sender.clone();
                         log::debug!("Datasource ID: {}", datasource_id);
+                        MESSAGES_CONSUMED.with_label_values(&["google"]).inc();
+                        let span = tracing::info_span!(
+                            "consume_message",
+                            provider = "google",
+                            datasource_id,
+                            message_id = cloned_message.message_id.as_str()
+                        );
+                        set_parent_from_headers(&span, &message_attributes);
                         process_message(
                             message_string,
                             stream_type,
@@ -82,7 +114,9 @@ pub async fn pubsub_consume(
                             //qdrant_client,
                             mongo_client,
                             sender,
+                            None,
                         )
+                        .instrument(span)
                         .await;
                     }
                     None => {
@@ -94,5 +128,34 @@ pub async fn pubsub_consume(
             }
             let _ = message.ack().await;
         }
+        set_consumer_attached(false);
+    }
+}
+
+/// Publishes tasks that were taken from the subscription but not processed back to the topic,
+/// with the same stream attribute the consumer expects
+pub async fn pubsub_requeue(tasks: Vec<(DataSources, Option<String>, String)>) -> usize {
+    let topic = GLOBAL_DATA.read().await.rabbitmq_stream.clone();
+    let messages = tasks
+        .into_iter()
+        .map(|(datasource, stream_config_key, message)| PubsubMessage {
+            data: message.into_bytes(),
+            attributes: HashMap::from([(
+                "_stream".to_string(),
+                format!(
+                    "{}_{}",
+                    datasource.id,
+                    stream_config_key.unwrap_or_default()
+                ),
+            )]),
+            ..Default::default()
+        })
+        .collect();
+    match publish_to_topic(topic.as_str(), messages).await {
+        Ok(requeued) => requeued,
+        Err(e) => {
+            log::error!("Could not requeue messages to PubSub. Error: {}", e);
+            0
+        }
     }
 }
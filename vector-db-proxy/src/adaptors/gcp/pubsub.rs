@@ -1,4 +1,5 @@
 use anyhow::Result;
+use google_cloud_googleapis::pubsub::v1::PubsubMessage;
 use google_cloud_pubsub::client::{Client, ClientConfig};
 use google_cloud_pubsub::subscription::{MessageStream, SubscriptionConfig};
 use google_cloud_pubsub::topic::TopicConfig;
@@ -58,3 +59,19 @@ pub async fn subscribe_to_topic(connection_details: PubSubConnect) -> Result<Mes
     let stream = subscription.subscribe(None).await?;
     Ok(stream)
 }
+
+/// Publishes the messages to the topic, returning how many were published
+pub async fn publish_to_topic(topic: &str, messages: Vec<PubsubMessage>) -> Result<usize> {
+    let client_config = ClientConfig::default().with_auth().await?;
+    let client = Client::new(client_config).await?;
+    let mut publisher = client.topic(topic).new_publisher(None);
+    let mut published = 0;
+    for awaiter in publisher.publish_bulk(messages).await {
+        match awaiter.get().await {
+            Ok(_) => published += 1,
+            Err(e) => log::error!("Could not publish message to {}. Error: {}", topic, e),
+        }
+    }
+    publisher.shutdown().await;
+    Ok(published)
+}
@@ -0,0 +1,44 @@
+use crate::adaptors::kafka::models::{KafkaConnect, KafkaConsumer, PartitionOffsetsContext};
+use anyhow::{anyhow, Result};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::Consumer;
+use rdkafka::producer::FutureProducer;
+
+/// Joins the consumer group and subscribes to the topic. Offsets are stored by the workers once
+/// they have processed a message and every earlier message of its partition, and stored offsets
+/// are committed in the background and on shutdown. A message that was consumed but not processed
+/// is delivered again to whichever consumer takes over its partition.
+pub fn subscribe_to_topic(connection_details: &KafkaConnect) -> Result<KafkaConsumer> {
+    let consumer: KafkaConsumer = ClientConfig::new()
+        .set("bootstrap.servers", &connection_details.bootstrap_servers)
+        .set("group.id", &connection_details.group_id)
+        .set("enable.auto.commit", "true")
+        .set("enable.auto.offset.store", "false")
+        .set("auto.offset.reset", "earliest")
+        .create_with_context(PartitionOffsetsContext)
+        .map_err(|e| {
+            log::error!("Error: {}", e);
+            anyhow!("Could not create Kafka consumer. Error: {}", e)
+        })?;
+    consumer
+        .subscribe(&[connection_details.topic.as_str()])
+        .map_err(|e| {
+            log::error!("Error: {}", e);
+            anyhow!(
+                "Could not subscribe to Kafka topic {}. Error: {}",
+                connection_details.topic,
+                e
+            )
+        })?;
+    Ok(consumer)
+}
+
+pub fn create_producer(connection_details: &KafkaConnect) -> Result<FutureProducer> {
+    ClientConfig::new()
+        .set("bootstrap.servers", &connection_details.bootstrap_servers)
+        .create()
+        .map_err(|e| {
+            log::error!("Error: {}", e);
+            anyhow!("Could not create Kafka producer. Error: {}", e)
+        })
+}
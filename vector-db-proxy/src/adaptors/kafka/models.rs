@@ -0,0 +1,326 @@
+use crate::adaptors::kafka::client::{create_producer, subscribe_to_topic};
+use crate::adaptors::mongo::models::DataSources;
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::messages::models::{MessageAck, MessageQueueConnection, QueueConnectionTypes};
+use crate::messages::tasks::process_message;
+use crate::utils::health::set_consumer_attached;
+use crate::utils::metrics::MESSAGES_CONSUMED;
+use crate::utils::shutdown::shutdown_requested;
+use crate::utils::telemetry::{set_parent_from_headers, TRACE_CONTEXT_HEADERS};
+use crossbeam::channel::Sender;
+use log::{error, warn};
+use mongodb::Database;
+use once_cell::sync::Lazy;
+use rdkafka::consumer::{CommitMode, Consumer, ConsumerContext, Rebalance, StreamConsumer};
+use rdkafka::message::{BorrowedMessage, Header, Headers, Message, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{ClientContext, Offset, TopicPartitionList};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio::sync::RwLock;
+use tokio::time::Duration;
+use tracing::{Instrument, Span};
+
+// How long the broker may take to acknowledge a requeued message
+const REQUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+// How long to wait before polling again after the consumer returned an error
+const CONSUME_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct PartitionOffsets {
+    // Offsets of the messages taken off the partition that have not been processed yet
+    pending: BTreeSet<i64>,
+    // The offset after the last message that was processed
+    processed: i64,
+}
+
+// Workers process messages out of order, so the offset to resume each partition from is tracked
+// by topic and partition
+static PARTITION_OFFSETS: Lazy<Mutex<HashMap<(String, i32), PartitionOffsets>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Forgets the offsets tracked for partitions as they are assigned or revoked. Messages taken off
+/// a revoked partition are delivered again to whoever takes it over, from the last committed
+/// offset, and a partition assigned anew starts over from there as well.
+pub struct PartitionOffsetsContext;
+
+impl ClientContext for PartitionOffsetsContext {}
+
+impl ConsumerContext for PartitionOffsetsContext {
+    fn pre_rebalance(&self, rebalance: &Rebalance<'_>) {
+        let partitions = match rebalance {
+            Rebalance::Assign(partitions) | Rebalance::Revoke(partitions) => partitions,
+            Rebalance::Error(e) => {
+                error!("Kafka rebalance failed. Error: {}", e);
+                return;
+            }
+        };
+        if let Ok(mut partition_offsets) = PARTITION_OFFSETS.lock() {
+            for partition in partitions.elements() {
+                partition_offsets.remove(&(partition.topic().to_string(), partition.partition()));
+            }
+        }
+    }
+}
+
+pub type KafkaConsumer = StreamConsumer<PartitionOffsetsContext>;
+
+pub struct KafkaConnect {
+    pub bootstrap_servers: String,
+    pub topic: String,
+    pub group_id: String,
+}
+
+impl Default for KafkaConnect {
+    fn default() -> Self {
+        KafkaConnect {
+            bootstrap_servers: String::from("localhost:9092"),
+            topic: String::from("streaming"),
+            group_id: String::from("vector-db-proxy"),
+        }
+    }
+}
+impl MessageQueueConnection for KafkaConnect {
+    async fn connect(&self) -> Option<QueueConnectionTypes> {
+        let consumer = match subscribe_to_topic(self) {
+            Ok(consumer) => consumer,
+            Err(e) => {
+                error!("{}", e);
+                return None;
+            }
+        };
+        match create_producer(self) {
+            Ok(producer) => Some(QueueConnectionTypes::Kafka(Arc::new(consumer), producer)),
+            Err(e) => {
+                error!("{}", e);
+                None
+            }
+        }
+    }
+}
+
+fn header_value(message: &BorrowedMessage<'_>, key: &str) -> Option<String> {
+    message
+        .headers()?
+        .iter()
+        .find(|header| header.key == key)
+        .and_then(|header| header.value)
+        .map(|value| String::from_utf8_lossy(value).to_string())
+}
+
+/// Marks a message as taken off its partition, returning what acknowledges it once processed
+fn take_message(consumer: &Arc<KafkaConsumer>, message: &BorrowedMessage<'_>) -> MessageAck {
+    if let Ok(mut partition_offsets) = PARTITION_OFFSETS.lock() {
+        partition_offsets
+            .entry((message.topic().to_string(), message.partition()))
+            .or_default()
+            .pending
+            .insert(message.offset());
+    }
+    MessageAck::Kafka {
+        consumer: Arc::clone(consumer),
+        topic: message.topic().to_string(),
+        partition: message.partition(),
+        offset: message.offset(),
+    }
+}
+
+/// Stores the offset to resume a partition from once one of its messages has been processed. That
+/// is the earliest message still being processed, so that a message processed before an earlier
+/// one does not have the earlier one skipped when the partition is taken over. Stored offsets are
+/// committed in the background. Nothing is stored for a partition revoked since the message was
+/// taken off it.
+pub fn kafka_ack(consumer: &KafkaConsumer, topic: &str, partition: i32, offset: i64) {
+    let resume_offset = match PARTITION_OFFSETS.lock() {
+        Ok(mut partition_offsets) => {
+            let Some(offsets) = partition_offsets.get_mut(&(topic.to_string(), partition)) else {
+                return;
+            };
+            if !offsets.pending.remove(&offset) {
+                return;
+            }
+            offsets.processed = offsets.processed.max(offset + 1);
+            offsets
+                .pending
+                .first()
+                .copied()
+                .unwrap_or(offsets.processed)
+        }
+        Err(_) => return,
+    };
+    let mut offsets = TopicPartitionList::new();
+    if let Err(e) = offsets
+        .add_partition_offset(topic, partition, Offset::Offset(resume_offset))
+        .and_then(|_| consumer.store_offsets(&offsets))
+    {
+        error!(
+            "Could not store offset {} of partition {}. Error: {}",
+            resume_offset, partition, e
+        );
+    }
+}
+
+/// Commits the offsets stored for the messages the workers processed, so that whoever takes over
+/// the partitions starts right after them, and leaves the consumer group
+pub fn kafka_close(consumer: &KafkaConsumer) {
+    if let Err(e) = consumer.commit_consumer_state(CommitMode::Sync) {
+        error!("Could not commit Kafka offsets on shutdown. Error: {}", e);
+    }
+    consumer.unsubscribe();
+}
+
+/// Hands a message to `process_message`. Like the RabbitMQ `stream` header, the `stream` header,
+/// or the message key when there is no such header, is `<datasource id>_<stream config key>`, and
+/// a `type` header marks a file upload. Messages that can not be processed are acknowledged
+/// straight away, as delivering them again would not help.
+async fn handle_message(
+    message: &BorrowedMessage<'_>,
+    mongo_client: Arc<RwLock<Database>>,
+    sender: Sender<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
+    ack: MessageAck,
+) {
+    let stream = header_value(message, "stream").or_else(|| {
+        message
+            .key_view::<str>()
+            .and_then(|key| key.ok())
+            .map(|key| key.to_string())
+    });
+    let Some(stream_string) = stream else {
+        warn!("There was no stream ID present in message headers or key...can not proceed!");
+        ack.ack().await;
+        return;
+    };
+    let (datasource_id, stream_config_key, stream_type) = match header_value(message, "type") {
+        Some(stream_type) => (
+            stream_string.split('_').next().unwrap_or_default(),
+            None,
+            Some(stream_type),
+        ),
+        None => match stream_string.split_once('_') {
+            Some((datasource_id, stream_config_key)) => {
+                (datasource_id, Some(stream_config_key.to_string()), None)
+            }
+            None => {
+                warn!(
+                    "Expected stream ID `{}` to be <datasource id>_<stream>...can not proceed!",
+                    stream_string
+                );
+                ack.ack().await;
+                return;
+            }
+        },
+    };
+    let message_string = match message.payload_view::<str>() {
+        Some(Ok(payload)) => payload.to_string(),
+        _ => {
+            warn!("Could not get message content from Kafka");
+            ack.ack().await;
+            return;
+        }
+    };
+    MESSAGES_CONSUMED.with_label_values(&["kafka"]).inc();
+    let trace_headers: HashMap<String, String> = TRACE_CONTEXT_HEADERS
+        .iter()
+        .filter_map(|header| header_value(message, header).map(|value| (header.to_string(), value)))
+        .collect();
+    let message_id = format!("{}-{}", message.partition(), message.offset());
+    let span = tracing::info_span!(
+        "consume_message",
+        provider = "kafka",
+        datasource_id,
+        message_id = message_id.as_str()
+    );
+    set_parent_from_headers(&span, &trace_headers);
+    process_message(
+        message_string,
+        stream_type,
+        datasource_id,
+        stream_config_key,
+        mongo_client,
+        sender,
+        Some(ack),
+    )
+    .instrument(span)
+    .await;
+}
+
+pub async fn kafka_consume(
+    consumer: &Arc<KafkaConsumer>,
+    mongo_client: Arc<RwLock<Database>>,
+    sender: Sender<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
+) {
+    set_consumer_attached(true);
+    loop {
+        // Messages received once shutdown has been requested are not processed, so their offsets
+        // are not stored and they are delivered again
+        let message = tokio::select! {
+            message = consumer.recv() => message,
+            _ = shutdown_requested() => break,
+        };
+        match message {
+            Ok(message) => {
+                set_consumer_attached(true);
+                let ack = take_message(consumer, &message);
+                handle_message(&message, Arc::clone(&mongo_client), sender.clone(), ack).await;
+            }
+            Err(e) => {
+                set_consumer_attached(false);
+                error!(
+                    "There was an error when consuming messages from Kafka. Error: {}",
+                    e
+                );
+                tokio::time::sleep(CONSUME_ERROR_BACKOFF).await;
+            }
+        }
+    }
+    set_consumer_attached(false);
+    // The consumer stays in the group until the workers have processed what was taken off it,
+    // when `kafka_close` commits their offsets
+    log::info!("Stopped consuming from Kafka");
+}
+
+/// Produces tasks that were not processed back to the topic, with the same stream header the
+/// consumer expects. Only tasks that did not come off the topic, like ingested records, get here.
+/// The stream is also the key, so that the records of a stream stay on one partition.
+pub async fn kafka_requeue(
+    producer: &FutureProducer,
+    tasks: Vec<(DataSources, Option<String>, String)>,
+) -> usize {
+    let topic = GLOBAL_DATA.read().await.kafka_topic.clone();
+    let mut requeued = 0;
+    for (datasource, stream_config_key, message) in tasks {
+        let stream = format!(
+            "{}_{}",
+            datasource.id,
+            stream_config_key.unwrap_or_default()
+        );
+        let headers = OwnedHeaders::new().insert(Header {
+            key: "stream",
+            value: Some(stream.as_str()),
+        });
+        let record = FutureRecord::to(topic.as_str())
+            .key(stream.as_str())
+            .payload(message.as_str())
+            .headers(headers);
+        match producer.send(record, REQUEUE_TIMEOUT).await {
+            Ok(_) => requeued += 1,
+            Err((e, _)) => error!(
+                "Could not requeue message of datasource {}. Error: {}",
+                datasource.id, e
+            ),
+        }
+    }
+    requeued
+}
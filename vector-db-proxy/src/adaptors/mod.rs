@@ -1,5 +1,8 @@
 pub mod gcp;
+pub mod kafka;
 pub mod mongo;
 pub mod pinecone;
 pub mod qdrant;
 pub mod rabbitmq;
+pub mod redis_streams;
+pub mod s3;
@@ -1,4 +1,5 @@
 use crate::data::models::FileType;
+use crate::utils::secret::SecretString;
 use bson::DateTime;
 use mongodb::bson::{doc, oid::ObjectId};
 use serde::{Deserialize, Serialize};
@@ -215,14 +216,21 @@ pub struct StreamConfig {
     pub syncMode: SyncMode,
     pub cursorField: Vec<String>,
     pub descriptionsMap: bson::Document,
+    // Renders the text embedded for each record, see `crate::embeddings::template`. Streams
+    // without one embed the datasource's `embedding_field`.
+    pub embeddingTemplate: Option<String>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct EmbeddingConfig {
     pub model: Option<Model>,
     pub embedding_key: Option<String>,
+    pub embedding_template: Option<String>,
     pub primary_key: Option<Vec<String>>,
     pub chunking_strategy: Option<UnstructuredChunkingConfig>,
+    pub sync_mode: Option<SyncMode>,
+    pub stream: Option<String>,
+    pub cursor_field: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -231,7 +239,9 @@ pub struct VectorDbs {
     pub _id: ObjectId,
     pub orgId: ObjectId,
     pub teamId: ObjectId,
-    pub apiKey: Option<String>,
+    // Encrypted with one of `VECTOR_DB_ENCRYPTION_KEYS`, or plaintext if written before those were
+    // configured
+    pub apiKey: Option<SecretString>,
     pub url: Option<String>,
     pub r#type: VectorDatabaseType,
     pub name: String,
@@ -265,3 +275,139 @@ impl Display for VectorDatabaseType {
         write!(f, "{}", str)
     }
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PointImportProgress {
+    pub datasource_id: ObjectId,
+    pub location: String,
+    // SHA-256 of the file, so that progress is only resumed for the same contents
+    pub content_hash: Option<String>,
+    pub imported_points: i64,
+    pub status: String,
+    pub last_error: Option<String>,
+    pub updated_date: DateTime,
+}
+
+/// The cursor of the latest version of a row that has been embedded, by the hash of its primary
+/// key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RowCursor {
+    pub datasource_id: ObjectId,
+    pub index: String,
+    pub cursor: Value,
+    pub updated_date: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncGeneration {
+    pub datasource_id: ObjectId,
+    pub stream: String,
+    pub generation: String,
+    pub status: String,
+    pub started_date: DateTime,
+    pub completed_date: Option<DateTime>,
+}
+
+/// Enum representing the lifecycle of a sync run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncRunStatus {
+    Syncing,
+    Embedding,
+    Ready,
+    Failed,
+}
+
+impl SyncRunStatus {
+    pub fn as_str<'a>(status: &Self) -> &'a str {
+        match status {
+            Self::Syncing => "syncing",
+            Self::Embedding => "embedding",
+            Self::Ready => "ready",
+            Self::Failed => "failed",
+        }
+    }
+
+    /// The status shown for the datasource in the webapp while the run is in this state
+    pub fn datasource_status<'a>(status: &Self) -> &'a str {
+        match status {
+            Self::Syncing => "processing",
+            Self::Embedding => "embedding",
+            Self::Ready => "ready",
+            Self::Failed => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncRunRecordCount {
+    pub received: i64,
+    pub success: i64,
+    pub failure: i64,
+    pub skipped: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncRun {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub datasource_id: ObjectId,
+    pub status: SyncRunStatus,
+    pub started_date: DateTime,
+    pub finished_date: Option<DateTime>,
+    pub record_count: SyncRunRecordCount,
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub completed_streams: Vec<String>,
+    pub last_state: Option<bson::Bson>,
+    pub last_state_date: Option<DateTime>,
+}
+
+/// A webhook event waiting in (or delivered from) the outbox
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookOutboxEvent {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub datasource_id: ObjectId,
+    pub event: String,
+    pub payload: bson::Document,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_date: DateTime,
+    pub last_error: Option<String>,
+    pub created_date: DateTime,
+    pub delivered_date: Option<DateTime>,
+}
+
+/// Per-team overrides of the default quotas. Unset limits fall back to the configured defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamQuota {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub team_id: ObjectId,
+    pub max_vectors: Option<i64>,
+    pub max_bytes: Option<i64>,
+    pub max_tokens_per_day: Option<i64>,
+}
+
+/// What a team has stored and embedded, as tracked for enforcing its quotas. Stored usage is
+/// recounted from the vector database every so often and incremented in between.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TeamUsage {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub team_id: ObjectId,
+    pub vectors: i64,
+    pub bytes: i64,
+    // The UTC day (YYYY-MM-DD) the token count applies to
+    pub tokens_day: String,
+    pub tokens: i64,
+    pub recounted_date: Option<DateTime>,
+}
@@ -1,10 +1,17 @@
-use crate::adaptors::mongo::models::{DataSources, EmbeddingConfig, Model, VectorDbs};
+use crate::adaptors::mongo::models::{
+    DataSources, EmbeddingConfig, Model, PointImportProgress, RowCursor, SyncGeneration, SyncRun,
+    SyncRunStatus, TeamQuota, TeamUsage, VectorDbs, WebhookOutboxEvent,
+};
 use anyhow::{anyhow, Result};
 use futures_util::StreamExt;
 use mongodb::bson::doc;
 use mongodb::bson::oid::ObjectId;
-use mongodb::options::FindOneOptions;
-use mongodb::{Collection, Database};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::{
+    FindOneAndUpdateOptions, FindOneOptions, IndexOptions, ReturnDocument, UpdateOptions,
+};
+use mongodb::{Collection, Database, IndexModel};
+use serde_json::Value;
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -85,6 +92,14 @@ pub async fn get_model_and_embedding_key(
         if let Some(config_key) = stream_config_key {
             if let Some(datasource_stream_config) = stream_config.get(config_key.as_str()) {
                 embedding_config.primary_key = Some(datasource_stream_config.primaryKey.clone());
+                embedding_config.sync_mode = Some(datasource_stream_config.syncMode.clone());
+                embedding_config.cursor_field =
+                    Some(datasource_stream_config.cursorField.clone()).filter(|c| !c.is_empty());
+                embedding_config.embedding_template = datasource_stream_config
+                    .embeddingTemplate
+                    .clone()
+                    .filter(|t| !t.trim().is_empty());
+                embedding_config.stream = Some(config_key);
             }
         }
     }
@@ -230,3 +245,849 @@ pub async fn get_vector_db_details(db: &Database, vector_db_id: ObjectId) -> Opt
         }
     }
 }
+
+pub async fn get_vector_dbs(db: &Database) -> Result<Vec<VectorDbs>> {
+    let vector_db_collection = db.collection::<VectorDbs>("vectordbs");
+    let mut vector_dbs = vec![];
+    match vector_db_collection.find(doc! {}, None).await {
+        Ok(mut cursor) => {
+            while let Some(vector_db) = cursor.next().await {
+                vector_dbs.push(vector_db?);
+            }
+            Ok(vector_dbs)
+        }
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to retrieve vector DBs. Error: {}", e))
+        }
+    }
+}
+
+/// Replaces the stored API key of a vector DB, unless it has been changed since `current_api_key`
+/// was read. Returns whether the key was replaced. `updatedAt` is left alone, since the key itself
+/// has not changed.
+pub async fn replace_vector_db_api_key(
+    db: &Database,
+    vector_db_id: ObjectId,
+    current_api_key: &str,
+    new_api_key: &str,
+) -> Result<bool> {
+    let vector_db_collection = db.collection::<VectorDbs>("vectordbs");
+    match vector_db_collection
+        .update_one(
+            doc! {"_id": vector_db_id, "apiKey": current_api_key},
+            doc! {"$set": {"apiKey": new_api_key}},
+            UpdateOptions::default(),
+        )
+        .await
+    {
+        Ok(result) => Ok(result.modified_count > 0),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to update vector DB API key. Error: {}", e))
+        }
+    }
+}
+
+pub async fn get_import_progress(
+    db: &Database,
+    datasource_id: &str,
+    location: &str,
+) -> Result<Option<PointImportProgress>> {
+    let imports_collection = db.collection::<PointImportProgress>("pointimports");
+    let filter = doc! {"datasourceId": ObjectId::from_str(datasource_id)?, "location": location};
+    match imports_collection.find_one(filter, None).await {
+        Ok(progress) => Ok(progress),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to retrieve import progress. Error: {}", e))
+        }
+    }
+}
+
+pub async fn set_import_progress(
+    db: &Database,
+    datasource_id: &str,
+    location: &str,
+    content_hash: &str,
+    imported_points: i64,
+    status: &str,
+    last_error: Option<String>,
+) -> Result<()> {
+    let imports_collection = db.collection::<PointImportProgress>("pointimports");
+    let filter = doc! {"datasourceId": ObjectId::from_str(datasource_id)?, "location": location};
+    let update = doc! {
+        "$set": {
+            "contentHash": content_hash,
+            "importedPoints": imported_points,
+            "status": status,
+            "lastError": last_error,
+            "updatedDate": bson::DateTime::now(),
+        }
+    };
+    let update_options = UpdateOptions::builder().upsert(true).build();
+    match imports_collection
+        .update_one(filter, update, update_options)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to update import progress. Error: {}", e))
+        }
+    }
+}
+
+pub async fn set_datasource_vector_db(
+    db: &Database,
+    datasource_id: &str,
+    vector_db_id: Option<ObjectId>,
+    collection_name: &str,
+    namespace: Option<String>,
+) -> Result<()> {
+    let datasources_collection = db.collection::<DataSources>("datasources");
+    let filter = doc! {"_id": ObjectId::from_str(datasource_id)?};
+    let update = doc! {
+        "$set": {
+            "vectorDbId": vector_db_id,
+            "byoVectorDb": vector_db_id.is_some(),
+            "collectionName": collection_name,
+            "namespace": namespace,
+        }
+    };
+    match datasources_collection
+        .update_one(filter, update, UpdateOptions::default())
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!(
+                "Failed to update datasource vector DB. Error: {}",
+                e
+            ))
+        }
+    }
+}
+
+// Returned by MongoDB when a write would break a unique index
+const DUPLICATE_KEY_ERROR: i32 = 11000;
+const SYNC_GENERATION_ATTEMPTS: usize = 3;
+const ROW_CURSOR_ATTEMPTS: usize = 5;
+const SYNC_RUN_ATTEMPTS: usize = 3;
+
+fn is_duplicate_key_error(error: &mongodb::error::Error) -> bool {
+    match error.kind.as_ref() {
+        ErrorKind::Command(e) => e.code == DUPLICATE_KEY_ERROR,
+        ErrorKind::Write(WriteFailure::WriteError(e)) => e.code == DUPLICATE_KEY_ERROR,
+        _ => false,
+    }
+}
+
+/// Creates the indexes the proxy relies on. Only one sync generation of a stream may be open at a
+/// time, so that workers starting one concurrently end up writing the same generation. Likewise a
+/// datasource has a single open sync run, and a row a single recorded cursor.
+pub async fn ensure_indexes(db: &Database) -> Result<()> {
+    let row_cursors_collection = db.collection::<RowCursor>("rowcursors");
+    let row_cursor_index = IndexModel::builder()
+        .keys(doc! {"datasourceId": 1, "index": 1})
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    if let Err(e) = row_cursors_collection
+        .create_index(row_cursor_index, None)
+        .await
+    {
+        log::error!("Error: {}", e);
+        return Err(anyhow!("Failed to create row cursors index. Error: {}", e));
+    }
+    let generations_collection = db.collection::<SyncGeneration>("syncgenerations");
+    let open_generation_index = IndexModel::builder()
+        .keys(doc! {"datasourceId": 1, "stream": 1})
+        .options(
+            IndexOptions::builder()
+                .name("open_generation_per_stream".to_string())
+                .unique(true)
+                .partial_filter_expression(doc! {"status": "open"})
+                .build(),
+        )
+        .build();
+    if let Err(e) = generations_collection
+        .create_index(open_generation_index, None)
+        .await
+    {
+        log::error!("Error: {}", e);
+        return Err(anyhow!(
+            "Failed to create sync generations index. Error: {}",
+            e
+        ));
+    }
+    let sync_runs_collection = db.collection::<SyncRun>("syncruns");
+    let open_run_index = IndexModel::builder()
+        .keys(doc! {"datasourceId": 1})
+        .options(
+            IndexOptions::builder()
+                .name("open_run_per_datasource".to_string())
+                .unique(true)
+                .partial_filter_expression(doc! {
+                    "status": {"$in": [
+                        SyncRunStatus::as_str(&SyncRunStatus::Syncing),
+                        SyncRunStatus::as_str(&SyncRunStatus::Embedding)
+                    ]}
+                })
+                .build(),
+        )
+        .build();
+    match sync_runs_collection
+        .create_index(open_run_index, None)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to create sync runs index. Error: {}", e))
+        }
+    }
+}
+
+/// Returns the generation of the sync that is currently writing to the stream, starting a new
+/// one if the previous generation has been completed
+pub async fn get_or_start_sync_generation(
+    db: &Database,
+    datasource_id: &str,
+    stream: &str,
+) -> Result<String> {
+    let generations_collection = db.collection::<SyncGeneration>("syncgenerations");
+    let filter = doc! {
+        "datasourceId": ObjectId::from_str(datasource_id)?,
+        "stream": stream,
+        "status": "open"
+    };
+    let update = doc! {
+        "$setOnInsert": {
+            "generation": uuid::Uuid::new_v4().to_string(),
+            "startedDate": bson::DateTime::now(),
+            "completedDate": null,
+        }
+    };
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+    // Upserts racing each other can both try to insert. The unique index on open generations only
+    // lets one of them in, and the others find its generation when they try again.
+    for _ in 0..SYNC_GENERATION_ATTEMPTS {
+        match generations_collection
+            .find_one_and_update(filter.clone(), update.clone(), options.clone())
+            .await
+        {
+            Ok(Some(sync_generation)) => return Ok(sync_generation.generation),
+            Ok(None) => {
+                return Err(anyhow!(
+                    "Failed to start sync generation for stream: {}",
+                    stream
+                ))
+            }
+            Err(e) if is_duplicate_key_error(&e) => continue,
+            Err(e) => {
+                log::error!("Error: {}", e);
+                return Err(anyhow!("Failed to retrieve sync generation. Error: {}", e));
+            }
+        }
+    }
+    Err(anyhow!(
+        "Failed to start sync generation for stream: {} after {} attempts",
+        stream,
+        SYNC_GENERATION_ATTEMPTS
+    ))
+}
+
+/// Records the cursor of a row as the latest one embedded, unless `is_older` tells it is older
+/// than the cursor recorded already. The stored cursor is only replaced if it has not changed
+/// since it was compared, so of two workers handling versions of the same row only one can win.
+/// Returns whether the cursor was recorded.
+pub async fn advance_row_cursor(
+    db: &Database,
+    datasource_id: &str,
+    index: &str,
+    cursor: &Value,
+    is_older: impl Fn(&Value) -> bool,
+) -> Result<bool> {
+    let row_cursors_collection = db.collection::<RowCursor>("rowcursors");
+    let filter = doc! {"datasourceId": ObjectId::from_str(datasource_id)?, "index": index};
+    for _ in 0..ROW_CURSOR_ATTEMPTS {
+        let stored = row_cursors_collection
+            .find_one(filter.clone(), None)
+            .await
+            .map_err(|e| {
+                log::error!("Error: {}", e);
+                anyhow!("Failed to retrieve row cursor. Error: {}", e)
+            })?;
+        match stored {
+            Some(stored) => {
+                if is_older(&stored.cursor) {
+                    return Ok(false);
+                }
+                let mut unchanged_filter = filter.clone();
+                unchanged_filter.insert("cursor", bson::to_bson(&stored.cursor)?);
+                let update = doc! {
+                    "$set": {
+                        "cursor": bson::to_bson(cursor)?,
+                        "updatedDate": bson::DateTime::now(),
+                    }
+                };
+                match row_cursors_collection
+                    .update_one(unchanged_filter, update, UpdateOptions::default())
+                    .await
+                {
+                    Ok(result) if result.matched_count > 0 => return Ok(true),
+                    // Another worker recorded a cursor in the meantime, compare with that one
+                    Ok(_) => continue,
+                    Err(e) => {
+                        log::error!("Error: {}", e);
+                        return Err(anyhow!("Failed to update row cursor. Error: {}", e));
+                    }
+                }
+            }
+            None => {
+                let row_cursor = RowCursor {
+                    datasource_id: ObjectId::from_str(datasource_id)?,
+                    index: index.to_string(),
+                    cursor: cursor.clone(),
+                    updated_date: bson::DateTime::now(),
+                };
+                match row_cursors_collection.insert_one(row_cursor, None).await {
+                    Ok(_) => return Ok(true),
+                    Err(e) if is_duplicate_key_error(&e) => continue,
+                    Err(e) => {
+                        log::error!("Error: {}", e);
+                        return Err(anyhow!("Failed to record row cursor. Error: {}", e));
+                    }
+                }
+            }
+        }
+    }
+    Err(anyhow!(
+        "The cursor of row {} kept changing while it was being recorded",
+        index
+    ))
+}
+
+/// Marks every open sync generation of the datasource as completed and returns them
+pub async fn complete_sync_generations(
+    db: &Database,
+    datasource_id: &str,
+) -> Result<Vec<SyncGeneration>> {
+    let generations_collection = db.collection::<SyncGeneration>("syncgenerations");
+    let filter = doc! {"datasourceId": ObjectId::from_str(datasource_id)?, "status": "open"};
+    let mut list_of_generations: Vec<SyncGeneration> = vec![];
+    let mut generations = generations_collection.find(filter, None).await?;
+    while let Some(generation) = generations.next().await {
+        list_of_generations.push(generation?)
+    }
+    // Only complete the generations we read so that a sync starting in the meantime stays open
+    let generation_ids: Vec<String> = list_of_generations
+        .iter()
+        .map(|g| g.generation.clone())
+        .collect();
+    let filter = doc! {"generation": {"$in": generation_ids}};
+    let update = doc! {"$set": {"status": "completed", "completedDate": bson::DateTime::now()}};
+    match generations_collection
+        .update_many(filter, update, UpdateOptions::default())
+        .await
+    {
+        Ok(_) => Ok(list_of_generations),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to complete sync generations. Error: {}", e))
+        }
+    }
+}
+
+// A run stays open until every record the source sent has been processed
+fn open_sync_run_filter(datasource_id: &str) -> Result<bson::Document> {
+    Ok(doc! {
+        "datasourceId": ObjectId::from_str(datasource_id)?,
+        "status": {"$in": [
+            SyncRunStatus::as_str(&SyncRunStatus::Syncing),
+            SyncRunStatus::as_str(&SyncRunStatus::Embedding)
+        ]}
+    })
+}
+
+/// Counts received records against the open sync run of the datasource, starting a new run if
+/// there is none
+pub async fn receive_sync_run_records(
+    db: &Database,
+    datasource_id: &str,
+    count: i64,
+) -> Result<SyncRun> {
+    let sync_runs_collection = db.collection::<SyncRun>("syncruns");
+    let update = doc! {
+        "$setOnInsert": {
+            "status": SyncRunStatus::as_str(&SyncRunStatus::Syncing),
+            "startedDate": bson::DateTime::now(),
+            "finishedDate": null,
+            "recordCount.success": 0_i64,
+            "recordCount.failure": 0_i64,
+            "recordCount.skipped": 0_i64,
+            "lastError": null,
+        },
+        "$inc": {"recordCount.received": count}
+    };
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+    // Only one of the first records racing to start the run gets to insert it, the unique index on
+    // open runs turns the others away and they are counted against that run on the next attempt
+    for _ in 0..SYNC_RUN_ATTEMPTS {
+        match sync_runs_collection
+            .find_one_and_update(
+                open_sync_run_filter(datasource_id)?,
+                update.clone(),
+                options.clone(),
+            )
+            .await
+        {
+            Ok(Some(sync_run)) => return Ok(sync_run),
+            Ok(None) => {
+                return Err(anyhow!(
+                    "Failed to start sync run for datasource: {}",
+                    datasource_id
+                ))
+            }
+            Err(e) if is_duplicate_key_error(&e) => continue,
+            Err(e) => {
+                log::error!("Error: {}", e);
+                return Err(anyhow!("Failed to update sync run. Error: {}", e));
+            }
+        }
+    }
+    Err(anyhow!(
+        "Failed to start sync run for datasource: {} after {} attempts",
+        datasource_id,
+        SYNC_RUN_ATTEMPTS
+    ))
+}
+
+/// Increments one of the outcome counters (`success`, `failure` or `skipped`) of the open sync run
+/// and returns the updated run
+pub async fn increment_sync_run_count(
+    db: &Database,
+    datasource_id: &str,
+    outcome: &str,
+    last_error: Option<String>,
+) -> Result<Option<SyncRun>> {
+    let sync_runs_collection = db.collection::<SyncRun>("syncruns");
+    let mut update = doc! {"$inc": {format!("recordCount.{}", outcome): 1_i64}};
+    if let Some(error) = last_error {
+        update.insert("$set", doc! {"lastError": error});
+    }
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+    match sync_runs_collection
+        .find_one_and_update(open_sync_run_filter(datasource_id)?, update, options)
+        .await
+    {
+        Ok(sync_run) => Ok(sync_run),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to update sync run. Error: {}", e))
+        }
+    }
+}
+
+pub async fn get_open_sync_run(db: &Database, datasource_id: &str) -> Result<Option<SyncRun>> {
+    let sync_runs_collection = db.collection::<SyncRun>("syncruns");
+    match sync_runs_collection
+        .find_one(open_sync_run_filter(datasource_id)?, None)
+        .await
+    {
+        Ok(sync_run) => Ok(sync_run),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to retrieve sync run. Error: {}", e))
+        }
+    }
+}
+
+/// The most recently started sync run of the datasource, whether it is still open or not
+pub async fn get_latest_sync_run(db: &Database, datasource_id: &str) -> Result<Option<SyncRun>> {
+    let sync_runs_collection = db.collection::<SyncRun>("syncruns");
+    let options = FindOneOptions::builder()
+        .sort(doc! {"startedDate": -1})
+        .build();
+    match sync_runs_collection
+        .find_one(
+            doc! {"datasourceId": ObjectId::from_str(datasource_id)?},
+            options,
+        )
+        .await
+    {
+        Ok(sync_run) => Ok(sync_run),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to retrieve sync run. Error: {}", e))
+        }
+    }
+}
+
+/// Moves a sync run from one status to another. Returns false if the run was no longer in the
+/// expected status, which means another worker already made the transition
+pub async fn transition_sync_run(
+    db: &Database,
+    sync_run_id: ObjectId,
+    from: SyncRunStatus,
+    to: SyncRunStatus,
+    last_error: Option<String>,
+) -> Result<bool> {
+    let sync_runs_collection = db.collection::<SyncRun>("syncruns");
+    let filter = doc! {"_id": sync_run_id, "status": SyncRunStatus::as_str(&from)};
+    let mut set = doc! {"status": SyncRunStatus::as_str(&to)};
+    if matches!(to, SyncRunStatus::Ready | SyncRunStatus::Failed) {
+        set.insert("finishedDate", bson::DateTime::now());
+    }
+    if let Some(error) = last_error {
+        set.insert("lastError", error);
+    }
+    match sync_runs_collection
+        .update_one(filter, doc! {"$set": set}, UpdateOptions::default())
+        .await
+    {
+        Ok(result) => Ok(result.modified_count == 1),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to update sync run status. Error: {}", e))
+        }
+    }
+}
+
+/// Records the latest state checkpoint emitted by the source on the open sync run
+pub async fn checkpoint_sync_run(
+    db: &Database,
+    datasource_id: &str,
+    state: bson::Bson,
+) -> Result<()> {
+    let sync_runs_collection = db.collection::<SyncRun>("syncruns");
+    let update = doc! {"$set": {"lastState": state, "lastStateDate": bson::DateTime::now()}};
+    match sync_runs_collection
+        .update_one(
+            open_sync_run_filter(datasource_id)?,
+            update,
+            UpdateOptions::default(),
+        )
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to checkpoint sync run. Error: {}", e))
+        }
+    }
+}
+
+/// Marks a stream of the open sync run as completed and returns the updated run
+pub async fn complete_sync_run_stream(
+    db: &Database,
+    datasource_id: &str,
+    stream: &str,
+) -> Result<Option<SyncRun>> {
+    let sync_runs_collection = db.collection::<SyncRun>("syncruns");
+    let update = doc! {"$addToSet": {"completedStreams": stream}};
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+    match sync_runs_collection
+        .find_one_and_update(open_sync_run_filter(datasource_id)?, update, options)
+        .await
+    {
+        Ok(sync_run) => Ok(sync_run),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to update sync run. Error: {}", e))
+        }
+    }
+}
+
+pub async fn insert_webhook_event(
+    db: &Database,
+    datasource_id: &str,
+    event: &str,
+    payload: bson::Document,
+) -> Result<()> {
+    let outbox_collection = db.collection::<WebhookOutboxEvent>("webhookoutbox");
+    let now = bson::DateTime::now();
+    let webhook_event = WebhookOutboxEvent {
+        id: ObjectId::new(),
+        datasource_id: ObjectId::from_str(datasource_id)?,
+        event: event.to_string(),
+        payload,
+        status: "pending".to_string(),
+        attempts: 0,
+        next_attempt_date: now,
+        last_error: None,
+        created_date: now,
+        delivered_date: None,
+    };
+    match outbox_collection.insert_one(webhook_event, None).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!(
+                "Failed to add webhook event to outbox. Error: {}",
+                e
+            ))
+        }
+    }
+}
+
+/// Claims the oldest pending webhook event that is due for delivery. The event is leased by
+/// pushing its next attempt back so that other dispatchers skip it while it is being delivered
+pub async fn claim_due_webhook_event(
+    db: &Database,
+    lease_millis: i64,
+) -> Result<Option<WebhookOutboxEvent>> {
+    let outbox_collection = db.collection::<WebhookOutboxEvent>("webhookoutbox");
+    let now = bson::DateTime::now();
+    let filter = doc! {"status": "pending", "nextAttemptDate": {"$lte": now}};
+    let update = doc! {"$set": {
+        "nextAttemptDate": bson::DateTime::from_millis(now.timestamp_millis() + lease_millis)
+    }};
+    let options = FindOneAndUpdateOptions::builder()
+        .sort(doc! {"nextAttemptDate": 1})
+        .return_document(ReturnDocument::After)
+        .build();
+    match outbox_collection
+        .find_one_and_update(filter, update, options)
+        .await
+    {
+        Ok(webhook_event) => Ok(webhook_event),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to claim webhook event. Error: {}", e))
+        }
+    }
+}
+
+pub async fn mark_webhook_event_delivered(db: &Database, event_id: ObjectId) -> Result<()> {
+    let outbox_collection = db.collection::<WebhookOutboxEvent>("webhookoutbox");
+    let update = doc! {"$set": {"status": "delivered", "deliveredDate": bson::DateTime::now()},
+    "$inc": {"attempts": 1}};
+    match outbox_collection
+        .update_one(doc! {"_id": event_id}, update, UpdateOptions::default())
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to update webhook event. Error: {}", e))
+        }
+    }
+}
+
+/// Records a failed delivery attempt. The event is retried at `next_attempt_date` unless it has
+/// run out of attempts, in which case it is marked as dead
+pub async fn reschedule_webhook_event(
+    db: &Database,
+    event_id: ObjectId,
+    next_attempt_date: Option<bson::DateTime>,
+    last_error: String,
+) -> Result<()> {
+    let outbox_collection = db.collection::<WebhookOutboxEvent>("webhookoutbox");
+    let set = match next_attempt_date {
+        Some(date) => doc! {"nextAttemptDate": date, "lastError": last_error},
+        None => doc! {"status": "dead", "lastError": last_error},
+    };
+    match outbox_collection
+        .update_one(
+            doc! {"_id": event_id},
+            doc! {"$set": set, "$inc": {"attempts": 1}},
+            UpdateOptions::default(),
+        )
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to update webhook event. Error: {}", e))
+        }
+    }
+}
+
+pub async fn get_team_quota(db: &Database, team_id: &str) -> Result<Option<TeamQuota>> {
+    let team_quotas_collection = db.collection::<TeamQuota>("teamquotas");
+    match team_quotas_collection
+        .find_one(doc! {"teamId": ObjectId::from_str(team_id)?}, None)
+        .await
+    {
+        Ok(team_quota) => Ok(team_quota),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to retrieve team quota. Error: {}", e))
+        }
+    }
+}
+
+/// Returns the usage of the team, creating it if the team has none yet and resetting the token
+/// count once the day it applies to has passed
+pub async fn get_team_usage(db: &Database, team_id: &str, day: &str) -> Result<TeamUsage> {
+    let team_usage_collection = db.collection::<TeamUsage>("teamusage");
+    let update = doc! {"$setOnInsert": {
+        "_id": ObjectId::new(),
+        "vectors": 0_i64,
+        "bytes": 0_i64,
+        "tokensDay": day,
+        "tokens": 0_i64,
+        "recountedDate": null,
+    }};
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+    let mut team_usage = match team_usage_collection
+        .find_one_and_update(
+            doc! {"teamId": ObjectId::from_str(team_id)?},
+            update,
+            options,
+        )
+        .await
+    {
+        Ok(Some(team_usage)) => team_usage,
+        Ok(None) => return Err(anyhow!("Team usage was not created for team: {}", team_id)),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            return Err(anyhow!("Failed to retrieve team usage. Error: {}", e));
+        }
+    };
+    if team_usage.tokens_day != day {
+        // Only the first worker to notice the new day resets the count
+        let filter = doc! {"_id": team_usage.id, "tokensDay": team_usage.tokens_day.as_str()};
+        let update = doc! {"$set": {"tokensDay": day, "tokens": 0_i64}};
+        if let Err(e) = team_usage_collection
+            .update_one(filter, update, UpdateOptions::default())
+            .await
+        {
+            log::error!("Error: {}", e);
+            return Err(anyhow!("Failed to reset daily token usage. Error: {}", e));
+        }
+        team_usage.tokens_day = day.to_string();
+        team_usage.tokens = 0;
+    }
+    Ok(team_usage)
+}
+
+pub async fn increment_team_usage(
+    db: &Database,
+    team_id: &str,
+    vectors: i64,
+    bytes: i64,
+    tokens: i64,
+) -> Result<()> {
+    let team_usage_collection = db.collection::<TeamUsage>("teamusage");
+    let update = doc! {"$inc": {"vectors": vectors, "bytes": bytes, "tokens": tokens}};
+    match team_usage_collection
+        .update_one(
+            doc! {"teamId": ObjectId::from_str(team_id)?},
+            update,
+            UpdateOptions::default(),
+        )
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to update team usage. Error: {}", e))
+        }
+    }
+}
+
+/// Claims the recount of the stored usage of the team, unless it was recounted or claimed since
+/// `recounted_before`, so that only one worker scans the vector database at a time
+pub async fn claim_team_usage_recount(
+    db: &Database,
+    team_id: &str,
+    recounted_before: bson::DateTime,
+) -> Result<bool> {
+    let team_usage_collection = db.collection::<TeamUsage>("teamusage");
+    let filter = doc! {
+        "teamId": ObjectId::from_str(team_id)?,
+        "$or": [
+            {"recountedDate": null},
+            {"recountedDate": {"$lt": recounted_before}},
+        ],
+    };
+    let update = doc! {"$set": {"recountedDate": bson::DateTime::now()}};
+    match team_usage_collection
+        .find_one_and_update(filter, update, FindOneAndUpdateOptions::default())
+        .await
+    {
+        Ok(claimed) => Ok(claimed.is_some()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!(
+                "Failed to claim recount of team usage. Error: {}",
+                e
+            ))
+        }
+    }
+}
+
+/// Replaces the stored usage of the team with a fresh count from the vector database
+pub async fn set_team_stored_usage(
+    db: &Database,
+    team_id: &str,
+    vectors: i64,
+    bytes: i64,
+) -> Result<()> {
+    let team_usage_collection = db.collection::<TeamUsage>("teamusage");
+    let update = doc! {"$set": {
+        "vectors": vectors,
+        "bytes": bytes,
+        "recountedDate": bson::DateTime::now(),
+    }};
+    match team_usage_collection
+        .update_one(
+            doc! {"teamId": ObjectId::from_str(team_id)?},
+            update,
+            UpdateOptions::default(),
+        )
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to update team usage. Error: {}", e))
+        }
+    }
+}
+
+/// Records why the datasource can no longer ingest data, or clears it once it can again
+pub async fn set_datasource_quota_error(
+    db: &Database,
+    datasource_id: ObjectId,
+    quota_error: Option<String>,
+) -> Result<()> {
+    let datasources_collection = db.collection::<DataSources>("datasources");
+    let update = match quota_error {
+        Some(error) => doc! {"$set": {"quotaError": error}},
+        None => doc! {"$unset": {"quotaError": ""}},
+    };
+    match datasources_collection
+        .update_one(
+            doc! {"_id": datasource_id},
+            update,
+            UpdateOptions::default(),
+        )
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!(
+                "Failed to update datasource quota error. Error: {}",
+                e
+            ))
+        }
+    }
+}
@@ -12,7 +12,7 @@ use pinecone_sdk::models::{Namespace, Vector};
 use pinecone_sdk::pinecone::PineconeClient;
 use prost_types::value::Kind;
 use prost_types::Value;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 #[async_trait]
@@ -237,6 +237,36 @@ impl VectorDatabase for PineconeClient {
         }
     }
 
+    async fn delete_points_by_id(
+        &self,
+        search_request: SearchRequest,
+        ids: Vec<String>,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError> {
+        let region = search_request.region.unwrap_or(Region::US_EAST_1);
+        let namespace: Namespace = search_request
+            .namespace
+            .clone()
+            .unwrap_or(search_request.collection.clone())
+            .into();
+        let index_name = search_request
+            .byo_vector_db
+            .filter(|k| *k == true)
+            .map_or(Region::to_str(region), |_| {
+                search_request.collection.as_str()
+            })
+            .to_string();
+        let index_model = get_index_model(&self, index_name).await?;
+        let mut index = match self.index(index_model.host.as_str()).await {
+            Ok(index) => index,
+            Err(e) => return Err(VectorDatabaseError::PineconeError(Arc::new(e))),
+        };
+        let ids: Vec<&str> = ids.iter().map(|id| id.as_str()).collect();
+        match index.delete_by_id(&ids, &namespace).await {
+            Ok(_) => Ok(VectorDatabaseStatus::Ok),
+            Err(e) => Err(VectorDatabaseError::PineconeError(Arc::new(e))),
+        }
+    }
+
     async fn bulk_insert_points(
         &self,
         search_request: SearchRequest,
@@ -373,6 +403,112 @@ impl VectorDatabase for PineconeClient {
         todo!()
     }
 
+    async fn get_points_page(
+        &self,
+        search_request: SearchRequest,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<(Vec<Point>, Option<String>), VectorDatabaseError> {
+        let region = search_request.clone().region.unwrap_or(Region::US_EAST_1);
+        let namespace: Namespace = search_request
+            .clone()
+            .namespace
+            .map_or(search_request.clone().collection, |n| n)
+            .into();
+        let index_name = search_request
+            .byo_vector_db
+            .filter(|k| *k == true)
+            .map_or(Region::to_str(region), |_| {
+                search_request.collection.as_str()
+            })
+            .to_string();
+        let index_model = get_index_model(&self, index_name).await?;
+        let mut index = match self.index(index_model.host.as_str()).await {
+            Ok(index) => index,
+            Err(e) => return Err(VectorDatabaseError::PineconeError(Arc::new(e))),
+        };
+        let list_response = match index
+            .list(&namespace, None, Some(limit), offset.as_deref())
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => return Err(VectorDatabaseError::PineconeError(Arc::new(e))),
+        };
+        let ids: Vec<&str> = list_response
+            .vectors
+            .iter()
+            .map(|item| item.id.as_str())
+            .collect();
+        let next_offset = list_response
+            .pagination
+            .clone()
+            .map(|p| p.next)
+            .filter(|next| !next.is_empty());
+        if ids.is_empty() {
+            return Ok((vec![], None));
+        }
+        match index.fetch(&ids, &namespace).await {
+            Ok(fetch_response) => {
+                let points = fetch_response
+                    .vectors
+                    .into_values()
+                    .map(|vector| {
+                        let mut point = vector
+                            .metadata
+                            .map_or(Point::new(None, vec![], None), Point::from);
+                        point.index = Some(serde_json::Value::String(vector.id));
+                        point.vector = vector.values;
+                        point
+                    })
+                    .collect();
+                Ok((points, next_offset))
+            }
+            Err(e) => Err(VectorDatabaseError::PineconeError(Arc::new(e))),
+        }
+    }
+
+    async fn get_point_payload(
+        &self,
+        search_request: SearchRequest,
+    ) -> Result<Option<HashMap<String, serde_json::Value>>, VectorDatabaseError> {
+        let region = search_request.clone().region.unwrap_or(Region::US_EAST_1);
+        let namespace: Namespace = search_request
+            .clone()
+            .namespace
+            .map_or(search_request.clone().collection, |n| n)
+            .into();
+        let index_name = search_request
+            .byo_vector_db
+            .filter(|k| *k == true)
+            .map_or(Region::to_str(region), |_| {
+                search_request.collection.as_str()
+            })
+            .to_string();
+        // Points are upserted with their ID serialised as a JSON string, see `From<Point> for Vector`
+        let id = match search_request.id.clone() {
+            Some(id) => serde_json::Value::String(id).to_string(),
+            None => {
+                return Err(VectorDatabaseError::Other(
+                    "Point ID is required".to_string(),
+                ))
+            }
+        };
+        let index_model = get_index_model(&self, index_name).await?;
+        let mut index = match self.index(index_model.host.as_str()).await {
+            Ok(index) => index,
+            Err(e) => return Err(VectorDatabaseError::PineconeError(Arc::new(e))),
+        };
+        match index.fetch(&[id.as_str()], &namespace).await {
+            Ok(fetch_response) => Ok(fetch_response
+                .vectors
+                .into_values()
+                .next()
+                .and_then(|vector| vector.metadata)
+                .and_then(|metadata| Point::from(metadata).payload)),
+            Err(e) => Err(VectorDatabaseError::PineconeError(Arc::new(e))),
+        }
+    }
+
     async fn similarity_search(
         &self,
         search_request: SearchRequest,
@@ -1,4 +1,6 @@
-use crate::adaptors::qdrant::helpers::{construct_point_struct, get_next_page, get_scroll_results};
+use crate::adaptors::qdrant::helpers::{
+    construct_point_struct, get_next_page, get_scroll_results, retrieved_point_to_point,
+};
 use crate::utils::conversions::convert_hashmap_to_qdrant_filters;
 use crate::vector_databases::error::VectorDatabaseError;
 use crate::vector_databases::models::{
@@ -19,10 +21,11 @@ use qdrant_client::qdrant::points_selector::PointsSelectorOneOf;
 use qdrant_client::qdrant::vectors_config::Config;
 use qdrant_client::qdrant::with_vectors_selector::SelectorOptions;
 use qdrant_client::qdrant::{
-    Condition, Filter, HasIdCondition, PointId, PointsSelector, ScrollPoints, VectorParams,
-    VectorParamsMap, VectorsConfig, WithVectorsSelector,
+    Condition, Filter, HasIdCondition, PointId, PointsIdsList, PointsSelector, ScrollPoints,
+    VectorParams, VectorParamsMap, VectorsConfig, WithVectorsSelector,
 };
-use serde_json::to_value;
+use serde_json::{to_value, Value};
+use std::collections::HashMap;
 use std::time::Duration;
 
 #[async_trait]
@@ -42,7 +45,6 @@ impl VectorDatabase for QdrantClient {
         search_request: SearchRequest,
     ) -> Result<CollectionsResult, VectorDatabaseError> {
         log::debug!("Qdrant URI: {:?}", &self.cfg.uri);
-        log::debug!("Qdrant API KEY: {:?}", &self.cfg.api_key);
         let collection_id = search_request.collection;
         match self.collection_exists(collection_id.clone()).await {
             Ok(collection_exists) => match collection_exists {
@@ -226,6 +228,30 @@ impl VectorDatabase for QdrantClient {
         }
     }
 
+    async fn delete_points_by_id(
+        &self,
+        search_request: SearchRequest,
+        ids: Vec<String>,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError> {
+        let ids: Vec<PointId> = ids
+            .into_iter()
+            .map(|id| match id.parse::<u64>() {
+                Ok(num) => PointId::from(num),
+                Err(_) => PointId::from(id),
+            })
+            .collect();
+        let point_selector = PointsSelector {
+            points_selector_one_of: Some(PointsSelectorOneOf::Points(PointsIdsList { ids })),
+        };
+        match self
+            .delete_points_blocking(search_request.collection, None, &point_selector, None)
+            .await
+        {
+            Ok(_) => Ok(VectorDatabaseStatus::Ok),
+            Err(e) => Err(VectorDatabaseError::AnyhowError(e)),
+        }
+    }
+
     async fn bulk_insert_points(
         &self,
         search_request: SearchRequest,
@@ -436,6 +462,70 @@ impl VectorDatabase for QdrantClient {
         }
         Ok(response)
     }
+    async fn get_points_page(
+        &self,
+        search_request: SearchRequest,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<(Vec<Point>, Option<String>), VectorDatabaseError> {
+        let scroll_points = ScrollPoints {
+            collection_name: search_request.collection,
+            offset: offset.map(|o| match o.parse::<u64>() {
+                Ok(num) => PointId::from(num),
+                Err(_) => PointId::from(o),
+            }),
+            limit: Some(limit),
+            with_payload: Some(true.into()),
+            with_vectors: Some(WithVectorsSelector {
+                selector_options: Some(SelectorOptions::Enable(true)),
+            }),
+            ..Default::default()
+        };
+        let (result, next_offset) = get_next_page(&self, &scroll_points).await?;
+        let points = result
+            .result
+            .into_iter()
+            .filter_map(retrieved_point_to_point)
+            .collect();
+        let next_offset = if next_offset == "Done" {
+            None
+        } else {
+            Some(next_offset)
+        };
+        Ok((points, next_offset))
+    }
+
+    async fn get_point_payload(
+        &self,
+        search_request: SearchRequest,
+    ) -> Result<Option<HashMap<String, Value>>, VectorDatabaseError> {
+        let point_id = match search_request.id {
+            Some(id) => PointId::from(id),
+            None => {
+                return Err(VectorDatabaseError::Other(
+                    "Point ID is required".to_string(),
+                ))
+            }
+        };
+        let response = self
+            .get_points(
+                search_request.collection,
+                None,
+                &[point_id],
+                Some(false),
+                Some(true),
+                None,
+            )
+            .await?;
+        Ok(response.result.into_iter().next().map(|point| {
+            point
+                .payload
+                .iter()
+                .filter_map(|(k, v)| to_value(v).ok().map(|v| (k.clone(), v)))
+                .collect()
+        }))
+    }
+
     async fn similarity_search(
         &self,
         search_request: SearchRequest,
@@ -2,13 +2,13 @@ use anyhow::Result;
 use qdrant_client::client::QdrantClient;
 use qdrant_client::qdrant::point_id::PointIdOptions;
 use qdrant_client::qdrant::vectors::VectorsOptions;
-use qdrant_client::qdrant::{PointId, PointStruct, ScrollPoints, ScrollResponse};
+use qdrant_client::qdrant::{PointId, PointStruct, RetrievedPoint, ScrollPoints, ScrollResponse};
 use serde_json::{json, to_string, Value};
 use std::collections::HashMap;
 use uuid::Uuid;
 
 use crate::embeddings::models::EmbeddingModels;
-use crate::vector_databases::models::{ScrollResults, VectorDatabaseStatus};
+use crate::vector_databases::models::{Point, ScrollResults, VectorDatabaseStatus};
 
 pub async fn get_next_page(
     qdrant_conn: &QdrantClient,
@@ -72,6 +72,24 @@ pub fn get_scroll_results(result: ScrollResponse) -> Result<Vec<ScrollResults>>
     Ok(response)
 }
 
+pub fn retrieved_point_to_point(point: RetrievedPoint) -> Option<Point> {
+    let id = match point.id?.point_id_options? {
+        PointIdOptions::Num(num) => num.to_string(),
+        PointIdOptions::Uuid(uuid) => uuid,
+    };
+    // Named vectors are not supported by the rest of the proxy so we skip them here too
+    let vector = match point.vectors?.vectors_options? {
+        VectorsOptions::Vector(v) => v.data,
+        VectorsOptions::Vectors(_) => return None,
+    };
+    let payload: HashMap<String, Value> = point
+        .payload
+        .iter()
+        .filter_map(|(k, v)| serde_json::to_value(v).ok().map(|v| (k.clone(), v)))
+        .collect();
+    Some(Point::new(Some(Value::String(id)), vector, Some(payload)))
+}
+
 pub async fn construct_point_struct(
     vector: &Vec<f32>,
     payload: HashMap<String, Value>,
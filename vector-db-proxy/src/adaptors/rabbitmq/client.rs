@@ -7,7 +7,6 @@ use amqprs::{
     connection::{Connection, OpenConnectionArguments},
 };
 use lazy_static::lazy_static;
-use log::log;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -1,15 +1,24 @@
 use crate::adaptors::mongo::models::DataSources;
 use crate::adaptors::rabbitmq::client::bind_queue_to_exchange;
 use crate::init::env_variables::GLOBAL_DATA;
-use crate::messages::models::{MessageQueueConnection, QueueConnectionTypes};
+use crate::messages::models::{MessageAck, MessageQueueConnection, QueueConnectionTypes};
 use crate::messages::tasks::process_message;
-use amqp_serde::types::ShortStr;
-use amqprs::channel::{BasicAckArguments, BasicConsumeArguments, Channel};
+use crate::utils::health::set_consumer_attached;
+use crate::utils::metrics::MESSAGES_CONSUMED;
+use crate::utils::shutdown::{is_shutting_down, shutdown_requested};
+use crate::utils::telemetry::{set_parent_from_headers, TRACE_CONTEXT_HEADERS};
+use amqp_serde::types::{FieldTable, FieldValue, ShortStr};
+use amqprs::channel::{
+    BasicAckArguments, BasicCancelArguments, BasicConsumeArguments, BasicPublishArguments, Channel,
+};
+use amqprs::BasicProperties;
 use crossbeam::channel::Sender;
 use log::{error, warn};
 use mongodb::Database;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tracing::{Instrument, Span};
 
 pub struct RabbitConnect {
     pub host: String,
@@ -53,19 +62,39 @@ pub async fn rabbit_consume(
     streaming_queue: &Channel,
     //vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
     mongo_client: Arc<RwLock<Database>>,
-    sender: Sender<(DataSources, Option<String>, String)>,
+    sender: Sender<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
 ) {
     let global_data = GLOBAL_DATA.read().await;
     let queue_name = global_data.rabbitmq_stream.as_str();
     let args = BasicConsumeArguments::new(queue_name, "");
-    loop {
+    while !is_shutting_down() {
         match streaming_queue.basic_consume_rx(args.clone()).await {
-            Ok((_, mut messages_rx)) => {
-                while let Some(message) = messages_rx.recv().await {
-                    let args =
-                        BasicAckArguments::new(message.deliver.unwrap().delivery_tag(), false);
+            Ok((consumer_tag, mut messages_rx)) => {
+                set_consumer_attached(true);
+                loop {
+                    // Messages delivered once shutdown has been requested are left unacknowledged
+                    let message = tokio::select! {
+                        message = messages_rx.recv() => message,
+                        _ = shutdown_requested() => None,
+                    };
+                    let message = match message {
+                        Some(message) => message,
+                        None => break,
+                    };
+                    let delivery_tag = message.deliver.unwrap().delivery_tag();
+                    let args = BasicAckArguments::new(delivery_tag, false);
                     let _ = streaming_queue.basic_ack(args).await;
-                    let headers = message.basic_properties.unwrap().headers().unwrap().clone();
+                    let basic_properties = message.basic_properties.unwrap();
+                    let message_id = basic_properties
+                        .message_id()
+                        .map_or(delivery_tag.to_string(), |id| id.to_string());
+                    let headers = basic_properties.headers().unwrap().clone();
                     match headers.get(&ShortStr::try_from("stream").unwrap()) {
                         Some(stream) => {
                             let stream_string: String = stream.to_string();
@@ -91,6 +120,25 @@ pub async fn rabbit_consume(
                                 {
                                     let sender_clone = sender.clone();
                                     let mongo_client = Arc::clone(&mongo_client);
+                                    MESSAGES_CONSUMED.with_label_values(&["rabbitmq"]).inc();
+                                    let trace_headers: HashMap<String, String> =
+                                        TRACE_CONTEXT_HEADERS
+                                            .iter()
+                                            .filter_map(|header| {
+                                                headers
+                                                    .get(&ShortStr::try_from(*header).unwrap())
+                                                    .map(|value| {
+                                                        (header.to_string(), value.to_string())
+                                                    })
+                                            })
+                                            .collect();
+                                    let span = tracing::info_span!(
+                                        "consume_message",
+                                        provider = "rabbitmq",
+                                        datasource_id,
+                                        message_id = message_id.as_str()
+                                    );
+                                    set_parent_from_headers(&span, &trace_headers);
                                     process_message(
                                         message_string,
                                         stream_type,
@@ -99,7 +147,9 @@ pub async fn rabbit_consume(
                                         //vector_database_client,
                                         mongo_client,
                                         sender_clone,
+                                        None,
                                     )
+                                    .instrument(span)
                                     .await;
                                 }
                             }
@@ -109,8 +159,17 @@ pub async fn rabbit_consume(
                         }
                     }
                 }
+                set_consumer_attached(false);
+                if is_shutting_down() {
+                    let _ = streaming_queue
+                        .basic_cancel(BasicCancelArguments::new(&consumer_tag))
+                        .await;
+                    log::info!("Stopped consuming from RabbitMQ");
+                    return;
+                }
             }
             Err(e) => {
+                set_consumer_attached(false);
                 error!(
                     "There was an error when consuming messages from rabbitMQ. Error: {}",
                     e
@@ -119,3 +178,44 @@ pub async fn rabbit_consume(
         }
     }
 }
+
+/// Publishes tasks that were taken from the queue but not processed back to the exchange, with the
+/// same stream header the consumer expects
+pub async fn rabbit_requeue(
+    streaming_queue: &Channel,
+    tasks: Vec<(DataSources, Option<String>, String)>,
+) -> usize {
+    let global_data = GLOBAL_DATA.read().await;
+    let mut requeued = 0;
+    for (datasource, stream_config_key, message) in tasks {
+        let stream = format!(
+            "{}_{}",
+            datasource.id,
+            stream_config_key.unwrap_or_default()
+        );
+        let mut headers = FieldTable::new();
+        headers.insert(
+            ShortStr::try_from("stream").unwrap(),
+            FieldValue::from(stream.as_str()),
+        );
+        let basic_properties = BasicProperties::default()
+            .with_headers(headers)
+            .with_persistence(true)
+            .finish();
+        let args = BasicPublishArguments::new(
+            &global_data.rabbitmq_exchange,
+            &global_data.rabbitmq_routing_key,
+        );
+        match streaming_queue
+            .basic_publish(basic_properties, message.into_bytes(), args)
+            .await
+        {
+            Ok(_) => requeued += 1,
+            Err(e) => error!(
+                "Could not requeue message of datasource {}. Error: {}",
+                datasource.id, e
+            ),
+        }
+    }
+    requeued
+}
@@ -0,0 +1,65 @@
+use crate::adaptors::redis_streams::models::RedisConnect;
+use anyhow::{anyhow, Result};
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncCommands, Client, ConnectionAddr, ConnectionInfo, RedisConnectionInfo};
+
+pub fn redis_client(connection_details: &RedisConnect) -> Result<Client> {
+    let connection_info = ConnectionInfo {
+        addr: ConnectionAddr::Tcp(connection_details.host.clone(), connection_details.port),
+        redis: RedisConnectionInfo {
+            password: Some(connection_details.password.clone()).filter(|p| !p.is_empty()),
+            ..Default::default()
+        },
+    };
+    Client::open(connection_info).map_err(|e| {
+        log::error!("Error: {}", e);
+        anyhow!("Could not create Redis client. Error: {}", e)
+    })
+}
+
+/// Opens a connection, retrying until Redis is reachable
+pub async fn connect_redis(client: &Client) -> MultiplexedConnection {
+    let mut connection_attempts = 0;
+    loop {
+        match client.get_multiplexed_async_connection().await {
+            Ok(connection) => return connection,
+            Err(e) => {
+                connection_attempts += 1;
+                let time_to_sleep = 2 + (connection_attempts * 2);
+                log::warn!(
+                    "Could not connect to Redis. Error: {}. Going to sleep for '{}' seconds then \
+                    will try to re-connect...",
+                    e,
+                    time_to_sleep
+                );
+                tokio::time::sleep(tokio::time::Duration::from_secs(time_to_sleep)).await;
+            }
+        }
+    }
+}
+
+/// Creates the consumer group, and the stream if it does not exist yet. New groups start from the
+/// beginning of the stream.
+pub async fn create_consumer_group(
+    connection: &mut MultiplexedConnection,
+    stream: &str,
+    group: &str,
+) -> Result<()> {
+    match connection
+        .xgroup_create_mkstream::<_, _, _, ()>(stream, group, "0")
+        .await
+    {
+        Ok(_) => Ok(()),
+        // The group was created by this or another instance before
+        Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!(
+                "Could not create consumer group {} on Redis stream {}. Error: {}",
+                group,
+                stream,
+                e
+            ))
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use crate::adaptors::mongo::models::DataSources;
+use crate::adaptors::redis_streams::client::{connect_redis, create_consumer_group, redis_client};
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::messages::models::{MessageAck, MessageQueueConnection, QueueConnectionTypes};
+use crate::messages::tasks::process_message;
+use crate::utils::health::set_consumer_attached;
+use crate::utils::metrics::MESSAGES_CONSUMED;
+use crate::utils::shutdown::{is_shutting_down, shutdown_requested};
+use crate::utils::telemetry::{set_parent_from_headers, TRACE_CONTEXT_HEADERS};
+use crossbeam::channel::Sender;
+use log::{error, warn};
+use mongodb::Database;
+use once_cell::sync::Lazy;
+use redis::aio::MultiplexedConnection;
+use redis::streams::{
+    StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamPendingCountReply,
+    StreamReadOptions, StreamReadReply,
+};
+use redis::{AsyncCommands, Client, RedisResult};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::{Arc, Mutex};
+use tokio::sync::RwLock;
+use tokio::time::{Duration, Instant};
+use tracing::{Instrument, Span};
+use uuid::Uuid;
+
+// How many entries are read or claimed at once
+const READ_COUNT: usize = 10;
+// How long a read waits for new entries before checking for stuck ones again
+const READ_BLOCK_MS: usize = 5000;
+// How often entries left pending by other consumers are looked for
+const CLAIM_INTERVAL: Duration = Duration::from_secs(30);
+const READ_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+// Name of this instance in the consumer group. Pods are named after their hostname, and the
+// suffix keeps restarted instances from being mistaken for the one they replace.
+static CONSUMER_NAME: Lazy<String> = Lazy::new(|| {
+    let hostname = env::var("HOSTNAME").unwrap_or_else(|_| "vector-db-proxy".to_string());
+    format!("{}-{}", hostname, &Uuid::new_v4().to_string()[..8])
+});
+
+// Entries handed to the work queue that have not been acknowledged yet, which this consumer must
+// not claim back from itself while they wait their turn
+static ENTRIES_IN_PROGRESS: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+pub struct RedisConnect {
+    pub host: String,
+    pub port: u16,
+    pub password: String,
+}
+
+impl Default for RedisConnect {
+    fn default() -> Self {
+        RedisConnect {
+            host: String::from("localhost"),
+            port: 6379,
+            password: String::new(),
+        }
+    }
+}
+impl MessageQueueConnection for RedisConnect {
+    async fn connect(&self) -> Option<QueueConnectionTypes> {
+        match redis_client(self) {
+            Ok(client) => {
+                let connection = connect_redis(&client).await;
+                Some(QueueConnectionTypes::Redis(client, connection))
+            }
+            Err(e) => {
+                error!("{}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Acknowledges an entry once the task made from it has been processed. Entries that are never
+/// acknowledged stay pending and are claimed by a consumer of the group after the idle time.
+pub async fn redis_ack(mut connection: MultiplexedConnection, stream: &str, group: &str, id: &str) {
+    let acknowledged: RedisResult<()> = connection.xack(stream, group, &[id]).await;
+    redis_release(id);
+    if let Err(e) = acknowledged {
+        error!(
+            "Could not acknowledge entry {} on Redis stream {}. Error: {}",
+            id, stream, e
+        );
+    }
+}
+
+/// Lets an entry that was not processed be claimed again, by this consumer as well as others
+pub fn redis_release(id: &str) {
+    if let Ok(mut entries_in_progress) = ENTRIES_IN_PROGRESS.lock() {
+        entries_in_progress.remove(id);
+    }
+}
+
+/// Hands a stream entry to `process_message`. The entry carries the message in its `data` field,
+/// next to the same `stream` and `type` fields the RabbitMQ consumer reads from message headers.
+/// Entries that can not be processed are acknowledged straight away, as claiming them again
+/// would not help.
+async fn handle_entry(
+    entry: &StreamId,
+    mongo_client: Arc<RwLock<Database>>,
+    sender: Sender<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
+    ack: MessageAck,
+) {
+    let Some(stream_string) = entry.get::<String>("stream") else {
+        warn!("There was no stream ID present in stream entry...can not proceed!");
+        ack.ack().await;
+        return;
+    };
+    let (datasource_id, stream_config_key, stream_type) = match entry.get::<String>("type") {
+        Some(stream_type) => (
+            stream_string.split('_').next().unwrap_or_default(),
+            None,
+            Some(stream_type),
+        ),
+        None => match stream_string.split_once('_') {
+            Some((datasource_id, stream_config_key)) => {
+                (datasource_id, Some(stream_config_key.to_string()), None)
+            }
+            None => {
+                warn!(
+                    "Expected stream ID `{}` to be <datasource id>_<stream>...can not proceed!",
+                    stream_string
+                );
+                ack.ack().await;
+                return;
+            }
+        },
+    };
+    let Some(message_string) = entry.get::<String>("data") else {
+        warn!("Could not get message content from Redis");
+        ack.ack().await;
+        return;
+    };
+    MESSAGES_CONSUMED.with_label_values(&["redis"]).inc();
+    let trace_headers: HashMap<String, String> = TRACE_CONTEXT_HEADERS
+        .iter()
+        .filter_map(|header| {
+            entry
+                .get::<String>(header)
+                .map(|value| (header.to_string(), value))
+        })
+        .collect();
+    let span = tracing::info_span!(
+        "consume_message",
+        provider = "redis",
+        datasource_id,
+        message_id = entry.id.as_str()
+    );
+    set_parent_from_headers(&span, &trace_headers);
+    process_message(
+        message_string,
+        stream_type,
+        datasource_id,
+        stream_config_key,
+        mongo_client,
+        sender,
+        Some(ack),
+    )
+    .instrument(span)
+    .await;
+}
+
+/// How many times the group has delivered an entry, counting the claim that returned it
+async fn times_delivered(
+    connection: &mut MultiplexedConnection,
+    stream: &str,
+    group: &str,
+    id: &str,
+) -> Option<usize> {
+    let reply: RedisResult<StreamPendingCountReply> =
+        connection.xpending_count(stream, group, id, id, 1).await;
+    match reply {
+        Ok(reply) => reply
+            .ids
+            .into_iter()
+            .find(|pending| pending.id == id)
+            .map(|pending| pending.times_delivered),
+        Err(e) => {
+            error!(
+                "Could not get the delivery count of entry {} on Redis stream {}. Error: {}",
+                id, stream, e
+            );
+            None
+        }
+    }
+}
+
+/// Moves an entry that keeps failing to `<stream>:dead-letter`, with its fields and delivery
+/// count, and acknowledges it so that it is not claimed again. It stays pending if it could not be
+/// moved.
+async fn dead_letter_entry(
+    connection: &mut MultiplexedConnection,
+    stream: &str,
+    group: &str,
+    entry: &StreamId,
+    times_delivered: usize,
+) {
+    let mut fields: Vec<(String, String)> = entry
+        .map
+        .keys()
+        .filter_map(|key| entry.get::<String>(key).map(|value| (key.clone(), value)))
+        .collect();
+    fields.push(("times_delivered".to_string(), times_delivered.to_string()));
+    let dead_letter_stream = format!("{}:dead-letter", stream);
+    let added: RedisResult<String> = connection
+        .xadd(dead_letter_stream.as_str(), "*", &fields)
+        .await;
+    match added {
+        Ok(_) => {
+            warn!(
+                "Moved entry {} to Redis stream {} after {} deliveries",
+                entry.id, dead_letter_stream, times_delivered
+            );
+            redis_ack(connection.clone(), stream, group, entry.id.as_str()).await;
+        }
+        Err(e) => error!(
+            "Could not move entry {} to Redis stream {}. Error: {}",
+            entry.id, dead_letter_stream, e
+        ),
+    }
+}
+
+/// Takes over entries that another consumer read but did not acknowledge within the idle time,
+/// because it crashed or was killed before processing them. Entries delivered more than
+/// `max_deliveries` times are moved to the dead-letter stream instead of being processed again.
+async fn claim_stuck_entries(
+    connection: &mut MultiplexedConnection,
+    stream: &str,
+    group: &str,
+    min_idle_ms: u64,
+    max_deliveries: usize,
+) -> Vec<StreamId> {
+    let options = StreamAutoClaimOptions::default().count(READ_COUNT);
+    let reply: RedisResult<StreamAutoClaimReply> = connection
+        .xautoclaim_options(
+            stream,
+            group,
+            CONSUMER_NAME.as_str(),
+            min_idle_ms,
+            "0-0",
+            options,
+        )
+        .await;
+    match reply {
+        Ok(reply) => {
+            // Entries of this consumer that are still on the work queue come back as well
+            let entries_in_progress = ENTRIES_IN_PROGRESS
+                .lock()
+                .map(|entries_in_progress| entries_in_progress.clone())
+                .unwrap_or_default();
+            let mut claimed: Vec<StreamId> = vec![];
+            for entry in reply.claimed {
+                if entries_in_progress.contains(&entry.id) {
+                    continue;
+                }
+                match times_delivered(connection, stream, group, entry.id.as_str()).await {
+                    Some(times_delivered) if times_delivered > max_deliveries => {
+                        dead_letter_entry(connection, stream, group, &entry, times_delivered).await;
+                    }
+                    _ => claimed.push(entry),
+                }
+            }
+            if !claimed.is_empty() {
+                log::info!(
+                    "Claimed {} entries left pending on Redis stream {}",
+                    claimed.len(),
+                    stream
+                );
+            }
+            claimed
+        }
+        Err(e) => {
+            error!("Could not claim pending entries from Redis. Error: {}", e);
+            vec![]
+        }
+    }
+}
+
+pub async fn redis_consume(
+    client: &Client,
+    connection: &MultiplexedConnection,
+    mongo_client: Arc<RwLock<Database>>,
+    sender: Sender<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
+) {
+    let (stream, group, claim_idle_ms, max_deliveries) = {
+        let global_data = GLOBAL_DATA.read().await;
+        (
+            global_data.redis_stream.clone(),
+            global_data.redis_consumer_group.clone(),
+            global_data.redis_claim_idle_secs * 1000,
+            global_data.redis_max_deliveries as usize,
+        )
+    };
+    let mut connection = connection.clone();
+    if let Err(e) = create_consumer_group(&mut connection, &stream, &group).await {
+        error!("{}", e);
+        return;
+    }
+    // Reads block, so they get a connection of their own rather than holding up acknowledgements
+    let mut read_connection = connect_redis(client).await;
+    let read_options = StreamReadOptions::default()
+        .group(&group, CONSUMER_NAME.as_str())
+        .count(READ_COUNT)
+        .block(READ_BLOCK_MS);
+    let stream_keys = [stream.as_str()];
+    let mut last_claim: Option<Instant> = None;
+    set_consumer_attached(true);
+    while !is_shutting_down() {
+        let mut entries: Vec<StreamId> = vec![];
+        if last_claim.is_none_or(|claimed_at| claimed_at.elapsed() >= CLAIM_INTERVAL) {
+            last_claim = Some(Instant::now());
+            entries = claim_stuck_entries(
+                &mut connection,
+                &stream,
+                &group,
+                claim_idle_ms,
+                max_deliveries,
+            )
+            .await;
+        }
+        if entries.is_empty() {
+            let read = read_connection.xread_options::<_, _, Option<StreamReadReply>>(
+                &stream_keys,
+                &[">"],
+                &read_options,
+            );
+            let reply = tokio::select! {
+                reply = read => reply,
+                _ = shutdown_requested() => break,
+            };
+            match reply {
+                Ok(reply) => {
+                    set_consumer_attached(true);
+                    entries = reply
+                        .map(|reply| reply.keys.into_iter().flat_map(|key| key.ids).collect())
+                        .unwrap_or_default();
+                }
+                Err(e) => {
+                    set_consumer_attached(false);
+                    error!(
+                        "There was an error when consuming messages from Redis. Error: {}",
+                        e
+                    );
+                    tokio::time::sleep(READ_ERROR_BACKOFF).await;
+                    read_connection = connect_redis(client).await;
+                    connection = connect_redis(client).await;
+                    continue;
+                }
+            }
+        }
+        for entry in entries {
+            // Entries read once shutdown has been requested are left pending, so that another
+            // consumer claims them
+            if is_shutting_down() {
+                break;
+            }
+            if let Ok(mut entries_in_progress) = ENTRIES_IN_PROGRESS.lock() {
+                entries_in_progress.insert(entry.id.clone());
+            }
+            let ack = MessageAck::Redis {
+                connection: connection.clone(),
+                stream: stream.clone(),
+                group: group.clone(),
+                id: entry.id.clone(),
+            };
+            handle_entry(&entry, Arc::clone(&mongo_client), sender.clone(), ack).await;
+        }
+    }
+    set_consumer_attached(false);
+    log::info!("Stopped consuming from Redis");
+}
+
+/// Adds tasks that were not processed to the stream, with the same fields the consumer expects.
+/// Only tasks that did not come off the stream, like ingested records, get here.
+pub async fn redis_requeue(
+    connection: &MultiplexedConnection,
+    tasks: Vec<(DataSources, Option<String>, String)>,
+) -> usize {
+    let stream = GLOBAL_DATA.read().await.redis_stream.clone();
+    let mut connection = connection.clone();
+    let mut requeued = 0;
+    for (datasource, stream_config_key, message) in tasks {
+        let stream_id = format!(
+            "{}_{}",
+            datasource.id,
+            stream_config_key.unwrap_or_default()
+        );
+        let added: RedisResult<String> = connection
+            .xadd(
+                &stream,
+                "*",
+                &[("stream", stream_id.as_str()), ("data", message.as_str())],
+            )
+            .await;
+        match added {
+            Ok(_) => requeued += 1,
+            Err(e) => error!(
+                "Could not requeue message of datasource {}. Error: {}",
+                datasource.id, e
+            ),
+        }
+    }
+    requeued
+}
@@ -0,0 +1,74 @@
+use crate::data::models::FileContent;
+use crate::init::env_variables::GLOBAL_DATA;
+use anyhow::{anyhow, Result};
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_s3::config::{Builder, Credentials};
+use aws_sdk_s3::error::DisplayErrorContext;
+use aws_sdk_s3::Client;
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::OnceCell;
+
+static S3_CLIENT: OnceCell<Client> = OnceCell::const_new();
+
+/// Builds the client from the configured endpoint, region and keys, so that it works with AWS as
+/// well as MinIO and other S3 compatible stores
+async fn s3_client() -> &'static Client {
+    S3_CLIENT
+        .get_or_init(|| async {
+            let global_data = GLOBAL_DATA.read().await.clone();
+            let mut loader = aws_config::defaults(BehaviorVersion::latest())
+                .region(Region::new(global_data.s3_region));
+            if !global_data.s3_access_key_id.is_empty() {
+                loader = loader.credentials_provider(Credentials::new(
+                    global_data.s3_access_key_id,
+                    global_data.s3_secret_access_key,
+                    None,
+                    None,
+                    "vector-db-proxy",
+                ));
+            }
+            let sdk_config = loader.load().await;
+            let mut config =
+                Builder::from(&sdk_config).force_path_style(global_data.s3_force_path_style);
+            if !global_data.s3_endpoint.is_empty() {
+                config = config.endpoint_url(global_data.s3_endpoint);
+            }
+            Client::from_conf(config.build())
+        })
+        .await
+}
+
+/// Downloads an object into a temporary file, a part at a time, so that large objects are not
+/// held in memory
+pub async fn get_object_from_s3(bucket: &str, key: &str) -> Result<FileContent> {
+    let mut object = s3_client()
+        .await
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .send()
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "An error occurred while fetching s3://{}/{}. Error: {}",
+                bucket,
+                key,
+                DisplayErrorContext(e)
+            )
+        })?;
+    let spooled = NamedTempFile::new()?;
+    let mut file = tokio::fs::File::from_std(spooled.as_file().try_clone()?);
+    while let Some(part) = object.body.try_next().await.map_err(|e| {
+        anyhow!(
+            "An error occurred while reading s3://{}/{}. Error: {}",
+            bucket,
+            key,
+            e
+        )
+    })? {
+        file.write_all(&part).await?;
+    }
+    file.flush().await?;
+    Ok(FileContent::Spooled(spooled))
+}
@@ -0,0 +1,109 @@
+use crate::adaptors::mongo::models::UnstructuredChunkingConfig;
+use crate::data::models::FileContent;
+use crate::data::unstructuredio::models::{Metadata, UnstructuredIOResponse};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::path::Path;
+use uuid::Uuid;
+
+// What Unstructured uses when no chunking config is given
+const DEFAULT_MAX_CHARACTERS: usize = 500;
+
+/// Writes a value as `path: value` lines, e.g. `author.name: Ada` or `tags[0]: science`
+fn flatten_json(path: &str, value: &Value, lines: &mut Vec<String>) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                let field_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                flatten_json(&field_path, field, lines);
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                flatten_json(&format!("{}[{}]", path, index), item, lines);
+            }
+        }
+        Value::Null => {}
+        Value::String(text) if path.is_empty() => lines.push(text.clone()),
+        Value::String(text) => lines.push(format!("{}: {}", path, text)),
+        other if path.is_empty() => lines.push(other.to_string()),
+        other => lines.push(format!("{}: {}", path, other)),
+    }
+}
+
+/// Packs lines into chunks of at most `max_characters`, splitting lines that are longer on their
+/// own
+fn split_lines(lines: &[String], max_characters: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut chunk = String::new();
+    for line in lines {
+        let characters: Vec<char> = line.chars().collect();
+        for piece in characters.chunks(max_characters) {
+            let piece: String = piece.iter().collect();
+            if !chunk.is_empty()
+                && chunk.chars().count() + 1 + piece.chars().count() > max_characters
+            {
+                chunks.push(std::mem::take(&mut chunk));
+            }
+            if !chunk.is_empty() {
+                chunk.push('\n');
+            }
+            chunk.push_str(&piece);
+        }
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Chunks JSON files without Unstructured, which only takes JSON in its own element format. Every
+/// item of a top level array is chunked on its own, and any other document as a whole.
+pub fn parse_json(
+    file: &FileContent,
+    file_name: Option<String>,
+    chunking_strategy: Option<UnstructuredChunkingConfig>,
+) -> Result<Vec<UnstructuredIOResponse>> {
+    let document: Value = serde_json::from_reader(file.reader()?)
+        .map_err(|e| anyhow!("Could not parse JSON file. Error: {}", e))?;
+    let items = match document {
+        Value::Array(items) => items,
+        document => vec![document],
+    };
+    let max_characters = chunking_strategy
+        .map(|config| config.max_characters)
+        .filter(|max_characters| *max_characters > 0)
+        .unwrap_or(DEFAULT_MAX_CHARACTERS);
+    // Unstructured only keeps the base name as well
+    let filename = file_name
+        .map(|name| {
+            Path::new(name.trim_matches('"'))
+                .file_name()
+                .map(|base_name| base_name.to_string_lossy().to_string())
+                .unwrap_or(name)
+        })
+        .unwrap_or_default();
+    let mut documents = vec![];
+    for item in items {
+        let mut lines = vec![];
+        flatten_json("", &item, &mut lines);
+        for text in split_lines(&lines, max_characters) {
+            documents.push(UnstructuredIOResponse {
+                field_type: "CompositeElement".to_string(),
+                element_id: Uuid::new_v4().simple().to_string(),
+                text,
+                metadata: Metadata {
+                    filetype: "application/json".to_string(),
+                    languages: vec![],
+                    page_number: None,
+                    filename: filename.clone(),
+                },
+            });
+        }
+    }
+    Ok(documents)
+}
@@ -1,4 +1,10 @@
-mod helpers;
+pub(crate) mod helpers;
+pub mod local_parsers;
 pub mod models;
+pub mod point_import;
 pub mod processing_incoming_messages;
+pub mod progress;
+pub mod quotas;
+pub mod sync_modes;
+pub mod sync_runs;
 pub mod unstructuredio;
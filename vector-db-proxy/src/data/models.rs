@@ -1,13 +1,29 @@
+use bytes::Bytes;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::io::{Cursor, Read};
+use tempfile::NamedTempFile;
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum FileType {
     PDF,
     TXT,
     CSV,
     DOCX,
+    ODT,
     MARKDOWN,
+    HTML,
+    JSON,
+    EPUB,
+    PPTX,
+    XLSX,
+    EML,
+    PNG,
+    JPEG,
+    TIFF,
+    BMP,
+    HEIC,
     UNKNOWN,
 }
 
@@ -17,11 +33,30 @@ impl FileType {
             Self::MARKDOWN => "markdown",
             Self::CSV => "csv",
             Self::DOCX => "docx",
+            Self::ODT => "odt",
             Self::PDF => "pdf",
             Self::TXT => "txt",
+            Self::HTML => "html",
+            Self::JSON => "json",
+            Self::EPUB => "epub",
+            Self::PPTX => "pptx",
+            Self::XLSX => "xlsx",
+            Self::EML => "eml",
+            Self::PNG => "png",
+            Self::JPEG => "jpg",
+            Self::TIFF => "tiff",
+            Self::BMP => "bmp",
+            Self::HEIC => "heic",
             _ => "unknown",
         }
     }
+
+    pub fn is_image(&self) -> bool {
+        matches!(
+            self,
+            Self::PNG | Self::JPEG | Self::TIFF | Self::BMP | Self::HEIC
+        )
+    }
 }
 
 impl From<String> for FileType {
@@ -30,9 +65,126 @@ impl From<String> for FileType {
             "pdf" => Self::PDF,
             "txt" => Self::TXT,
             "csv" => Self::CSV,
-            "markdown" => Self::MARKDOWN,
-            "docx" | "pptx" | "xlsx" | "odt" | "ods" | "odp" => Self::DOCX,
+            "markdown" | "md" => Self::MARKDOWN,
+            "docx" => Self::DOCX,
+            // OpenDocument spreadsheets and presentations are not chunked by Unstructured
+            "odt" => Self::ODT,
+            "html" | "htm" => Self::HTML,
+            "json" => Self::JSON,
+            "epub" => Self::EPUB,
+            "pptx" => Self::PPTX,
+            "xlsx" => Self::XLSX,
+            "eml" => Self::EML,
+            "png" => Self::PNG,
+            "jpg" | "jpeg" => Self::JPEG,
+            "tif" | "tiff" => Self::TIFF,
+            "bmp" => Self::BMP,
+            "heic" => Self::HEIC,
             _ => Self::UNKNOWN,
         }
     }
 }
+
+/// The contents of a file read from a source. Large objects are spooled to a temporary file, which
+/// is removed once the contents are dropped, rather than held in memory.
+pub enum FileContent {
+    Memory(Bytes),
+    Spooled(NamedTempFile),
+}
+
+impl FileContent {
+    /// A reader from the start of the contents. Each call starts over, so that a request can be
+    /// retried with the same contents.
+    pub fn reader(&self) -> std::io::Result<Box<dyn Read + Send>> {
+        match self {
+            Self::Memory(bytes) => Ok(Box::new(Cursor::new(bytes.clone()))),
+            Self::Spooled(file) => Ok(Box::new(file.reopen()?)),
+        }
+    }
+}
+
+impl From<Vec<u8>> for FileContent {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Memory(Bytes::from(value))
+    }
+}
+
+/// A file read from a source, to be chunked and embedded
+pub struct SourceFile {
+    pub file_type: FileType,
+    pub content: FileContent,
+    // Where the file was read from, or its path inside the archive it was extracted from
+    pub path: String,
+    pub in_archive: bool,
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    JSONL,
+    PARQUET,
+}
+
+impl ImportFormat {
+    pub fn from_path(path: &str) -> Option<Self> {
+        let path = path.trim_matches('"').to_lowercase();
+        if path.ends_with(".jsonl") || path.ends_with(".ndjson") {
+            Some(Self::JSONL)
+        } else if path.ends_with(".parquet") {
+            Some(Self::PARQUET)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "lowercase")]
+pub enum ImportSource {
+    // Relative to the configured `import_directory`
+    Local { path: String },
+    Google { bucket: String, object: String },
+}
+
+impl ImportSource {
+    pub fn location(&self) -> String {
+        match self {
+            Self::Local { path } => path.clone(),
+            Self::Google { bucket, object } => format!("gs://{}/{}", bucket, object),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImportRequest {
+    #[serde(flatten)]
+    pub source: ImportSource,
+    pub format: Option<ImportFormat>,
+    pub batch_size: Option<usize>,
+    // Start from the first point again instead of picking up where a previous run stopped
+    pub restart: Option<bool>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ImportSummary {
+    pub location: String,
+    pub total_points: usize,
+    pub skipped_points: usize,
+    pub imported_points: usize,
+}
+
+/// A file to be chunked and embedded, in the shape of the file messages on the message queue:
+/// `source` is what those carry in their `type` header, and the other fields locate the file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IngestFile {
+    pub source: String,
+    #[serde(flatten)]
+    pub location: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum IngestRequest {
+    Records { records: Vec<Map<String, Value>> },
+    File { file: IngestFile },
+}
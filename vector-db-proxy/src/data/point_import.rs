@@ -0,0 +1,307 @@
+use crate::adaptors::gcp::gcs::get_object_from_gcs;
+use crate::adaptors::mongo::models::DataSources;
+use crate::adaptors::mongo::queries::{get_import_progress, get_model, set_import_progress};
+use crate::data::helpers::hash_string_to_uuid;
+use crate::data::models::{ImportFormat, ImportRequest, ImportSource, ImportSummary};
+use crate::data::quotas::{check_quota, record_usage, UsageRequest};
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::utils::file_operations::is_team_object;
+use crate::vector_databases::models::{
+    Point, Region, SearchRequest, SearchType, VectorDatabaseStatus,
+};
+use crate::vector_databases::vector_database::VectorDatabase;
+use anyhow::{anyhow, Result};
+use mongodb::Database;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::fs;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+const DEFAULT_IMPORT_BATCH_SIZE: usize = 100;
+
+// A single record as it appears in an import file. `index` is accepted as an alias of `id` so
+// that files written from `Point` structs can be imported as is
+#[derive(Deserialize, Debug)]
+struct ImportedPoint {
+    #[serde(alias = "index")]
+    id: Option<Value>,
+    vector: Vec<f32>,
+    payload: Option<Value>,
+}
+
+impl ImportedPoint {
+    // Qdrant only accepts UUIDs as point IDs, so any other ID is hashed into a stable UUID, as
+    // migration does, and the original is kept in the payload
+    fn into_point(self, salt: &str) -> Result<Point> {
+        let mut payload = match self.payload {
+            Some(Value::Object(map)) => Some(HashMap::from_iter(map)),
+            // Parquet writers commonly store the payload as a JSON encoded string column
+            Some(Value::String(s)) => match serde_json::from_str::<Value>(s.as_str())? {
+                Value::Object(map) => Some(HashMap::from_iter(map)),
+                _ => return Err(anyhow!("Payload string is not a JSON object")),
+            },
+            Some(Value::Null) | None => None,
+            Some(_) => return Err(anyhow!("Payload must be a JSON object")),
+        };
+        let index = self.id.map(|id| {
+            let id = match id {
+                Value::String(s) => s.trim_matches('"').to_string(),
+                other => other.to_string(),
+            };
+            if Uuid::parse_str(id.as_str()).is_ok() {
+                return Value::String(id);
+            }
+            let hashed_id = hash_string_to_uuid(salt, id.as_str());
+            payload
+                .get_or_insert_with(Default::default)
+                .insert("ac_source_id".to_string(), Value::String(id));
+            Value::String(hashed_id)
+        });
+        Ok(Point::new(index, self.vector, payload))
+    }
+}
+
+/// Reads a local file from inside the import directory, or an object stored under the team's or
+/// the datasource's prefix
+async fn read_import_source(
+    source: &ImportSource,
+    team_id: &str,
+    datasource_id: &str,
+) -> Result<Vec<u8>> {
+    match source {
+        ImportSource::Local { path } => {
+            let import_directory = GLOBAL_DATA.read().await.import_directory.clone();
+            if import_directory.is_empty() {
+                return Err(anyhow!(
+                    "Importing local files is disabled. Set import_directory to allow it"
+                ));
+            }
+            // Resolved before comparing, so that `..` and symlinks can not lead out of it
+            let import_directory = fs::canonicalize(import_directory.as_str()).await?;
+            let file_path = fs::canonicalize(import_directory.join(path.as_str()))
+                .await
+                .map_err(|e| anyhow!("Could not find import file '{}'. Error: {}", path, e))?;
+            if !file_path.starts_with(&import_directory) {
+                return Err(anyhow!(
+                    "Import file '{}' is outside of the import directory",
+                    path
+                ));
+            }
+            fs::read(file_path).await.map_err(|e| {
+                anyhow!(
+                    "An error occurred while reading import file from DISK. Error: {}",
+                    e
+                )
+            })
+        }
+        ImportSource::Google { bucket, object } => {
+            if !is_team_object(object.as_str(), team_id, datasource_id) {
+                return Err(anyhow!(
+                    "Import object '{}' is not stored under the team or datasource prefix",
+                    object
+                ));
+            }
+            get_object_from_gcs(bucket.as_str(), object.as_str()).await
+        }
+    }
+}
+
+fn parse_jsonl(content: &[u8], salt: &str) -> Result<Vec<Point>> {
+    let content = std::str::from_utf8(content)?;
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(line_number, line)| {
+            serde_json::from_str::<ImportedPoint>(line)
+                .map_err(|e| anyhow!("Invalid point on line {}. Error: {}", line_number + 1, e))
+                .and_then(|point| point.into_point(salt))
+        })
+        .collect()
+}
+
+fn parse_parquet(content: Vec<u8>, salt: &str) -> Result<Vec<Point>> {
+    let reader = SerializedFileReader::new(bytes::Bytes::from(content))?;
+    let mut points = vec![];
+    for (row_number, row) in reader.get_row_iter(None)?.enumerate() {
+        let row_json = row?.to_json_value();
+        let point = serde_json::from_value::<ImportedPoint>(row_json)
+            .map_err(|e| anyhow!("Invalid point in row {}. Error: {}", row_number, e))
+            .and_then(|point| point.into_point(salt))?;
+        points.push(point);
+    }
+    Ok(points)
+}
+
+fn validate_dimensions(points: &[Point], dimensions: usize) -> Result<()> {
+    if let Some((position, point)) = points
+        .iter()
+        .enumerate()
+        .find(|(_, p)| p.vector.len() != dimensions)
+    {
+        return Err(anyhow!(
+            "Point {} has {} dimensions but the collection expects {}",
+            position,
+            point.vector.len(),
+            dimensions
+        ));
+    }
+    Ok(())
+}
+
+/// Reads points from a JSONL or Parquet file and bulk inserts them into the datasource's
+/// collection. Progress is recorded after every batch so that a failed import can be re-run and
+/// will continue from the last successfully inserted batch, as long as the file is unchanged.
+pub async fn import_points(
+    mongo: &Database,
+    datasource: DataSources,
+    vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
+    import_request: ImportRequest,
+) -> Result<ImportSummary> {
+    let datasource_id = datasource.id.to_string();
+    let location = import_request.source.location();
+    let format = import_request
+        .format
+        .or(ImportFormat::from_path(location.as_str()))
+        .ok_or(anyhow!(
+            "Could not determine import format for '{}'. Expected one of `jsonl` or `parquet`",
+            location
+        ))?;
+
+    let content = read_import_source(
+        &import_request.source,
+        datasource.team_id.to_string().as_str(),
+        datasource_id.as_str(),
+    )
+    .await?;
+    let content_hash = hex::encode(Sha256::digest(&content));
+    let hashing_salt = GLOBAL_DATA.read().await.hashing_salt.clone();
+    let points = match format {
+        ImportFormat::JSONL => parse_jsonl(&content, hashing_salt.as_str())?,
+        ImportFormat::PARQUET => parse_parquet(content, hashing_salt.as_str())?,
+    };
+
+    // The collection is created using the embedding length of the datasource's model, so we
+    // validate every point against it before anything is written
+    let model = get_model(mongo, datasource_id.as_str())
+        .await?
+        .ok_or(anyhow!(
+            "There was no embedding model associated with datasource: {}",
+            datasource_id
+        ))?;
+    let dimensions = model.embeddingLength as usize;
+    validate_dimensions(&points, dimensions)?;
+
+    let mut skipped_points = 0;
+    if !import_request.restart.unwrap_or(false) {
+        if let Some(progress) =
+            get_import_progress(mongo, datasource_id.as_str(), location.as_str()).await?
+        {
+            if progress.content_hash.as_deref() != Some(content_hash.as_str()) {
+                log::info!(
+                    "'{}' changed since it was last imported, importing it from the start",
+                    location
+                );
+            } else if progress.status != "completed" {
+                skipped_points = (progress.imported_points as usize).min(points.len());
+                log::info!(
+                    "Resuming import of '{}' after {} points",
+                    location,
+                    skipped_points
+                );
+            }
+        }
+    }
+
+    // Surfaced as a `QuotaExceeded` error so that the route can answer with the quota status
+    let team_id = datasource.team_id.to_string();
+    let usage = UsageRequest::storage(points.len() - skipped_points, dimensions);
+    if let Some(quota_exceeded) = check_quota(mongo, team_id.as_str(), &usage).await {
+        return Err(quota_exceeded.into());
+    }
+
+    let mut search_request = SearchRequest::new(
+        SearchType::Collection,
+        datasource
+            .collection_name
+            .clone()
+            .unwrap_or(datasource_id.clone()),
+    );
+    search_request.byo_vector_db = datasource.byo_vector_db;
+    search_request.namespace = datasource.namespace.clone();
+    search_request.region = datasource.region.clone().map(|r| Region::from_str(&r));
+
+    let batch_size = import_request
+        .batch_size
+        .filter(|s| *s > 0)
+        .unwrap_or(DEFAULT_IMPORT_BATCH_SIZE);
+    let mut imported_points = skipped_points;
+    let vector_database_client = vector_database_client.read().await;
+    for batch in points[skipped_points..].chunks(batch_size) {
+        let error = match vector_database_client
+            .bulk_insert_points(search_request.clone(), batch.to_vec())
+            .await
+        {
+            Ok(VectorDatabaseStatus::Ok) => None,
+            Ok(status) => Some(format!("Batch insert returned status: {:?}", status)),
+            Err(e) => Some(e.to_string()),
+        };
+        if let Some(error) = error {
+            set_import_progress(
+                mongo,
+                datasource_id.as_str(),
+                location.as_str(),
+                content_hash.as_str(),
+                imported_points as i64,
+                "failed",
+                Some(error.clone()),
+            )
+            .await?;
+            return Err(anyhow!(
+                "Import of '{}' failed after {} points. Error: {}",
+                location,
+                imported_points,
+                error
+            ));
+        }
+        imported_points += batch.len();
+        record_usage(
+            mongo,
+            team_id.as_str(),
+            &UsageRequest::storage(batch.len(), dimensions),
+        )
+        .await;
+        set_import_progress(
+            mongo,
+            datasource_id.as_str(),
+            location.as_str(),
+            content_hash.as_str(),
+            imported_points as i64,
+            "in_progress",
+            None,
+        )
+        .await?;
+    }
+    set_import_progress(
+        mongo,
+        datasource_id.as_str(),
+        location.as_str(),
+        content_hash.as_str(),
+        imported_points as i64,
+        "completed",
+        None,
+    )
+    .await?;
+
+    Ok(ImportSummary {
+        location,
+        total_points: points.len(),
+        skipped_points,
+        imported_points: imported_points - skipped_points,
+    })
+}
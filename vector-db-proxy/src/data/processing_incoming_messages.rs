@@ -1,12 +1,25 @@
-use crate::adaptors::mongo::models::{DataSources, Model, UnstructuredChunkingConfig};
+use crate::adaptors::mongo::models::{DataSources, Model, SyncMode, UnstructuredChunkingConfig};
 use crate::adaptors::mongo::queries::{
-    get_model_and_embedding_key, increment_by_one, set_datasource_state,
+    get_datasource, get_model_and_embedding_key, increment_by_one,
+};
+use crate::data::models::FileContent;
+use crate::data::quotas::{check_datasource_quota, record_usage, UsageRequest};
+use crate::data::sync_modes::{
+    apply_sync_mode, delete_source_deleted_row, is_source_deleted, is_stale_update,
+};
+use crate::data::sync_runs::{
+    checkpoint, complete_stream, fail_sync_run, finish_record, start_records, RecordOutcome,
 };
-use crate::data::helpers::hash_string_to_uuid;
 use crate::data::unstructuredio::apis::chunk_text;
-use crate::embeddings::helpers::clean_text;
+use crate::embeddings::template::{EmbeddingSource, EmbeddingTemplate};
 use crate::embeddings::utils::{embed_bulk_insert_unstructured_response, embed_text};
 use crate::init::env_variables::GLOBAL_DATA;
+use crate::messages::airbyte::{
+    AirbyteLogLevel, AirbyteMessage, AirbyteRecordMessage, AirbyteStreamStatus, AirbyteTraceType,
+};
+use crate::messages::models::MessageAck;
+use crate::messages::task_handoff::{finish_task, receive_task};
+use crate::utils::metrics::WORK_QUEUE_DEPTH;
 use crate::vector_databases::helpers::check_byo_vector_database;
 use crate::vector_databases::models::{
     Point, Region, SearchRequest, SearchType, VectorDatabaseStatus,
@@ -17,14 +30,14 @@ use crossbeam::channel::Receiver;
 use mongodb::Database;
 use serde_json::{to_vec, Value};
 use std::collections::HashMap;
-use std::io::Cursor;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tracing::{Instrument, Span};
 
 pub async fn embed_text_construct_point(
     mongo_conn: Arc<RwLock<Database>>,
     data: &HashMap<String, Value>,
-    embedding_field_name: &String,
+    embedding_source: &EmbeddingSource,
     datasource: Option<DataSources>,
     embedding_model: Model,
     chunking_strategy: Option<UnstructuredChunkingConfig>,
@@ -32,13 +45,12 @@ pub async fn embed_text_construct_point(
 ) -> anyhow::Result<Option<Point>, anyhow::Error> {
     if !data.is_empty() {
         if let Some(ds) = datasource {
-            // Convert embedding_field_name to lowercase
             let mut payload: HashMap<String, Value> = data.clone();
-            if let Some(value) = payload.remove(embedding_field_name) {
-                payload.insert(
-                    "page_content".to_string(),
-                    Value::String(clean_text(value.to_string())),
-                );
+            if let Some(text) = embedding_source.text(&payload) {
+                if let EmbeddingSource::Field(embedding_field_name) = embedding_source {
+                    payload.remove(embedding_field_name);
+                }
+                payload.insert("page_content".to_string(), Value::String(text.clone()));
 
                 if let Some(chunking_config) = chunking_strategy.clone() {
                     let global_data = GLOBAL_DATA.read().await.clone();
@@ -46,8 +58,7 @@ pub async fn embed_text_construct_point(
                     let unstructuredio_api_key =
                         Some(global_data.unstructuredio_api_key).filter(|s| !s.is_empty());
                     //    write value to buffer
-                    let buffer =
-                        Cursor::new(to_vec(&Value::String(clean_text(value.to_string())))?);
+                    let buffer = FileContent::from(to_vec(&Value::String(text.clone()))?);
                     let handle = tokio::task::spawn_blocking(move || {
                         let response = chunk_text(
                             unstructuredio_url,
@@ -81,8 +92,7 @@ pub async fn embed_text_construct_point(
                     }
                 }
                 // Embedding data
-                let embedding_vec =
-                    embed_text(vec![&clean_text(value.to_string())], &embedding_model).await?;
+                let embedding_vec = embed_text(vec![&text], &embedding_model).await?;
                 // Construct a Point to insert into the vector DB
                 if !embedding_vec.is_empty() {
                     if let Some(vector) = embedding_vec.into_iter().next() {
@@ -101,15 +111,16 @@ pub async fn embed_text_construct_point(
     Err(anyhow!("Row is empty"))
 }
 
+#[tracing::instrument(skip_all, fields(datasource_id = %datasource.id))]
 async fn handle_embedding(
     mongo_connection: Arc<RwLock<Database>>,
     //mut vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
     metadata: HashMap<String, Value>,
-    embedding_field_name: String,
+    embedding_source: EmbeddingSource,
     datasource: DataSources,
     embedding_model: Model,
     chunking_strategy: Option<UnstructuredChunkingConfig>,
-) {
+) -> RecordOutcome {
     let mongo_connection_clone = Arc::clone(&mongo_connection);
     let metadata = metadata.clone();
     let field_path = "recordCount.failure";
@@ -131,10 +142,10 @@ async fn handle_embedding(
     search_request.namespace = datasource.namespace.clone();
     search_request.region = datasource.region.clone().map(|r| Region::from_str(&r));
     log::debug!("Search request going to vector API: {:?}", search_request);
-    match embed_text_construct_point(
+    let outcome = match embed_text_construct_point(
         mongo_connection.clone(),
         &metadata,
-        &embedding_field_name,
+        &embedding_source,
         Some(datasource.clone()),
         embedding_model,
         chunking_strategy,
@@ -152,12 +163,16 @@ async fn handle_embedding(
                     .await
                 {
                     Ok(result) => match result {
-                        VectorDatabaseStatus::Ok => (),
-                        _ => {
+                        VectorDatabaseStatus::Ok => RecordOutcome::Success,
+                        status => {
                             log::warn!("An error occurred while inserting into vector database");
                             increment_by_one(&mongo, &datasource.id.to_string(), field_path)
                                 .await
                                 .unwrap();
+                            RecordOutcome::Failure(format!(
+                                "Insert into vector database returned status: {:?}",
+                                status
+                            ))
                         }
                     },
                     Err(e) => {
@@ -168,10 +183,12 @@ async fn handle_embedding(
                         increment_by_one(&mongo, &datasource.id.to_string(), field_path)
                             .await
                             .unwrap();
+                        RecordOutcome::Failure(e.to_string())
                     }
                 }
             }
-            None => (),
+            // Chunked rows are embedded and inserted in bulk as part of constructing the point
+            None => RecordOutcome::Success,
         },
         Err(e) => {
             increment_by_one(&mongo, &datasource.id.to_string(), field_path)
@@ -181,100 +198,303 @@ async fn handle_embedding(
                 "An error occurred while upserting  point structs to vector database: {}",
                 e
             );
+            RecordOutcome::Failure(e.to_string())
+        }
+    };
+    drop(vector_database_client);
+    outcome
+}
+
+#[tracing::instrument(skip_all, fields(datasource_id = %datasource.id))]
+async fn process_record(
+    mongo_connection: Arc<RwLock<Database>>,
+    datasource: DataSources,
+    stream_config_key: Option<String>,
+    record: AirbyteRecordMessage,
+    hashing_salt: &str,
+) -> RecordOutcome {
+    let mongo = mongo_connection.read().await;
+    // Tasks carry the datasource as it was when they were queued, which no longer points at the
+    // right vector database once a migration has moved it
+    let datasource = match get_datasource(&mongo, datasource.id.to_string().as_str()).await {
+        Ok(Some(current)) => current,
+        _ => datasource,
+    };
+    let stream_config_key = stream_config_key.or(record.stream);
+    match get_model_and_embedding_key(&mongo, datasource.clone(), stream_config_key).await {
+        Ok(embedding_config) => {
+            if let Some(embedding_model) = embedding_config.model.clone() {
+                let mut metadata = HashMap::from_iter(record.data);
+                if let Err(e) = apply_sync_mode(
+                    &mongo,
+                    datasource.id.to_string().as_str(),
+                    &embedding_config,
+                    &mut metadata,
+                    hashing_salt,
+                )
+                .await
+                {
+                    // Embedded anyway, the row would miss the primary key hash or generation
+                    // tag that its sync mode relies on
+                    log::error!(
+                        "An error occurred while applying the stream's sync mode. Error: {}",
+                        e
+                    );
+                    return RecordOutcome::Failure(e.to_string());
+                }
+
+                if is_source_deleted(&metadata) {
+                    // Append modes write every version of a row as a point of its own, without
+                    // the primary key hash to find them by, so the delete is not applied
+                    if matches!(
+                        embedding_config.sync_mode,
+                        Some(SyncMode::FullRefreshAppend) | Some(SyncMode::IncrementalAppend)
+                    ) {
+                        log::info!(
+                            "Ignoring row deleted at source for datasource {} since its stream \
+                            is synced in an append mode",
+                            datasource.id
+                        );
+                        return RecordOutcome::Skipped;
+                    }
+                    // Rows deleted at the source are removed rather than embedded
+                    return match delete_source_deleted_row(&mongo, datasource, &metadata).await {
+                        Ok(()) => RecordOutcome::Success,
+                        Err(e) => {
+                            log::error!(
+                                "An error occurred while removing a row deleted at source. \
+                                Error: {}",
+                                e
+                            );
+                            RecordOutcome::Failure(e.to_string())
+                        }
+                    };
+                }
+                if is_stale_update(&mongo, datasource.clone(), &metadata)
+                    .await
+                    .unwrap_or_else(|e| {
+                        log::warn!("Could not check the cursor of the row. Error: {}", e);
+                        false
+                    })
+                {
+                    log::debug!(
+                        "Skipping out of order update for datasource {}",
+                        datasource.id
+                    );
+                    return RecordOutcome::Skipped;
+                }
+                // The stream's template takes precedence over the datasource's single field
+                let embedding_source = match embedding_config.embedding_template.as_deref() {
+                    Some(template) => match EmbeddingTemplate::for_stream(
+                        datasource.id.to_string().as_str(),
+                        embedding_config.stream.as_deref().unwrap_or_default(),
+                        template,
+                    ) {
+                        Ok(template) => Some(EmbeddingSource::Template(template)),
+                        Err(e) => {
+                            log::error!("Error: {}", e);
+                            return RecordOutcome::Failure(e.to_string());
+                        }
+                    },
+                    None => embedding_config
+                        .embedding_key
+                        .clone()
+                        .map(EmbeddingSource::Field),
+                };
+                if let Some(embedding_source) = embedding_source {
+                    let text = embedding_source.text(&metadata).unwrap_or_default();
+                    let usage = UsageRequest::embedding(
+                        &[text.as_str()],
+                        embedding_model.embeddingLength as usize,
+                    );
+                    if let Some(quota_exceeded) =
+                        check_datasource_quota(&mongo, &datasource, &usage).await
+                    {
+                        log::warn!(
+                            "Not embedding record of datasource {}. Error: {}",
+                            datasource.id,
+                            quota_exceeded
+                        );
+                        return RecordOutcome::Failure(quota_exceeded.to_string());
+                    }
+                    let team_id = datasource.team_id.to_string();
+                    let mongo_connection_clone = Arc::clone(&mongo_connection);
+                    let embed_text_worker = tokio::spawn(async move {
+                        handle_embedding(
+                            mongo_connection_clone,
+                            metadata,
+                            embedding_source,
+                            datasource.clone(),
+                            embedding_model,
+                            embedding_config.chunking_strategy,
+                        )
+                        .await
+                    });
+                    tokio::select! {
+                        result = embed_text_worker => {
+                            log::info!("Finished embedding task");
+                            let outcome =
+                                result.unwrap_or_else(|e| RecordOutcome::Failure(e.to_string()));
+                            if let RecordOutcome::Success = outcome {
+                                record_usage(&mongo, team_id.as_str(), &usage).await;
+                            }
+                            return outcome;
+                        }
+                    }
+                }
+            }
+            RecordOutcome::Skipped
+        }
+        Err(e) => {
+            log::error!("An error occurred: {}", e);
+            RecordOutcome::Failure(e.to_string())
         }
     }
-    drop(vector_database_client)
 }
 
+// Everything but records drives the sync run rather than the vector store
+async fn handle_control_message(
+    mongo: &Database,
+    datasource: DataSources,
+    message: AirbyteMessage,
+) -> anyhow::Result<()> {
+    match message {
+        AirbyteMessage::Record { .. } => Ok(()),
+        AirbyteMessage::State { state } => checkpoint(mongo, datasource, state.checkpoint()).await,
+        AirbyteMessage::Trace { trace } => match trace.trace_type {
+            AirbyteTraceType::Error => {
+                let error = trace
+                    .error
+                    .map_or("Unknown source error".to_string(), |e| e.message);
+                log::error!(
+                    "Source reported an error for datasource {}. Error: {}",
+                    datasource.id,
+                    error
+                );
+                fail_sync_run(mongo, datasource, error).await
+            }
+            AirbyteTraceType::StreamStatus => match trace.stream_status {
+                Some(stream_status) if stream_status.status == AirbyteStreamStatus::Complete => {
+                    complete_stream(
+                        mongo,
+                        datasource,
+                        stream_status.stream_descriptor.name.as_str(),
+                    )
+                    .await
+                }
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        },
+        AirbyteMessage::Log { log } => {
+            match log.level {
+                AirbyteLogLevel::Fatal | AirbyteLogLevel::Error => {
+                    log::error!("[{}] {}", datasource.id, log.message)
+                }
+                AirbyteLogLevel::Warn => log::warn!("[{}] {}", datasource.id, log.message),
+                AirbyteLogLevel::Info | AirbyteLogLevel::Unknown => {
+                    log::info!("[{}] {}", datasource.id, log.message)
+                }
+                AirbyteLogLevel::Debug | AirbyteLogLevel::Trace => {
+                    log::debug!("[{}] {}", datasource.id, log.message)
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Processes tasks from the work queue until every sender is gone and the queue is empty, or until
+/// shutdown has taken the unfinished tasks to requeue them
 pub async fn process_incoming_messages(
-    receiver: Receiver<(DataSources, Option<String>, String)>,
+    worker: usize,
+    receiver: Receiver<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
     //vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
     mongo_conn: Arc<RwLock<Database>>,
 ) {
     let mongo_connection = Arc::clone(&mongo_conn);
     let receiver_clone = receiver.clone();
     let global_data = GLOBAL_DATA.read().await;
-    while let Ok(msg) = receiver_clone.recv() {
-        let (datasource, stream_config_key, message) = msg;
-        let datasource_clone = datasource.clone();
-        match serde_json::from_str(message.as_str()) {
-            Ok::<Value, _>(message_data) => {
-                let mongo = mongo_connection.read().await;
-                match get_model_and_embedding_key(&mongo, datasource.clone(), stream_config_key)
-                    .await
-                {
-                    Ok(embedding_config) => {
-                        if let Some(embedding_model) = embedding_config.model {
-                            // extract metadata from message if message is coming from pubsub
-                            if let Value::Object(mut data_obj) = message_data {
-                                // This is to account for airbyte sending the data in the _airbyte_data object when the destination is PubSub
-                                if let Some(is_pubsub) = data_obj.get("_airbyte_data") {
-                                    if let Some(pubsub_is_obj) = is_pubsub.as_object() {
-                                        data_obj = pubsub_is_obj.to_owned();
-                                    }
-                                }
-
-                                let mut metadata = HashMap::from_iter(data_obj);
-                                // If we find a primary key associated with the datasource, use
-                                // as vector index so that we do not create duplicates
-                                if let Some(list_of_primary_keys) = embedding_config.primary_key {
-                                    let list_of_primary_key_values: Vec<Value> =
-                                        list_of_primary_keys
-                                            .iter()
-                                            .map(|k| metadata.get(k).cloned().unwrap())
-                                            .collect();
-                                    if let Ok(json_string) =
-                                        serde_json::to_string(&list_of_primary_key_values)
-                                    {
-                                        let json_string_hash = hash_string_to_uuid(
-                                            global_data.hashing_salt.as_str(),
-                                            json_string.as_str(),
-                                        );
-                                        metadata.insert(
-                                            String::from("index"),
-                                            Value::String(json_string_hash),
-                                        );
-                                    }
-                                };
-
-                                if let Some(embedding_field_name) = embedding_config.embedding_key {
-                                    let mongo_connection_clone = Arc::clone(&mongo_connection);
-                                    let embed_text_worker = tokio::spawn(async move {
-                                        let _ = handle_embedding(
-                                            mongo_connection_clone,
-                                            metadata,
-                                            embedding_field_name,
-                                            datasource.clone(),
-                                            embedding_model,
-                                            embedding_config.chunking_strategy,
-                                        )
-                                        .await;
-                                    });
-                                    tokio::select! {
-                                        _ = embed_text_worker => {
-                                            set_datasource_state(
-                                                &mongo,
-                                                datasource_clone,
-                                                "ready"
-                                            ).await.unwrap();
-                                            log::info!("Finished embedding task")
-                                        }
-                                    }
-                                }
-                            }
+    while let Some(msg) = receive_task(worker, &receiver_clone) {
+        WORK_QUEUE_DEPTH.set(receiver_clone.len() as i64);
+        let (datasource, stream_config_key, message, parent_span, ack) = msg;
+        let span = tracing::info_span!(
+            parent: &parent_span,
+            "process_incoming_message",
+            datasource_id = %datasource.id
+        );
+        // Whether the message is done with, so that it is not delivered again
+        let processed = async {
+            match AirbyteMessage::parse(message.as_str()) {
+                Ok(AirbyteMessage::Record { record }) => {
+                    // Ingested records were counted when they were queued
+                    if !record.ingested {
+                        if let Err(e) =
+                            start_records(&*mongo_connection.read().await, datasource.clone(), 1)
+                                .await
+                        {
+                            log::error!(
+                                "An error occurred while updating the sync run. Error: {}",
+                                e
+                            );
                         }
                     }
-                    Err(e) => {
-                        log::error!("An error occurred: {}", e);
+                    let outcome = process_record(
+                        Arc::clone(&mongo_connection),
+                        datasource.clone(),
+                        stream_config_key,
+                        record,
+                        global_data.hashing_salt.as_str(),
+                    )
+                    .await;
+                    if let Err(e) =
+                        finish_record(&*mongo_connection.read().await, datasource, outcome).await
+                    {
+                        log::error!(
+                            "An error occurred while updating the sync run. Error: {}",
+                            e
+                        );
                     }
+                    // A failed record is counted as such by the sync run, so it is done with as
+                    // well. Delivering it again would count it a second time.
+                    true
+                }
+                Ok(control_message) => {
+                    let mongo = mongo_connection.read().await;
+                    if let Err(e) =
+                        handle_control_message(&mongo, datasource, control_message).await
+                    {
+                        log::error!(
+                            "An error occurred while handling Airbyte message. Error: {}",
+                            e
+                        );
+                        return false;
+                    }
+                    true
+                }
+                Err(e) => {
+                    // Delivering it again would not parse it either
+                    log::error!(
+                        "An error occurred while attempting to parse Airbyte message: {}",
+                        e
+                    );
+                    true
                 }
             }
-            Err(e) => {
-                log::error!(
-                    "An error occurred while attempting to convert message to JSON: {}",
-                    e
-                );
-            }
         }
+        .instrument(span)
+        .await;
+        match ack {
+            Some(ack) if processed => ack.ack().await,
+            Some(ack) => ack.release(),
+            None => {}
+        }
+        finish_task(worker);
     }
 }
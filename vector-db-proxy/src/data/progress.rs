@@ -0,0 +1,79 @@
+use crate::adaptors::mongo::models::{SyncRun, SyncRunRecordCount, SyncRunStatus};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+// Slow subscribers that fall further behind than this skip the missed events
+const PROGRESS_CHANNEL_CAPACITY: usize = 1024;
+
+static PROGRESS_EVENTS: Lazy<broadcast::Sender<ProgressEvent>> =
+    Lazy::new(|| broadcast::channel(PROGRESS_CHANNEL_CAPACITY).0);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressEventType {
+    Received,
+    Embedded,
+    Failed,
+    Skipped,
+    Status,
+}
+
+impl ProgressEventType {
+    pub fn as_str<'a>(event_type: &Self) -> &'a str {
+        match event_type {
+            Self::Received => "received",
+            Self::Embedded => "embedded",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+            Self::Status => "status",
+        }
+    }
+}
+
+/// A snapshot of the ingestion progress of a datasource, published every time a record is
+/// received or processed and whenever the status of its sync run changes
+#[derive(Serialize, Clone, Debug)]
+pub struct ProgressEvent {
+    #[serde(skip)]
+    pub datasource_id: String,
+    pub event: ProgressEventType,
+    pub status: SyncRunStatus,
+    pub record_count: SyncRunRecordCount,
+    pub records_per_second: Option<f64>,
+    pub eta_seconds: Option<f64>,
+    pub error: Option<String>,
+}
+
+impl ProgressEvent {
+    pub fn new(datasource_id: String, event: ProgressEventType, sync_run: &SyncRun) -> Self {
+        let count = sync_run.record_count.clone();
+        let processed = (count.success + count.failure + count.skipped) as f64;
+        let elapsed_seconds = (bson::DateTime::now().timestamp_millis()
+            - sync_run.started_date.timestamp_millis()) as f64
+            / 1000.0;
+        let records_per_second =
+            Some(processed / elapsed_seconds).filter(|r| r.is_finite() && *r > 0.0);
+        let eta_seconds =
+            records_per_second.map(|rate| (count.received as f64 - processed).max(0.0) / rate);
+        ProgressEvent {
+            datasource_id,
+            event,
+            status: sync_run.status,
+            record_count: count,
+            records_per_second,
+            eta_seconds,
+            error: sync_run.last_error.clone(),
+        }
+    }
+}
+
+/// Publishes a progress event to every subscriber in this process. Events are dropped when no
+/// one is listening.
+pub fn publish(event: ProgressEvent) {
+    let _ = PROGRESS_EVENTS.send(event);
+}
+
+pub fn subscribe() -> broadcast::Receiver<ProgressEvent> {
+    PROGRESS_EVENTS.subscribe()
+}
@@ -0,0 +1,300 @@
+use crate::adaptors::mongo::models::{DataSources, TeamUsage};
+use crate::adaptors::mongo::queries::{
+    claim_team_usage_recount, get_model, get_team_datasources, get_team_quota, get_team_usage,
+    increment_team_usage, set_datasource_quota_error, set_team_stored_usage,
+};
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::routes::models::CollectionStorageSizeResponse;
+use crate::vector_databases::helpers::check_byo_vector_database;
+use crate::vector_databases::models::{SearchRequest, SearchType};
+use crate::vector_databases::utils::calculate_vector_storage_size;
+use crate::vector_databases::vector_database::default_vector_db_client;
+use actix_web::http::StatusCode;
+use anyhow::Result;
+use chrono::Utc;
+use mongodb::Database;
+use std::fmt;
+
+// Stored usage is recounted from the vector database once it is older than this
+const USAGE_RECOUNT_INTERVAL_SECS: i64 = 300;
+// Rough number of characters per token, used to estimate the tokens of a text before embedding
+const CHARACTERS_PER_TOKEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaKind {
+    Vectors,
+    Bytes,
+    TokensPerDay,
+}
+
+impl QuotaKind {
+    pub fn as_str<'a>(kind: &Self) -> &'a str {
+        match kind {
+            Self::Vectors => "vectors",
+            Self::Bytes => "bytes",
+            Self::TokensPerDay => "embedding tokens per day",
+        }
+    }
+}
+
+/// A request that would take the team over one of its quotas
+#[derive(Debug, Clone)]
+pub struct QuotaExceeded {
+    pub kind: QuotaKind,
+    pub used: i64,
+    pub requested: i64,
+    pub limit: i64,
+}
+
+impl QuotaExceeded {
+    /// The daily token quota frees up again tomorrow, whereas storage has to be freed by the team
+    pub fn status_code(&self) -> StatusCode {
+        match self.kind {
+            QuotaKind::TokensPerDay => StatusCode::TOO_MANY_REQUESTS,
+            QuotaKind::Vectors | QuotaKind::Bytes => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl QuotaExceeded {
+    /// Which quota was exceeded, without the usage that changes with every request, so that the
+    /// error marked on a datasource stays the same while it is over the same quota
+    pub fn summary(&self) -> String {
+        format!(
+            "Team quota of {} {} exceeded",
+            self.limit,
+            QuotaKind::as_str(&self.kind)
+        )
+    }
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({} used, {} requested)",
+            self.summary(),
+            self.used,
+            self.requested
+        )
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+/// The usage a request is about to add for a team
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsageRequest {
+    pub vectors: i64,
+    pub bytes: i64,
+    pub tokens: i64,
+}
+
+impl UsageRequest {
+    /// Usage of storing `vectors` vectors of the given dimensions
+    pub fn storage(vectors: usize, dimensions: usize) -> Self {
+        UsageRequest {
+            vectors: vectors as i64,
+            bytes: calculate_vector_storage_size(vectors, dimensions).ceil() as i64,
+            tokens: 0,
+        }
+    }
+
+    /// Usage of embedding the texts and storing one vector of the given dimensions for each
+    pub fn embedding(texts: &[&str], dimensions: usize) -> Self {
+        UsageRequest {
+            tokens: texts.iter().map(|text| estimate_tokens(text)).sum(),
+            ..Self::storage(texts.len(), dimensions)
+        }
+    }
+}
+
+pub fn estimate_tokens(text: &str) -> i64 {
+    text.chars().count().div_ceil(CHARACTERS_PER_TOKEN) as i64
+}
+
+fn today() -> String {
+    Utc::now().format("%Y-%m-%d").to_string()
+}
+
+/// Adds up the storage used by every datasource of the team
+pub async fn team_storage_size(
+    mongo: &Database,
+    team_id: &str,
+) -> Result<CollectionStorageSizeResponse> {
+    let mut collection_size_response = CollectionStorageSizeResponse {
+        list_of_datasources: vec![],
+        total_size: 0.0,
+        total_points: 0,
+    };
+    let list_of_team_datasources = get_team_datasources(mongo, team_id).await?;
+    for datasource in list_of_team_datasources {
+        let vector_database_client = check_byo_vector_database(datasource.clone(), mongo)
+            .await
+            .unwrap_or(default_vector_db_client().await);
+        let vector_database_client = vector_database_client.read().await;
+        let model_result = get_model(mongo, datasource.id.to_string().as_str()).await;
+        match model_result {
+            Ok(Some(embedding_model)) => {
+                let mut search_request =
+                    SearchRequest::new(SearchType::Collection, datasource.id.to_string());
+                search_request.byo_vector_db = Some(true);
+                search_request.collection = datasource
+                    .collection_name
+                    .map_or(datasource.id.to_string(), |d| d);
+                search_request.namespace = datasource.namespace;
+                if let Ok(Some(collection_storage_info)) = vector_database_client
+                    .get_storage_size(search_request, embedding_model.embeddingLength as usize)
+                    .await
+                {
+                    collection_size_response.total_points +=
+                        collection_storage_info.points_count.unwrap_or(0);
+                    collection_size_response.total_size +=
+                        collection_storage_info.size.unwrap_or(0.0);
+                    collection_size_response
+                        .list_of_datasources
+                        .push(collection_storage_info);
+                }
+            }
+            Ok(None) => {
+                continue;
+            }
+            Err(e) => {
+                log::error!(
+                    "Error retrieving model for datasource {}: {:?}",
+                    datasource.id,
+                    e
+                );
+                continue;
+            }
+        }
+    }
+    Ok(collection_size_response)
+}
+
+async fn current_usage(mongo: &Database, team_id: &str) -> Result<TeamUsage> {
+    let mut team_usage = get_team_usage(mongo, team_id, today().as_str()).await?;
+    let recounted_before = bson::DateTime::from_millis(
+        bson::DateTime::now().timestamp_millis() - USAGE_RECOUNT_INTERVAL_SECS * 1000,
+    );
+    let recount_due = team_usage
+        .recounted_date
+        .is_none_or(|recounted| recounted < recounted_before);
+    // Workers that lose the claim go on with the stored usage until the recount is written
+    if recount_due && claim_team_usage_recount(mongo, team_id, recounted_before).await? {
+        let storage = team_storage_size(mongo, team_id).await?;
+        team_usage.vectors = storage.total_points as i64;
+        team_usage.bytes = storage.total_size.ceil() as i64;
+        set_team_stored_usage(mongo, team_id, team_usage.vectors, team_usage.bytes).await?;
+    }
+    Ok(team_usage)
+}
+
+/// Checks whether the request fits within the quotas of the team. Quotas are not enforced when
+/// the usage of the team can not be determined, so that an outage of the usage tracking does not
+/// stop ingestion.
+pub async fn check_quota(
+    mongo: &Database,
+    team_id: &str,
+    request: &UsageRequest,
+) -> Option<QuotaExceeded> {
+    let (default_vectors, default_bytes, default_tokens) = {
+        let global_data = GLOBAL_DATA.read().await;
+        (
+            global_data.quota_max_vectors,
+            global_data.quota_max_bytes,
+            global_data.quota_max_tokens_per_day,
+        )
+    };
+    let team_quota = match get_team_quota(mongo, team_id).await {
+        Ok(team_quota) => team_quota,
+        Err(e) => {
+            log::error!("Could not retrieve quota of team {}. Error: {}", team_id, e);
+            return None;
+        }
+    };
+    let limits = [
+        (
+            QuotaKind::Vectors,
+            team_quota.as_ref().and_then(|q| q.max_vectors),
+            default_vectors,
+            request.vectors,
+        ),
+        (
+            QuotaKind::Bytes,
+            team_quota.as_ref().and_then(|q| q.max_bytes),
+            default_bytes,
+            request.bytes,
+        ),
+        (
+            QuotaKind::TokensPerDay,
+            team_quota.as_ref().and_then(|q| q.max_tokens_per_day),
+            default_tokens,
+            request.tokens,
+        ),
+    ];
+    if limits
+        .iter()
+        .all(|(_, limit, default, _)| limit.unwrap_or(*default) <= 0)
+    {
+        return None;
+    }
+    let team_usage = match current_usage(mongo, team_id).await {
+        Ok(team_usage) => team_usage,
+        Err(e) => {
+            log::error!("Could not retrieve usage of team {}. Error: {}", team_id, e);
+            return None;
+        }
+    };
+    limits
+        .into_iter()
+        .find_map(|(kind, limit, default, requested)| {
+            let limit = limit.unwrap_or(default);
+            let used = match kind {
+                QuotaKind::Vectors => team_usage.vectors,
+                QuotaKind::Bytes => team_usage.bytes,
+                QuotaKind::TokensPerDay => team_usage.tokens,
+            };
+            (limit > 0 && requested > 0 && used + requested > limit).then_some(QuotaExceeded {
+                kind,
+                used,
+                requested,
+                limit,
+            })
+        })
+}
+
+/// Checks the quotas of the team owning the datasource. The datasource is marked with the quota
+/// error while it is over quota, and the mark is cleared once it fits again. Like `check_quota`
+/// this fails open: while the quota or usage of the team can not be read, records keep being
+/// embedded and the error is logged.
+pub async fn check_datasource_quota(
+    mongo: &Database,
+    datasource: &DataSources,
+    request: &UsageRequest,
+) -> Option<QuotaExceeded> {
+    let exceeded = check_quota(mongo, datasource.team_id.to_string().as_str(), request).await;
+    let marked_error = datasource.extra_fields.get_str("quotaError").ok();
+    let update = match &exceeded {
+        Some(quota_exceeded) if marked_error != Some(quota_exceeded.summary().as_str()) => {
+            Some(Some(quota_exceeded.summary()))
+        }
+        None if marked_error.is_some() => Some(None),
+        _ => None,
+    };
+    if let Some(quota_error) = update {
+        if let Err(e) = set_datasource_quota_error(mongo, datasource.id, quota_error).await {
+            log::error!("{}", e);
+        }
+    }
+    exceeded
+}
+
+/// Adds usage to the team once it has been stored and embedded
+pub async fn record_usage(mongo: &Database, team_id: &str, usage: &UsageRequest) {
+    if let Err(e) =
+        increment_team_usage(mongo, team_id, usage.vectors, usage.bytes, usage.tokens).await
+    {
+        log::error!("Could not record usage of team {}. Error: {}", team_id, e);
+    }
+}
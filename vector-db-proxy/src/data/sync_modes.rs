@@ -0,0 +1,278 @@
+use crate::adaptors::mongo::models::{DataSources, EmbeddingConfig, SyncMode};
+use crate::adaptors::mongo::queries::{
+    advance_row_cursor, complete_sync_generations, get_or_start_sync_generation,
+};
+use crate::data::helpers::hash_string_to_uuid;
+use crate::vector_databases::helpers::check_byo_vector_database;
+use crate::vector_databases::models::{
+    FilterConditions, Region, SearchRequest, SearchType, VectorDatabaseStatus,
+};
+use crate::vector_databases::vector_database::default_vector_db_client;
+use anyhow::{anyhow, Result};
+use chrono::DateTime;
+use mongodb::Database;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+pub const STREAM_FIELD: &str = "ac_stream";
+pub const SYNC_GENERATION_FIELD: &str = "ac_sync_generation";
+pub const CURSOR_FIELD: &str = "ac_cursor";
+// Set by Airbyte CDC sources on rows that have been deleted at the source
+pub const CDC_DELETED_AT_FIELD: &str = "_ab_cdc_deleted_at";
+
+/// Prepares a row's metadata according to the sync mode of the stream it came from.
+///
+/// * Append modes never dedupe so the row is always inserted as a new point.
+/// * Deduped history upserts the row using the hash of its primary key as the point ID.
+/// * Full refresh overwrite upserts by primary key as well and tags the row with the generation
+///   of the running sync so that rows missing from it can be removed once it completes.
+pub async fn apply_sync_mode(
+    mongo: &Database,
+    datasource_id: &str,
+    embedding_config: &EmbeddingConfig,
+    metadata: &mut HashMap<String, Value>,
+    hashing_salt: &str,
+) -> Result<()> {
+    if let Some(stream) = &embedding_config.stream {
+        metadata.insert(STREAM_FIELD.to_string(), Value::String(stream.clone()));
+    }
+    match embedding_config.sync_mode {
+        Some(SyncMode::FullRefreshAppend) | Some(SyncMode::IncrementalAppend) => {
+            metadata.remove("index");
+        }
+        _ => {
+            // If we find a primary key associated with the datasource, use
+            // as vector index so that we do not create duplicates
+            if let Some(list_of_primary_keys) = &embedding_config.primary_key {
+                let list_of_primary_key_values: Vec<Value> = list_of_primary_keys
+                    .iter()
+                    .map(|k| metadata.get(k).cloned().unwrap_or(Value::Null))
+                    .collect();
+                let json_string = serde_json::to_string(&list_of_primary_key_values)?;
+                let json_string_hash = hash_string_to_uuid(hashing_salt, json_string.as_str());
+                metadata.insert(String::from("index"), Value::String(json_string_hash));
+            }
+        }
+    }
+    // Keep the cursor of the row so that older versions arriving later can be discarded
+    if let Some(cursor) = embedding_config
+        .cursor_field
+        .as_ref()
+        .and_then(|cursor_field| extract_cursor(metadata, cursor_field))
+    {
+        metadata.insert(CURSOR_FIELD.to_string(), cursor);
+    }
+    if let (Some(SyncMode::FullRefreshOverwrite), Some(stream)) =
+        (&embedding_config.sync_mode, &embedding_config.stream)
+    {
+        let generation =
+            get_or_start_sync_generation(mongo, datasource_id, stream.as_str()).await?;
+        metadata.insert(SYNC_GENERATION_FIELD.to_string(), Value::String(generation));
+    }
+    Ok(())
+}
+
+/// Completes the running full refresh overwrite syncs of a datasource by deleting every point of
+/// the synced streams that was not written by the latest generation
+pub async fn complete_overwrite_syncs(mongo: &Database, datasource: DataSources) -> Result<()> {
+    let datasource_id = datasource.id.to_string();
+    let completed_generations = complete_sync_generations(mongo, datasource_id.as_str()).await?;
+    if completed_generations.is_empty() {
+        return Ok(());
+    }
+    let vector_database_client = check_byo_vector_database(datasource.clone(), mongo)
+        .await
+        .unwrap_or(default_vector_db_client().await);
+    let vector_database_client = vector_database_client.read().await;
+    // A stream only has one open generation, but points of any generation closed here are kept
+    // so that one stream is never cleaned up against another generation of itself
+    let mut generations_by_stream: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for sync_generation in completed_generations {
+        generations_by_stream
+            .entry(sync_generation.stream)
+            .or_default()
+            .push(sync_generation.generation);
+    }
+    for (stream, generations) in generations_by_stream {
+        let mut search_request = SearchRequest::new(
+            SearchType::Point,
+            datasource
+                .collection_name
+                .clone()
+                .unwrap_or(datasource_id.clone()),
+        );
+        search_request.byo_vector_db = datasource.byo_vector_db;
+        search_request.namespace = datasource.namespace.clone();
+        search_request.region = datasource.region.clone().map(|r| Region::from_str(&r));
+        search_request.filters = Some(FilterConditions {
+            must: Some(vec![HashMap::from([(
+                STREAM_FIELD.to_string(),
+                stream.clone(),
+            )])]),
+            must_not: Some(
+                generations
+                    .into_iter()
+                    .map(|generation| {
+                        HashMap::from([(SYNC_GENERATION_FIELD.to_string(), generation)])
+                    })
+                    .collect(),
+            ),
+            should: Some(vec![]),
+        });
+        match vector_database_client.delete_point(search_request).await {
+            Ok(VectorDatabaseStatus::Ok) => log::info!(
+                "Removed stale points of stream '{}' for datasource {}",
+                stream,
+                datasource_id
+            ),
+            Ok(status) => {
+                return Err(anyhow!(
+                    "Could not remove stale points of stream '{}'. Status: {:?}",
+                    stream,
+                    status
+                ))
+            }
+            Err(e) => {
+                return Err(anyhow!(
+                    "Could not remove stale points of stream '{}'. Error: {}",
+                    stream,
+                    e
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns true when the row is an Airbyte CDC delete marker, i.e. it has been deleted at the
+/// source and should be removed from the vector store rather than embedded
+pub fn is_source_deleted(metadata: &HashMap<String, Value>) -> bool {
+    metadata
+        .get(CDC_DELETED_AT_FIELD)
+        .is_some_and(|deleted_at| !deleted_at.is_null())
+}
+
+/// Removes every point (including all chunks) written for the row identified by the primary key
+/// hash stored in the row's `index`
+pub async fn delete_source_deleted_row(
+    mongo: &Database,
+    datasource: DataSources,
+    metadata: &HashMap<String, Value>,
+) -> Result<()> {
+    let datasource_id = datasource.id.to_string();
+    let index = match metadata.get("index") {
+        Some(Value::String(index)) => index.clone(),
+        _ => {
+            return Err(anyhow!(
+                "Received a delete for datasource {} but the stream has no primary key to \
+                identify the row with",
+                datasource_id
+            ))
+        }
+    };
+    let mut must = HashMap::from([(String::from("index"), index.clone())]);
+    if let Some(Value::String(stream)) = metadata.get(STREAM_FIELD) {
+        must.insert(STREAM_FIELD.to_string(), stream.clone());
+    }
+    let mut search_request = SearchRequest::new(
+        SearchType::Point,
+        datasource
+            .collection_name
+            .clone()
+            .unwrap_or(datasource_id.clone()),
+    );
+    search_request.byo_vector_db = datasource.byo_vector_db;
+    search_request.namespace = datasource.namespace.clone();
+    search_request.region = datasource.region.clone().map(|r| Region::from_str(&r));
+    search_request.filters = Some(FilterConditions {
+        must: Some(vec![must]),
+        must_not: Some(vec![]),
+        should: Some(vec![]),
+    });
+    let vector_database_client = check_byo_vector_database(datasource.clone(), mongo)
+        .await
+        .unwrap_or(default_vector_db_client().await);
+    let vector_database_client = vector_database_client.read().await;
+    match vector_database_client.delete_point(search_request).await {
+        Ok(VectorDatabaseStatus::Ok) => {
+            log::debug!(
+                "Removed point {} deleted at source for datasource {}",
+                index,
+                datasource_id
+            );
+            Ok(())
+        }
+        Ok(status) => Err(anyhow!(
+            "Could not remove point {} deleted at source. Status: {:?}",
+            index,
+            status
+        )),
+        Err(e) => Err(anyhow!(
+            "Could not remove point {} deleted at source. Error: {}",
+            index,
+            e
+        )),
+    }
+}
+
+// The cursor field is a path into the record, nested fields are addressed one key at a time
+fn extract_cursor(metadata: &HashMap<String, Value>, cursor_field: &[String]) -> Option<Value> {
+    let (first, rest) = cursor_field.split_first()?;
+    let mut value = metadata.get(first)?;
+    for key in rest {
+        value = value.get(key)?;
+    }
+    match value {
+        Value::Null => None,
+        v => Some(v.clone()),
+    }
+}
+
+// Cursors are usually numbers or timestamps. Pinecone stores all metadata as strings, so numeric
+// and date strings are parsed before falling back to comparing the raw strings
+fn compare_cursors(incoming: &Value, stored: &Value) -> Option<Ordering> {
+    fn as_str(value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            v => v.to_string(),
+        }
+    }
+    let (incoming, stored) = (as_str(incoming), as_str(stored));
+    if let (Ok(a), Ok(b)) = (incoming.parse::<f64>(), stored.parse::<f64>()) {
+        return a.partial_cmp(&b);
+    }
+    if let (Ok(a), Ok(b)) = (
+        DateTime::parse_from_rfc3339(incoming.as_str()),
+        DateTime::parse_from_rfc3339(stored.as_str()),
+    ) {
+        return Some(a.cmp(&b));
+    }
+    Some(incoming.cmp(&stored))
+}
+
+/// Returns true when a newer version of the row has been embedded already, based on the cursor
+/// set by `apply_sync_mode`. Cursors are recorded per row in Mongo rather than read back from the
+/// stored point, which saves a vector database read per row and covers chunked rows, whose points
+/// are not stored under the row's `index`. Checking and recording the cursor is a single compare
+/// and set, so only rows with a primary key can be checked.
+pub async fn is_stale_update(
+    mongo: &Database,
+    datasource: DataSources,
+    metadata: &HashMap<String, Value>,
+) -> Result<bool> {
+    let (Some(Value::String(index)), Some(incoming_cursor)) =
+        (metadata.get("index"), metadata.get(CURSOR_FIELD))
+    else {
+        return Ok(false);
+    };
+    let recorded = advance_row_cursor(
+        mongo,
+        datasource.id.to_string().as_str(),
+        index.as_str(),
+        incoming_cursor,
+        |stored_cursor| compare_cursors(incoming_cursor, stored_cursor) == Some(Ordering::Less),
+    )
+    .await?;
+    Ok(!recorded)
+}
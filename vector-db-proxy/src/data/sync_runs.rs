@@ -0,0 +1,268 @@
+use crate::adaptors::mongo::models::{DataSources, SyncRun, SyncRunStatus};
+use crate::adaptors::mongo::queries::{
+    checkpoint_sync_run, complete_sync_run_stream, get_open_sync_run, increment_sync_run_count,
+    receive_sync_run_records, set_datasource_state, transition_sync_run,
+};
+use crate::data::progress::{publish, ProgressEvent, ProgressEventType};
+use crate::data::sync_modes::complete_overwrite_syncs;
+use crate::utils::webhook::{dispatch_event, WebhookEvent};
+use anyhow::Result;
+use mongodb::Database;
+use serde_json::{json, Value};
+
+// A progress event is sent every time this many records of a run have been processed
+const PROGRESS_EVENT_INTERVAL: i64 = 100;
+
+/// The result of processing a single record of a sync
+#[derive(Debug, Clone)]
+pub enum RecordOutcome {
+    Success,
+    Failure(String),
+    Skipped,
+}
+
+impl RecordOutcome {
+    fn counter<'a>(&self) -> &'a str {
+        match self {
+            RecordOutcome::Success => "success",
+            RecordOutcome::Failure(_) => "failure",
+            RecordOutcome::Skipped => "skipped",
+        }
+    }
+}
+
+async fn set_run_status(
+    mongo: &Database,
+    datasource: DataSources,
+    sync_run: &SyncRun,
+    to: SyncRunStatus,
+    last_error: Option<String>,
+) -> Result<bool> {
+    let last_error_of_transition = last_error.clone();
+    let transitioned =
+        transition_sync_run(mongo, sync_run.id, sync_run.status, to, last_error).await?;
+    if transitioned {
+        log::info!(
+            "Sync run {} of datasource {} is now {}",
+            sync_run.id,
+            datasource.id,
+            SyncRunStatus::as_str(&to)
+        );
+        let event = match to {
+            SyncRunStatus::Ready => Some(WebhookEvent::EmbedCompleted),
+            SyncRunStatus::Failed => Some(WebhookEvent::EmbedFailed),
+            _ => None,
+        };
+        let datasource_id = datasource.id.to_string();
+        let mut run_after_transition = sync_run.clone();
+        run_after_transition.status = to;
+        if last_error_of_transition.is_some() {
+            run_after_transition.last_error = last_error_of_transition.clone();
+        }
+        publish(ProgressEvent::new(
+            datasource_id.clone(),
+            ProgressEventType::Status,
+            &run_after_transition,
+        ));
+        set_datasource_state(mongo, datasource, SyncRunStatus::datasource_status(&to)).await?;
+        if let Some(event) = event {
+            let mut payload = run_event_payload(sync_run);
+            payload["status"] = json!(SyncRunStatus::as_str(&to));
+            if let Some(error) = last_error_of_transition {
+                payload["lastError"] = json!(error);
+            }
+            dispatch_event(mongo, datasource_id.as_str(), event, payload).await?;
+        }
+    }
+    Ok(transitioned)
+}
+
+fn run_event_payload(sync_run: &SyncRun) -> Value {
+    json!({
+        "syncRunId": sync_run.id.to_hex(),
+        "status": SyncRunStatus::as_str(&sync_run.status),
+        "recordCount": {
+            "received": sync_run.record_count.received,
+            "success": sync_run.record_count.success,
+            "failure": sync_run.record_count.failure,
+            "skipped": sync_run.record_count.skipped,
+        },
+        "lastError": sync_run.last_error,
+    })
+}
+
+// Once the source has finished sending and every record it sent has been processed the run is
+// over. It is only considered failed if not a single record could be embedded. Points left over
+// from earlier full refresh overwrite syncs are only removed at this point, as the rows of this
+// sync that replace them have all been written by then.
+async fn finish_if_drained(
+    mongo: &Database,
+    datasource: DataSources,
+    sync_run: &SyncRun,
+) -> Result<()> {
+    let count = &sync_run.record_count;
+    if sync_run.status != SyncRunStatus::Embedding
+        || count.success + count.failure + count.skipped < count.received
+    {
+        return Ok(());
+    }
+    let status = if count.success == 0 && count.failure > 0 {
+        SyncRunStatus::Failed
+    } else {
+        SyncRunStatus::Ready
+    };
+    if status == SyncRunStatus::Ready {
+        complete_overwrite_syncs(mongo, datasource.clone()).await?;
+    }
+    set_run_status(mongo, datasource, sync_run, status, None).await?;
+    Ok(())
+}
+
+/// Records that `count` records of the datasource have been received, which starts a new sync run
+/// if none is open
+pub async fn start_records(mongo: &Database, datasource: DataSources, count: i64) -> Result<()> {
+    let sync_run =
+        receive_sync_run_records(mongo, datasource.id.to_string().as_str(), count).await?;
+    publish(ProgressEvent::new(
+        datasource.id.to_string(),
+        ProgressEventType::Received,
+        &sync_run,
+    ));
+    if sync_run.record_count.received == count && sync_run.status == SyncRunStatus::Syncing {
+        log::info!(
+            "Started sync run {} for datasource {}",
+            sync_run.id,
+            datasource.id
+        );
+        set_datasource_state(
+            mongo,
+            datasource.clone(),
+            SyncRunStatus::datasource_status(&SyncRunStatus::Syncing),
+        )
+        .await?;
+        dispatch_event(
+            mongo,
+            datasource.id.to_string().as_str(),
+            WebhookEvent::EmbedStarted,
+            run_event_payload(&sync_run),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Records the outcome of a received record against the open sync run and finishes the run if it
+/// was the last record outstanding
+pub async fn finish_record(
+    mongo: &Database,
+    datasource: DataSources,
+    outcome: RecordOutcome,
+) -> Result<()> {
+    let last_error = match &outcome {
+        RecordOutcome::Failure(e) => Some(e.clone()),
+        _ => None,
+    };
+    if let Some(sync_run) = increment_sync_run_count(
+        mongo,
+        datasource.id.to_string().as_str(),
+        outcome.counter(),
+        last_error,
+    )
+    .await?
+    {
+        let event_type = match outcome {
+            RecordOutcome::Success => ProgressEventType::Embedded,
+            RecordOutcome::Failure(_) => ProgressEventType::Failed,
+            RecordOutcome::Skipped => ProgressEventType::Skipped,
+        };
+        publish(ProgressEvent::new(
+            datasource.id.to_string(),
+            event_type,
+            &sync_run,
+        ));
+        let count = &sync_run.record_count;
+        if (count.success + count.failure + count.skipped) % PROGRESS_EVENT_INTERVAL == 0 {
+            dispatch_event(
+                mongo,
+                datasource.id.to_string().as_str(),
+                WebhookEvent::EmbedProgress,
+                run_event_payload(&sync_run),
+            )
+            .await?;
+        }
+        finish_if_drained(mongo, datasource, &sync_run).await?;
+    }
+    Ok(())
+}
+
+/// Marks the end of the source side of the open sync run. The run then waits for the remaining
+/// records to be embedded before becoming ready.
+pub async fn complete_sync_run(mongo: &Database, datasource: DataSources) -> Result<()> {
+    let datasource_id = datasource.id.to_string();
+    let Some(sync_run) = get_open_sync_run(mongo, datasource_id.as_str()).await? else {
+        return Ok(());
+    };
+    if sync_run.status == SyncRunStatus::Syncing {
+        set_run_status(
+            mongo,
+            datasource.clone(),
+            &sync_run,
+            SyncRunStatus::Embedding,
+            None,
+        )
+        .await?;
+    }
+    // Re-read the run as records may have finished while it was still syncing
+    match get_open_sync_run(mongo, datasource_id.as_str()).await? {
+        Some(sync_run) => finish_if_drained(mongo, datasource, &sync_run).await,
+        None => Ok(()),
+    }
+}
+
+/// Records the latest state checkpoint emitted by the source against the open sync run
+pub async fn checkpoint(mongo: &Database, datasource: DataSources, state: Value) -> Result<()> {
+    checkpoint_sync_run(
+        mongo,
+        datasource.id.to_string().as_str(),
+        bson::to_bson(&state)?,
+    )
+    .await
+}
+
+/// Marks a stream as completed by the source. Once every stream configured on the datasource has
+/// completed the source side of the sync is over and the run is completed.
+pub async fn complete_stream(
+    mongo: &Database,
+    datasource: DataSources,
+    stream: &str,
+) -> Result<()> {
+    let Some(sync_run) =
+        complete_sync_run_stream(mongo, datasource.id.to_string().as_str(), stream).await?
+    else {
+        return Ok(());
+    };
+    let all_streams_completed = datasource.stream_config.as_ref().is_some_and(|config| {
+        config
+            .keys()
+            .all(|stream| sync_run.completed_streams.contains(stream))
+    });
+    if all_streams_completed {
+        complete_sync_run(mongo, datasource).await?;
+    }
+    Ok(())
+}
+
+/// Fails the open sync run of the datasource, recording the error that caused it
+pub async fn fail_sync_run(mongo: &Database, datasource: DataSources, error: String) -> Result<()> {
+    if let Some(sync_run) = get_open_sync_run(mongo, datasource.id.to_string().as_str()).await? {
+        set_run_status(
+            mongo,
+            datasource,
+            &sync_run,
+            SyncRunStatus::Failed,
+            Some(error),
+        )
+        .await?;
+    }
+    Ok(())
+}
@@ -1,20 +1,21 @@
 use crate::adaptors::mongo::models::{
     UnstructuredChunkingConfig, UnstructuredChunkingStrategy, UnstructuredPartitioningStrategy,
 };
-use crate::data::models::FileType;
+use crate::data::local_parsers::parse_json;
+use crate::data::models::{FileContent, FileType};
 use crate::data::unstructuredio::models::UnstructuredIOResponse;
+use crate::utils::metrics::{UNSTRUCTURED_DURATION, UNSTRUCTURED_RETRIES};
 use anyhow::{anyhow, Result};
 use backoff::backoff::Backoff;
 use backoff::ExponentialBackoff;
 use reqwest::blocking::multipart::{Form, Part};
 use reqwest::blocking::Client;
 use reqwest::header::{HeaderMap, HeaderValue};
-use std::io::Cursor;
 use std::thread::sleep;
 use std::time::Duration;
 
 fn chunking_strategy_to_form_data(
-    file_buffer: Cursor<Vec<u8>>,
+    file: &FileContent,
     file_name: Option<String>,
     chunking_strategy: Option<UnstructuredChunkingConfig>,
     file_type: Option<FileType>,
@@ -25,7 +26,7 @@ fn chunking_strategy_to_form_data(
         "text_file.{}",
         FileType::to_str(file_type.unwrap())
     ));
-    let mut form = Form::new().part("files", Part::reader(file_buffer).file_name(name));
+    let mut form = Form::new().part("files", Part::reader(file.reader()?).file_name(name));
 
     if let Some(strategy_config) = chunking_strategy {
         let chunking_strategy = UnstructuredChunkingStrategy::as_str(&strategy_config.strategy);
@@ -44,8 +45,16 @@ fn chunking_strategy_to_form_data(
         let overlap_all = strategy_config.overlap_all.to_string();
         form = form.text("overlap_all", overlap_all);
 
-        let partitioning_strategy =
-            UnstructuredPartitioningStrategy::as_str(&strategy_config.partitioning);
+        let partitioning = match strategy_config.partitioning {
+            // Images have no text to extract, so they can only be read with OCR
+            UnstructuredPartitioningStrategy::Fast
+                if file_type.is_some_and(|file_type| file_type.is_image()) =>
+            {
+                UnstructuredPartitioningStrategy::HiRes
+            }
+            partitioning => partitioning,
+        };
+        let partitioning_strategy = UnstructuredPartitioningStrategy::as_str(&partitioning);
         form = form.text("strategy", partitioning_strategy);
     }
 
@@ -64,7 +73,24 @@ fn chunking_strategy_to_form_data(
 pub fn chunk_text(
     url: String,
     api_key: Option<String>,
-    file_buffer: Cursor<Vec<u8>>,
+    file: FileContent,
+    file_name: Option<String>,
+    chunking_strategy: Option<UnstructuredChunkingConfig>,
+    file_type: Option<FileType>,
+) -> Result<Vec<UnstructuredIOResponse>> {
+    if file_type == Some(FileType::JSON) {
+        return parse_json(&file, file_name, chunking_strategy);
+    }
+    let timer = UNSTRUCTURED_DURATION.start_timer();
+    let response = request_chunks(url, api_key, file, file_name, chunking_strategy, file_type);
+    timer.observe_duration();
+    response
+}
+
+fn request_chunks(
+    url: String,
+    api_key: Option<String>,
+    file: FileContent,
     file_name: Option<String>,
     chunking_strategy: Option<UnstructuredChunkingConfig>,
     file_type: Option<FileType>,
@@ -92,7 +118,7 @@ pub fn chunk_text(
 
     loop {
         let form = chunking_strategy_to_form_data(
-            file_buffer.clone(),
+            &file,
             file_name.clone(),
             chunking_strategy.clone(),
             file_type,
@@ -136,6 +162,7 @@ pub fn chunk_text(
         }
 
         if let Some(next_backoff) = backoff.next_backoff() {
+            UNSTRUCTURED_RETRIES.inc();
             sleep(next_backoff);
         } else {
             return Err(anyhow!("Reached maximum retry attempts"));
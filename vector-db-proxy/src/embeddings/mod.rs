@@ -1,3 +1,4 @@
 pub(crate) mod helpers;
 pub mod models;
+pub mod template;
 pub mod utils;
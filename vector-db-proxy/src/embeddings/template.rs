@@ -0,0 +1,377 @@
+use crate::embeddings::helpers::clean_text;
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// Templates parsed for each datasource and stream, with the text they were parsed from so that an
+// edited template is parsed again
+static PARSED_TEMPLATES: Lazy<Mutex<HashMap<(String, String), (String, Arc<EmbeddingTemplate>)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone)]
+enum TemplatePart {
+    Text(String),
+    // `{field}`, or `{field|fallback}` to write the fallback when the field is missing or empty
+    Field {
+        path: String,
+        fallback: Option<String>,
+    },
+    // `{#field}...{/field}` is only written when the field has a value, and `{^field}...{/field}`
+    // only when it has none
+    Section {
+        path: String,
+        inverted: bool,
+        parts: Vec<TemplatePart>,
+    },
+}
+
+/// Text embedded for a record, rendered from its fields, e.g.
+/// `{title}\n{description}{#category}\nCategory: {category}{/category}`. Fields can be nested
+/// objects, addressed as `{author.name}`, and `{{` and `}}` write literal braces. The values of
+/// fields are cleaned like a single embedding field, while the template's own text is kept as is.
+#[derive(Debug, Clone)]
+pub struct EmbeddingTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl EmbeddingTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        // Sections that are still open, with the parts written before each of them
+        let mut open_sections: Vec<(String, bool, Vec<TemplatePart>)> = vec![];
+        let mut parts: Vec<TemplatePart> = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => return Err(anyhow!("Unexpected `}}` in embedding template")),
+                '{' => {
+                    let mut tag = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => tag.push(c),
+                            None => return Err(anyhow!("Unclosed `{{` in embedding template")),
+                        }
+                    }
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    let tag = tag.trim();
+                    if let Some(path) = tag.strip_prefix('#').or_else(|| tag.strip_prefix('^')) {
+                        let inverted = tag.starts_with('^');
+                        open_sections.push((
+                            path.trim().to_string(),
+                            inverted,
+                            std::mem::take(&mut parts),
+                        ));
+                    } else if let Some(path) = tag.strip_prefix('/') {
+                        let Some((open_path, inverted, outer_parts)) = open_sections.pop() else {
+                            return Err(anyhow!(
+                                "`{{/{}}}` closes no section in embedding template",
+                                path.trim()
+                            ));
+                        };
+                        if open_path != path.trim() {
+                            return Err(anyhow!(
+                                "`{{/{}}}` closes section `{}` in embedding template",
+                                path.trim(),
+                                open_path
+                            ));
+                        }
+                        let section_parts = std::mem::replace(&mut parts, outer_parts);
+                        parts.push(TemplatePart::Section {
+                            path: open_path,
+                            inverted,
+                            parts: section_parts,
+                        });
+                    } else {
+                        let (path, fallback) = match tag.split_once('|') {
+                            Some((path, fallback)) => (path.trim(), Some(fallback.to_string())),
+                            None => (tag, None),
+                        };
+                        if path.is_empty() {
+                            return Err(anyhow!("Empty field name in embedding template"));
+                        }
+                        parts.push(TemplatePart::Field {
+                            path: path.to_string(),
+                            fallback,
+                        });
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+        if let Some((path, _, _)) = open_sections.last() {
+            return Err(anyhow!(
+                "Section `{}` is never closed in embedding template",
+                path
+            ));
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+        Ok(EmbeddingTemplate { parts })
+    }
+
+    /// Parses the template of a stream once, reusing it for every record of the stream until the
+    /// template is changed
+    pub fn for_stream(
+        datasource_id: &str,
+        stream: &str,
+        template: &str,
+    ) -> Result<Arc<EmbeddingTemplate>> {
+        let key = (datasource_id.to_string(), stream.to_string());
+        if let Ok(parsed_templates) = PARSED_TEMPLATES.lock() {
+            if let Some((text, parsed)) = parsed_templates.get(&key) {
+                if text == template {
+                    return Ok(Arc::clone(parsed));
+                }
+            }
+        }
+        let parsed = Arc::new(EmbeddingTemplate::parse(template)?);
+        if let Ok(mut parsed_templates) = PARSED_TEMPLATES.lock() {
+            parsed_templates.insert(key, (template.to_string(), Arc::clone(&parsed)));
+        }
+        Ok(parsed)
+    }
+
+    pub fn render(&self, record: &HashMap<String, Value>) -> String {
+        let mut rendered = String::new();
+        render_parts(&self.parts, record, &mut rendered);
+        rendered.trim().to_string()
+    }
+}
+
+/// Looks a field up by its name, or by its path through nested objects
+fn lookup<'a>(record: &'a HashMap<String, Value>, path: &str) -> Option<&'a Value> {
+    if let Some(value) = record.get(path) {
+        return Some(value);
+    }
+    let mut keys = path.split('.');
+    let mut value = record.get(keys.next()?)?;
+    for key in keys {
+        value = value.get(key)?;
+    }
+    Some(value)
+}
+
+/// Fields that are missing, null, empty or false count as having no value
+fn has_value(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+        Some(Value::String(text)) => !text.trim().is_empty(),
+        Some(Value::Array(items)) => !items.is_empty(),
+        Some(Value::Object(fields)) => !fields.is_empty(),
+        Some(_) => true,
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(value_text)
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<String>>()
+            .join(", "),
+        other => other.to_string(),
+    }
+}
+
+fn render_parts(parts: &[TemplatePart], record: &HashMap<String, Value>, rendered: &mut String) {
+    for part in parts {
+        match part {
+            TemplatePart::Text(text) => rendered.push_str(text),
+            TemplatePart::Field { path, fallback } => {
+                let value = lookup(record, path);
+                if has_value(value) {
+                    rendered.push_str(&clean_text(value.map(value_text).unwrap_or_default()));
+                } else if let Some(fallback) = fallback {
+                    rendered.push_str(fallback);
+                }
+            }
+            TemplatePart::Section {
+                path,
+                inverted,
+                parts,
+            } => {
+                if has_value(lookup(record, path)) != *inverted {
+                    render_parts(parts, record, rendered);
+                }
+            }
+        }
+    }
+}
+
+/// Where the text embedded for a record comes from
+#[derive(Debug, Clone)]
+pub enum EmbeddingSource {
+    // The stream's embedding template
+    Template(Arc<EmbeddingTemplate>),
+    // The datasource's single embedding field, which is taken out of the payload
+    Field(String),
+}
+
+impl EmbeddingSource {
+    /// The text to embed, or None when the record has nothing to embed
+    pub fn text(&self, record: &HashMap<String, Value>) -> Option<String> {
+        match self {
+            Self::Template(template) => Some(template.render(record)).filter(|t| !t.is_empty()),
+            Self::Field(field) => record.get(field).map(|value| clean_text(value.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn render(template: &str, value: Value) -> String {
+        EmbeddingTemplate::parse(template)
+            .unwrap()
+            .render(&record(value))
+    }
+
+    fn parse_error(template: &str) -> String {
+        EmbeddingTemplate::parse(template).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn renders_fields_and_nested_paths() {
+        let rendered = render(
+            "{title}\nBy {author.name}",
+            json!({"title": "Dune", "author": {"name": "Frank Herbert"}}),
+        );
+        assert_eq!(rendered, "Dune\nBy Frank Herbert");
+    }
+
+    #[test]
+    fn prefers_a_field_named_like_the_path() {
+        let rendered = render(
+            "{author.name}",
+            json!({"author.name": "Flat", "author": {"name": "Nested"}}),
+        );
+        assert_eq!(rendered, "Flat");
+    }
+
+    #[test]
+    fn writes_fallbacks_for_missing_or_empty_fields() {
+        let template = "{title|Untitled} ({year|unknown year})";
+        assert_eq!(
+            render(template, json!({"title": "  "})),
+            "Untitled (unknown year)"
+        );
+        assert_eq!(
+            render(template, json!({"title": "Dune", "year": 1965})),
+            "Dune (1965)"
+        );
+        assert_eq!(render("{missing}", json!({})), "");
+    }
+
+    #[test]
+    fn renders_nested_and_inverted_sections() {
+        let template =
+            "{#author}By {author.name}{#author.born} ({author.born}){/author.born}{/author}\
+            {^author}Anonymous{/author}";
+        assert_eq!(
+            render(
+                template,
+                json!({"author": {"name": "Frank Herbert", "born": 1920}})
+            ),
+            "By Frank Herbert (1920)"
+        );
+        assert_eq!(
+            render(template, json!({"author": {"name": "Frank Herbert"}})),
+            "By Frank Herbert"
+        );
+        assert_eq!(render(template, json!({"author": null})), "Anonymous");
+    }
+
+    #[test]
+    fn writes_literal_braces() {
+        assert_eq!(
+            render("{{title}} is {title}", json!({"title": "Dune"})),
+            "{title} is Dune"
+        );
+        assert_eq!(render("}}{{", json!({})), "}{");
+    }
+
+    #[test]
+    fn joins_arrays_and_cleans_values_but_not_the_template() {
+        let rendered = render(
+            "Tags: {tags}\nQuote: {quote}",
+            json!({"tags": ["sci-fi", null, "classic"], "quote": "Fear is\nthe \"mind-killer\""}),
+        );
+        assert_eq!(
+            rendered,
+            "Tags: sci-fi, classic\nQuote: Fear isthe mind-killer"
+        );
+    }
+
+    #[test]
+    fn rejects_unclosed_and_unexpected_braces() {
+        assert_eq!(parse_error("{title"), "Unclosed `{` in embedding template");
+        assert_eq!(
+            parse_error("title}"),
+            "Unexpected `}` in embedding template"
+        );
+        assert_eq!(
+            parse_error("{ |fallback}"),
+            "Empty field name in embedding template"
+        );
+    }
+
+    #[test]
+    fn rejects_unclosed_and_mismatched_sections() {
+        assert_eq!(
+            parse_error("{#author}{author.name}"),
+            "Section `author` is never closed in embedding template"
+        );
+        assert_eq!(
+            parse_error("{/author}"),
+            "`{/author}` closes no section in embedding template"
+        );
+        assert_eq!(
+            parse_error("{#author}{#tags}{/author}{/tags}"),
+            "`{/author}` closes section `tags` in embedding template"
+        );
+    }
+
+    #[test]
+    fn reparses_a_stream_template_once_it_changes() {
+        let first = EmbeddingTemplate::for_stream("datasource", "stream", "{title}").unwrap();
+        let again = EmbeddingTemplate::for_stream("datasource", "stream", "{title}").unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        let changed = EmbeddingTemplate::for_stream("datasource", "stream", "{name}").unwrap();
+        assert!(!Arc::ptr_eq(&first, &changed));
+        assert_eq!(changed.render(&record(json!({"name": "Dune"}))), "Dune");
+    }
+
+    #[test]
+    fn embeds_nothing_for_an_empty_render() {
+        let source =
+            EmbeddingSource::Template(Arc::new(EmbeddingTemplate::parse("{title}").unwrap()));
+        assert_eq!(source.text(&record(json!({}))), None);
+        assert_eq!(
+            source.text(&record(json!({"title": "Dune"}))),
+            Some("Dune".to_string())
+        );
+    }
+}
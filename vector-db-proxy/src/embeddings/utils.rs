@@ -1,9 +1,11 @@
 use crate::adaptors::mongo::models::{DataSources, Model};
 use crate::adaptors::mongo::queries::increment_by_one;
+use crate::data::quotas::estimate_tokens;
 use crate::data::unstructuredio::models::UnstructuredIOResponse;
 use crate::embeddings::helpers::clean_text;
 use crate::embeddings::models::{EmbeddingModels, FastEmbedModels};
 use crate::init::env_variables::GLOBAL_DATA;
+use crate::utils::metrics::{EMBEDDING_DURATION, EMBEDDING_TOKENS};
 use crate::vector_databases::helpers::check_byo_vector_database;
 use crate::vector_databases::models::{Point, SearchRequest, SearchType, VectorDatabaseStatus};
 use crate::vector_databases::vector_database::default_vector_db_client;
@@ -20,6 +22,7 @@ use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc as arc;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::mpsc;
 use tokio::sync::RwLock;
 use tokio::task;
@@ -129,9 +132,11 @@ async fn fastembed_models(
     }
 }
 
+#[tracing::instrument(skip_all, fields(model = %model.model, texts = text.len()))]
 pub async fn embed_text(text: Vec<&String>, model: &Model) -> Result<Vec<Vec<f32>>> {
     let model_name = model.clone().model;
-    match EmbeddingModels::from(model_name.clone()) {
+    let start = Instant::now();
+    let embeddings = match EmbeddingModels::from(model_name.clone()) {
         EmbeddingModels::UNKNOWN => Err(anyhow!("This is an unknown model type!")),
         // Group all fast embed models together
         EmbeddingModels::BAAI_BGE_SMALL_EN
@@ -141,7 +146,12 @@ pub async fn embed_text(text: Vec<&String>, model: &Model) -> Result<Vec<Vec<f32
         | EmbeddingModels::ENTENCE_TRANSFORMERS_ALL_MINILM_L6_V2
         | EmbeddingModels::XENOVA_FAST_MULTILINGUAL_E5_LARGE => {
             let global_data = GLOBAL_DATA.read().await;
-            let model = FastEmbedModels::from(model_name);
+            // Local models do not report usage so their tokens are estimated
+            let estimated_tokens: i64 = text.iter().map(|t| estimate_tokens(t.as_str())).sum();
+            EMBEDDING_TOKENS
+                .with_label_values(&[model_name.as_str()])
+                .inc_by(estimated_tokens as u64);
+            let model = FastEmbedModels::from(model_name.clone());
             fastembed_models(&model, global_data.use_gpu.as_str(), text).await
         }
         // Assume OAI models for now...
@@ -162,6 +172,9 @@ pub async fn embed_text(text: Vec<&String>, model: &Model) -> Result<Vec<Vec<f32
                     .input(text)
                     .build()?;
                 let response = client.embeddings().create(request).await?;
+                EMBEDDING_TOKENS
+                    .with_label_values(&[model_name.as_str()])
+                    .inc_by(response.usage.total_tokens as u64);
                 let embedding: Vec<Vec<f32>> = response
                     .data
                     .iter()
@@ -172,7 +185,11 @@ pub async fn embed_text(text: Vec<&String>, model: &Model) -> Result<Vec<Vec<f32
                 Err(anyhow!("Model missing api key"))
             }
         }
-    }
+    };
+    EMBEDDING_DURATION
+        .with_label_values(&[model_name.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+    embeddings
 }
 
 pub async fn embed_text_chunks_async(
@@ -207,6 +224,7 @@ pub async fn embed_text_chunks_async(
     Ok(list_of_embeddings)
 }
 
+#[tracing::instrument(skip_all, fields(datasource_id = %datasource.id, documents = documents.len()))]
 pub async fn embed_bulk_insert_unstructured_response(
     documents: Vec<UnstructuredIOResponse>,
     datasource: DataSources,
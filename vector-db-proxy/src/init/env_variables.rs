@@ -11,7 +11,8 @@ pub async fn set_all_env_vars() {
 }
 
 
+// Replaced by the loaded configuration at startup, see `crate::init::config::load_config`
 pub static GLOBAL_DATA: Lazy<RwLock<GlobalData>> = Lazy::new(|| {
-    let data: GlobalData = GlobalData::new();
+    let data: GlobalData = GlobalData::default();
     RwLock::new(data)
 });
\ No newline at end of file
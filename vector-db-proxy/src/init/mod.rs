@@ -1,2 +1,3 @@
+pub mod config;
 pub mod env_variables;
 pub mod models;
\ No newline at end of file
@@ -1,7 +1,10 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::thread::available_parallelism;
 
-#[derive(Clone, Serialize, Debug, Default)]
+/// Configuration of the proxy. Every field can be set in the config file under its own name and
+/// overridden by the environment variable listed in `crate::init::config::ENV_OVERRIDES`.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
 pub struct GlobalData {
     pub port: String,
     pub host: String,
@@ -12,6 +15,9 @@ pub struct GlobalData {
     pub rabbitmq_routing_key: String,
     pub rabbitmq_username: String,
     pub rabbitmq_password: String,
+    pub kafka_bootstrap_servers: String,
+    pub kafka_topic: String,
+    pub kafka_group_id: String,
     pub mongo_uri: String,
     pub mongo_db_name: String,
     pub qdrant_host: String,
@@ -19,8 +25,17 @@ pub struct GlobalData {
     pub webapp_host: String,
     pub webapp_port: String,
     pub redis_host: String,
-    pub redis_port: String,
+    pub redis_port: u16,
+    pub redis_password: String,
+    pub redis_stream: String,
+    pub redis_consumer_group: String,
+    // How long an entry may stay unacknowledged before another consumer takes it over
+    pub redis_claim_idle_secs: u64,
+    // How many times an entry is delivered before it is moved to the dead-letter stream
+    pub redis_max_deliveries: u64,
     pub thread_percentage_utilisation: f64,
+    // Detected from the machine rather than configured
+    #[serde(skip)]
     pub number_of_threads: f64,
     pub use_gpu: String,
     pub logging_level: String,
@@ -31,50 +46,98 @@ pub struct GlobalData {
     pub vector_database_api_key: String,
     pub vector_database_url: String,
     pub hashing_salt: String,
+    pub webhook_secret: String,
+    pub webhook_max_attempts: i32,
+    pub api_key: String,
+    pub cors_allowed_origins: Vec<String>,
+    // A quota of 0 means the team is not limited
+    pub quota_max_vectors: i64,
+    pub quota_max_bytes: i64,
+    pub quota_max_tokens_per_day: i64,
+    pub otel_exporter_otlp_endpoint: String,
+    pub otel_service_name: String,
+    // How long in-flight work may take to finish on shutdown before it is requeued
+    pub shutdown_timeout_secs: u64,
+    // How long a pooled BYO vector database client is used before its record is checked again
+    pub vector_db_client_ttl_secs: u64,
+    // `<key id>:<base64 AES-256 key>,...` encrypting BYO vector DB credentials, current key first
+    pub vector_db_encryption_keys: String,
+    // Left empty for AWS itself, or the URL of MinIO or another S3 compatible store
+    pub s3_endpoint: String,
+    pub s3_region: String,
+    // Without keys, credentials come from the environment, e.g. the instance's IAM role
+    pub s3_access_key_id: String,
+    pub s3_secret_access_key: String,
+    // MinIO and most other S3 compatible stores address buckets by path rather than subdomain
+    pub s3_force_path_style: bool,
+    // Archives with more entries, or expanding to more bytes, are rejected as likely zip bombs
+    pub archive_max_entries: usize,
+    pub archive_max_uncompressed_bytes: u64,
+    // Local files can only be imported from inside this directory, and not at all when it is empty
+    pub import_directory: String,
 }
 
-impl GlobalData {
-    pub fn new() -> Self {
+impl Default for GlobalData {
+    fn default() -> Self {
         GlobalData {
-            host: dotenv::var("HOST").unwrap_or("0.0.0.0".to_string()),
-            port: dotenv::var("PORT").unwrap_or("9001".to_string()),
-            rabbitmq_port: dotenv::var("RABBITMQ_PORT")
-                .unwrap()
-                .parse()
-                .unwrap_or(5672),
-            rabbitmq_host: dotenv::var("RABBITMQ_HOST").unwrap_or("localhost".to_string()),
-            rabbitmq_stream: dotenv::var("RABBITMQ_STREAM").unwrap_or("streaming".to_string()),
-            rabbitmq_exchange: dotenv::var("RABBITMQ_EXCHANGE").unwrap_or("agentcloud".to_string()),
-            rabbitmq_routing_key: dotenv::var("RABBITMQ_ROUTING_KEY").unwrap_or("key".to_string()),
-            rabbitmq_username: dotenv::var("RABBITMQ_USERNAME").unwrap_or("agentcloud".to_string()),
-            rabbitmq_password: dotenv::var("RABBITMQ_PASSWORD")
-                .unwrap_or("alphanumeric123".to_string()),
-            mongo_uri: dotenv::var("MONGO_URI").unwrap_or("mongodb://localhost:27017".to_string()),
-            mongo_db_name: dotenv::var("MONGO_DB_NAME").unwrap_or("agentcloud".to_string()),
-            qdrant_host: dotenv::var("QDRANT_HOST").unwrap_or("http://localhost".to_string()),
-            qdrant_port: dotenv::var("QDRANT_PORT").unwrap_or("6334".to_string()),
-            webapp_host: dotenv::var("WEBAPP_HOST").unwrap_or("localhost".to_string()),
-            webapp_port: dotenv::var("WEBAPP_PORT").unwrap_or("3000".to_string()),
-            redis_host: dotenv::var("REDIS_HOST").unwrap_or("localhost".to_string()),
-            redis_port: dotenv::var("REDIS_PORT").unwrap_or("6379".to_string()),
-            thread_percentage_utilisation: dotenv::var("THREAD_PERCENTAGE_UTILISATION")
-                .unwrap_or("1".to_string())
-                .parse()
-                .unwrap_or(0.8),
+            host: "0.0.0.0".to_string(),
+            port: "9001".to_string(),
+            rabbitmq_port: 5672,
+            rabbitmq_host: "localhost".to_string(),
+            rabbitmq_stream: "streaming".to_string(),
+            rabbitmq_exchange: "agentcloud".to_string(),
+            rabbitmq_routing_key: "key".to_string(),
+            rabbitmq_username: "agentcloud".to_string(),
+            rabbitmq_password: "alphanumeric123".to_string(),
+            kafka_bootstrap_servers: "localhost:9092".to_string(),
+            kafka_topic: "streaming".to_string(),
+            kafka_group_id: "vector-db-proxy".to_string(),
+            mongo_uri: "mongodb://localhost:27017".to_string(),
+            mongo_db_name: "agentcloud".to_string(),
+            qdrant_host: "http://localhost".to_string(),
+            qdrant_port: "6334".to_string(),
+            webapp_host: "localhost".to_string(),
+            webapp_port: "3000".to_string(),
+            redis_host: "localhost".to_string(),
+            redis_port: 6379,
+            redis_password: String::new(),
+            redis_stream: "streaming".to_string(),
+            redis_consumer_group: "vector-db-proxy".to_string(),
+            redis_claim_idle_secs: 300,
+            redis_max_deliveries: 5,
+            thread_percentage_utilisation: 1.0,
             number_of_threads: available_parallelism()
                 .map(|t| t.get() as f64)
                 .unwrap_or(12.0),
-            use_gpu: dotenv::var("USE_GPU").unwrap_or("false".to_string()),
-            logging_level: dotenv::var("LOGGING_LEVEL").unwrap_or("info".to_string()),
-            message_queue_provider: dotenv::var("MESSAGE_QUEUE_PROVIDER")
-                .unwrap_or("rabbitmq".to_string()),
-            unstructuredio_url: dotenv::var("UNSTRUCTURED_API_URL")
-                .unwrap_or("http://localhost:9500/general/v0/general".to_string()),
-            unstructuredio_api_key: dotenv::var("UNSTRUCTURED_API_KEY").unwrap_or(String::new()),
-            vector_database: dotenv::var("VECTOR_DATABASE").unwrap_or("qdrant".to_string()),
-            vector_database_api_key: dotenv::var("VECTOR_DATABASE_API_KEY").unwrap_or_default(),
-            vector_database_url: dotenv::var("VECTOR_DATABASE_URL").unwrap_or_default(),
-            hashing_salt: dotenv::var("HASHING_SALT").unwrap_or("something_secretive".to_string()),
+            use_gpu: "false".to_string(),
+            logging_level: "info".to_string(),
+            message_queue_provider: "rabbitmq".to_string(),
+            unstructuredio_url: "http://localhost:9500/general/v0/general".to_string(),
+            unstructuredio_api_key: String::new(),
+            vector_database: "qdrant".to_string(),
+            vector_database_api_key: String::new(),
+            vector_database_url: String::new(),
+            hashing_salt: "something_secretive".to_string(),
+            webhook_secret: String::new(),
+            webhook_max_attempts: 10,
+            api_key: String::new(),
+            cors_allowed_origins: vec![],
+            quota_max_vectors: 0,
+            quota_max_bytes: 0,
+            quota_max_tokens_per_day: 0,
+            otel_exporter_otlp_endpoint: String::new(),
+            otel_service_name: "vector-db-proxy".to_string(),
+            shutdown_timeout_secs: 25,
+            vector_db_client_ttl_secs: 300,
+            vector_db_encryption_keys: String::new(),
+            s3_endpoint: String::new(),
+            s3_region: "us-east-1".to_string(),
+            s3_access_key_id: String::new(),
+            s3_secret_access_key: String::new(),
+            s3_force_path_style: false,
+            archive_max_entries: 1000,
+            archive_max_uncompressed_bytes: 1024 * 1024 * 1024,
+            import_directory: String::new(),
         }
     }
 }
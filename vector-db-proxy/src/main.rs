@@ -4,15 +4,18 @@
 #![allow(unused_assignments)]
 
 use std::sync::Arc;
-use std::thread;
+use std::{env, process, thread};
 
 use actix_cors::Cors;
-use actix_web::{middleware::Logger, web, App, HttpServer};
+use actix_web::http::header::{HeaderName, AUTHORIZATION, CONTENT_TYPE};
+use actix_web::middleware::{from_fn, Logger};
+use actix_web::{web, App, HttpServer};
 use anyhow::Context;
 use crossbeam::channel;
 use env_logger::Env;
-use tokio::signal;
 use tokio::sync::RwLock;
+use tokio::time::{timeout, timeout_at, Duration, Instant};
+use tracing::Span;
 
 use routes::apis::{
     bulk_upsert_data_to_collection, check_collection_exists, delete_collection,
@@ -20,12 +23,26 @@ use routes::apis::{
 };
 
 use crate::adaptors::mongo::models::DataSources;
+use crate::adaptors::mongo::queries::ensure_indexes;
 use crate::data::processing_incoming_messages::process_incoming_messages;
+use crate::init::config::{config_file_path, load_config, print_config};
 use crate::init::env_variables::set_all_env_vars;
 use crate::init::env_variables::GLOBAL_DATA;
-use crate::messages::models::{MessageQueue, MessageQueueProvider};
+use crate::messages::models::{MessageAck, MessageQueue, MessageQueueProvider};
+use crate::messages::task_handoff::{
+    close_work_queue, open_work_queue, take_pending_ingests, take_unfinished_tasks,
+};
 use crate::messages::tasks::get_message_queue;
-use crate::routes::apis::{create_collection, get_storage_size, scroll_data};
+use crate::routes::apis::{
+    complete_datasource_sync, create_collection, datasource_events, get_storage_size,
+    import_points_to_collection, ingest_records, liveness, metrics,
+    migrate_collection_to_vector_db, readiness, scroll_data,
+};
+use crate::routes::auth::authenticate;
+use crate::utils::shutdown::{request_shutdown, wait_for_shutdown_signal};
+use crate::utils::telemetry::{init_tracing, shutdown_tracing};
+use crate::utils::webhook::{flush_webhook_events, run_webhook_dispatcher};
+use crate::vector_databases::credentials::rotate_vector_db_credentials;
 use adaptors::mongo::client::start_mongo_connection;
 
 mod adaptors;
@@ -37,39 +54,100 @@ mod routes;
 mod utils;
 mod vector_databases;
 
-pub fn init(config: &mut web::ServiceConfig) {
-    let cors = Cors::default()
-        .allow_any_origin()
-        .allowed_methods(["GET", "POST", "PUT", "OPTIONS"])
-        .supports_credentials()
-        .allow_any_header();
-
-    config.service(
-        web::scope("/api/v1")
-            .wrap(cors)
-            .service(health_check)
-            .service(list_collections)
-            .service(delete_collection)
-            .service(check_collection_exists)
-            .service(create_collection)
-            .service(upsert_data_point_to_collection)
-            .service(bulk_upsert_data_to_collection)
-            .service(scroll_data)
-            .service(get_collection_info)
-            .service(get_storage_size),
-    );
+// How long delivering the remaining webhook events may take on shutdown
+const WEBHOOK_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn init(config: &mut web::ServiceConfig, allowed_origins: &[String]) {
+    // The API is called server to server, so browsers are only let in from the configured origins
+    let cors = allowed_origins
+        .iter()
+        .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+        .allowed_methods(["GET", "POST", "PUT", "DELETE", "OPTIONS"])
+        .allowed_headers([
+            AUTHORIZATION,
+            CONTENT_TYPE,
+            HeaderName::from_static("x-team-id"),
+        ]);
+
+    config
+        .service(metrics)
+        .service(liveness)
+        .service(readiness)
+        .service(
+            web::scope("/api/v1")
+                .wrap(from_fn(authenticate))
+                .wrap(cors)
+                .service(health_check)
+                .service(list_collections)
+                .service(delete_collection)
+                .service(check_collection_exists)
+                .service(create_collection)
+                .service(upsert_data_point_to_collection)
+                .service(bulk_upsert_data_to_collection)
+                .service(scroll_data)
+                .service(get_collection_info)
+                .service(get_storage_size)
+                .service(import_points_to_collection)
+                .service(migrate_collection_to_vector_db)
+                .service(complete_datasource_sync)
+                .service(ingest_records)
+                .service(datasource_events),
+        );
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     log::info!("Starting Vector DB Proxy APP...");
-    let global_data = GLOBAL_DATA.read().await;
     let _ = set_all_env_vars().await;
+    let args: Vec<String> = env::args().collect();
+    let config = match load_config(config_file_path(&args).as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    if args.iter().any(|arg| arg == "--print-config") {
+        match print_config(&config) {
+            Ok(printed_config) => println!("{}", printed_config),
+            Err(e) => eprintln!("Could not print configuration: {}", e),
+        }
+        return Ok(());
+    }
+    *GLOBAL_DATA.write().await = config;
+    let global_data = GLOBAL_DATA.read().await;
     let logging_level = global_data.logging_level.clone().to_lowercase();
     let host = global_data.host.clone();
     let port = global_data.port.clone();
+    let cors_allowed_origins = global_data.cors_allowed_origins.clone();
+    if let Err(e) = init_tracing(
+        global_data.otel_exporter_otlp_endpoint.as_str(),
+        global_data.otel_service_name.as_str(),
+    ) {
+        log::error!("Could not set up tracing. Error: {}", e);
+    }
 
     let mongo_connection = start_mongo_connection().await.unwrap();
+    if args.iter().any(|arg| arg == "--rotate-credentials") {
+        match rotate_vector_db_credentials(&mongo_connection).await {
+            Ok((rotated, 0)) => println!("Re-encrypted the credentials of {} vector DBs", rotated),
+            Ok((rotated, failed)) => {
+                eprintln!(
+                    "Re-encrypted the credentials of {} vector DBs, {} could not be re-encrypted",
+                    rotated, failed
+                );
+                process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("Could not rotate credentials: {}", e);
+                process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+    if let Err(e) = ensure_indexes(&mongo_connection).await {
+        log::error!("Could not create indexes. Error: {}", e);
+    }
     // Create Arcs to allow sending across threads
     let app_mongo_client = Arc::new(RwLock::new(mongo_connection));
 
@@ -77,20 +155,34 @@ async fn main() -> std::io::Result<()> {
     let mongo_client_for_streaming = Arc::clone(&app_mongo_client);
 
     // Clones of the receiver and sender so that they can be sent to the right threads
-    let (s, r) = channel::unbounded::<(DataSources, Option<String>, String)>();
+    let (s, r) = channel::unbounded::<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>();
     let sender_clone = s.clone();
+    // Records sent to the ingest endpoint go on the same work queue
+    open_work_queue(s.clone());
 
     // This is to allow the use of multiple message queues
     let message_queue_provider =
         MessageQueueProvider::from(global_data.message_queue_provider.clone());
     let connection = get_message_queue(message_queue_provider).await;
+    let requeue_connection = connection.clone();
 
     // Thread to read messages from message queue and pass them to channel for processing
-    let subscribe_to_message_stream = tokio::spawn(async move {
+    let mut subscribe_to_message_stream = tokio::spawn(async move {
         let _ = connection
             .consume(connection.clone(), mongo_client_for_streaming, sender_clone)
             .await;
     });
+    // Task delivering webhook events from the outbox to the webapp
+    let mongo_client_for_webhooks = Arc::clone(&app_mongo_client);
+    tokio::spawn(async move {
+        run_webhook_dispatcher(mongo_client_for_webhooks).await;
+    });
     // Figure out how many threads are available on the machine and the percentage of those that the user would like to use when syncing data
     let number_of_workers =
         (global_data.number_of_threads * global_data.thread_percentage_utilisation) as i32;
@@ -98,14 +190,14 @@ async fn main() -> std::io::Result<()> {
     // Thread for receiving messages in channel and processing them across workers
     // Spawn multiple threads to process messages
     let mut handles = vec![];
-    for _ in 0..(number_of_workers * 10) {
+    for worker in 0..(number_of_workers * 10) as usize {
         // let receiver_clone = receiver.clone();
         let mongo_client_clone = Arc::clone(&app_mongo_client);
         let receiver = r.clone();
         let handle = thread::spawn(move || {
             let rt = tokio::runtime::Runtime::new().unwrap();
             rt.block_on(async {
-                process_incoming_messages(receiver, mongo_client_clone).await;
+                process_incoming_messages(worker, receiver, mongo_client_clone).await;
             });
         });
         handles.push(handle);
@@ -113,21 +205,81 @@ async fn main() -> std::io::Result<()> {
 
     // Set the default logging level
     env_logger::Builder::from_env(Env::default().default_filter_or(logging_level)).init();
-    let web_task = tokio::spawn(async move {
-        log::info!("Running on http://{}:{}", host.clone(), port.clone());
-        let server = HttpServer::new(move || App::new().wrap(Logger::default()).configure(init))
-            .bind(format!("{}:{}", host, port))?
-            .run();
-
-        server.await.context("server error!")
-    });
+    log::info!("Running on http://{}:{}", host.clone(), port.clone());
+    // Signals are handled below, so that the server keeps answering while the workers drain
+    let mongo_client_for_server = Arc::clone(&app_mongo_client);
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(Arc::clone(&mongo_client_for_server)))
+            .wrap(Logger::default())
+            .configure(|config| init(config, &cors_allowed_origins))
+    })
+    .bind(format!("{}:{}", host, port))?
+    .disable_signals()
+    .run();
+    let server_handle = server.handle();
+    let mut web_task = tokio::spawn(async move { server.await.context("server error!") });
 
     tokio::select! {
-        _ = web_task => log::info!("Web server task completed"),
-        _ = subscribe_to_message_stream => log::info!("Message stream task completed"),
-        _ = signal::ctrl_c() => {
-            log::info!("Received Ctrl+C, shutting down");
+        _ = &mut web_task => log::info!("Web server task completed"),
+        _ = &mut subscribe_to_message_stream => log::info!("Message stream task completed"),
+        _ = wait_for_shutdown_signal() => {}
+    }
+
+    // Stop consuming, then let the workers finish the work queue until the timeout
+    log::info!("Shutting down, draining in-flight work");
+    request_shutdown();
+    let drain_deadline = Instant::now() + Duration::from_secs(global_data.shutdown_timeout_secs);
+    close_work_queue();
+    // File ingests still loading their file hold a sender, and the rest of it would be lost
+    for ingest in take_pending_ingests() {
+        if timeout_at(drain_deadline, ingest).await.is_err() {
+            log::warn!("A file ingest did not reach the work queue before the shutdown timeout");
         }
     }
+    // The workers stop once the work queue is empty and every sender is gone
+    drop(s);
+    if !subscribe_to_message_stream.is_finished()
+        && timeout_at(drain_deadline, &mut subscribe_to_message_stream)
+            .await
+            .is_err()
+    {
+        log::warn!("The message queue consumer did not stop in time");
+        subscribe_to_message_stream.abort();
+    }
+    let workers = tokio::task::spawn_blocking(move || {
+        for handle in handles {
+            let _ = handle.join();
+        }
+    });
+    if timeout_at(drain_deadline, workers).await.is_ok() {
+        log::info!("All workers finished");
+    } else {
+        // Whatever is still queued or being processed goes back on the message queue, unless its
+        // message is left pending there to be delivered again. Upserts are idempotent, so a task
+        // that completes after being requeued is only embedded twice.
+        let unfinished_tasks = take_unfinished_tasks(&r);
+        let number_of_unfinished_tasks = unfinished_tasks.len();
+        let requeued = requeue_connection.requeue(unfinished_tasks).await;
+        log::warn!(
+            "Workers did not finish within {}s, requeued {} of {} unfinished tasks",
+            global_data.shutdown_timeout_secs,
+            requeued,
+            number_of_unfinished_tasks
+        );
+    }
+    requeue_connection.close().await;
+    if timeout(
+        WEBHOOK_FLUSH_TIMEOUT,
+        flush_webhook_events(Arc::clone(&app_mongo_client)),
+    )
+    .await
+    .is_err()
+    {
+        log::warn!("Webhook events left in the outbox will be delivered by the next instance");
+    }
+    server_handle.stop(true).await;
+    shutdown_tracing();
+    log::info!("Shutdown complete");
     Ok(())
 }
@@ -0,0 +1,267 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// Set next to `_airbyte_data` on records from the ingestion API, which are counted against the
+// sync run when they are queued rather than when a worker takes them
+pub const INGESTED_RECORD_FIELD: &str = "_ac_ingested";
+
+/// A message of the Airbyte protocol as written by a source. Only the message types the proxy
+/// acts upon are modelled, anything else is rejected when parsing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "UPPERCASE")]
+pub enum AirbyteMessage {
+    Record { record: AirbyteRecordMessage },
+    State { state: AirbyteStateMessage },
+    Trace { trace: AirbyteTraceMessage },
+    Log { log: AirbyteLogMessage },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AirbyteRecordMessage {
+    pub stream: Option<String>,
+    pub namespace: Option<String>,
+    pub data: Map<String, Value>,
+    pub emitted_at: Option<i64>,
+    #[serde(skip)]
+    pub ingested: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AirbyteStreamDescriptor {
+    pub name: String,
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AirbyteStreamState {
+    pub stream_descriptor: AirbyteStreamDescriptor,
+    pub stream_state: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AirbyteStateType {
+    Stream,
+    Global,
+    Legacy,
+    // State types added to the protocol later on are still accepted
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AirbyteStateMessage {
+    #[serde(rename = "type")]
+    pub state_type: Option<AirbyteStateType>,
+    pub stream: Option<AirbyteStreamState>,
+    pub global: Option<Value>,
+    pub data: Option<Value>,
+}
+
+impl AirbyteStateMessage {
+    /// The name of the stream this checkpoint belongs to, if it is a per-stream state
+    pub fn stream_name(&self) -> Option<String> {
+        self.stream
+            .as_ref()
+            .map(|s| s.stream_descriptor.name.clone())
+    }
+
+    /// The checkpoint itself, whatever the flavour of state message
+    pub fn checkpoint(&self) -> Value {
+        self.stream
+            .as_ref()
+            .and_then(|s| s.stream_state.clone())
+            .or(self.global.clone())
+            .or(self.data.clone())
+            .unwrap_or(Value::Null)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AirbyteTraceType {
+    Error,
+    Estimate,
+    StreamStatus,
+    Analytics,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AirbyteErrorTraceMessage {
+    pub message: String,
+    pub internal_message: Option<String>,
+    pub stack_trace: Option<String>,
+    pub failure_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AirbyteStreamStatus {
+    Started,
+    Running,
+    Complete,
+    Incomplete,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AirbyteStreamStatusTraceMessage {
+    pub stream_descriptor: AirbyteStreamDescriptor,
+    pub status: AirbyteStreamStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AirbyteTraceMessage {
+    #[serde(rename = "type")]
+    pub trace_type: AirbyteTraceType,
+    pub emitted_at: Option<f64>,
+    pub error: Option<AirbyteErrorTraceMessage>,
+    pub stream_status: Option<AirbyteStreamStatusTraceMessage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum AirbyteLogLevel {
+    Fatal,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AirbyteLogMessage {
+    pub level: AirbyteLogLevel,
+    pub message: String,
+}
+
+impl AirbyteMessage {
+    /// Parses a message coming off the queue. Besides full protocol messages, destinations write
+    /// records either wrapped in `_airbyte_data` (PubSub) or as the bare row (RabbitMQ), so both of
+    /// those are turned into `RECORD` messages. A message only counts as a protocol message when
+    /// it has the envelope its `type` names, e.g. `record` for a `RECORD`, since rows can have a
+    /// `type` column of their own.
+    pub fn parse(message: &str) -> Result<Self> {
+        let value: Value = serde_json::from_str(message)?;
+        let Value::Object(message_obj) = value else {
+            return Err(anyhow!("Expected message to be a JSON object"));
+        };
+        if let Some(Value::String(message_type)) = message_obj.get("type").cloned() {
+            let envelope = message_type.to_lowercase();
+            if matches!(envelope.as_str(), "record" | "state" | "trace" | "log")
+                && message_obj.contains_key(envelope.as_str())
+            {
+                return serde_json::from_value(Value::Object(message_obj)).map_err(|e| {
+                    anyhow!("Invalid Airbyte {} message. Error: {}", message_type, e)
+                });
+            }
+        }
+        let stream = match message_obj.get("_airbyte_stream") {
+            Some(Value::String(stream)) => Some(stream.clone()),
+            _ => None,
+        };
+        let emitted_at = message_obj
+            .get("_airbyte_emitted_at")
+            .and_then(|e| e.as_i64());
+        let (data, ingested) = match message_obj.get("_airbyte_data").cloned() {
+            Some(Value::Object(data)) => (
+                data,
+                message_obj.get(INGESTED_RECORD_FIELD) == Some(&Value::Bool(true)),
+            ),
+            _ => (message_obj, false),
+        };
+        Ok(AirbyteMessage::Record {
+            record: AirbyteRecordMessage {
+                stream,
+                namespace: None,
+                data,
+                emitted_at,
+                ingested,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse_record(message: Value) -> AirbyteRecordMessage {
+        match AirbyteMessage::parse(&message.to_string()).unwrap() {
+            AirbyteMessage::Record { record } => record,
+            other => panic!("Expected a record, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn takes_a_bare_row_for_a_record() {
+        let record = parse_record(json!({"id": 1, "title": "Dune"}));
+        assert_eq!(
+            Value::Object(record.data),
+            json!({"id": 1, "title": "Dune"})
+        );
+        assert_eq!(record.stream, None);
+        assert!(!record.ingested);
+    }
+
+    #[test]
+    fn unwraps_airbyte_data() {
+        let record = parse_record(json!({
+            "_airbyte_stream": "books",
+            "_airbyte_emitted_at": 1700000000000_i64,
+            "_airbyte_data": {"id": 1, "title": "Dune"},
+        }));
+        assert_eq!(
+            Value::Object(record.data),
+            json!({"id": 1, "title": "Dune"})
+        );
+        assert_eq!(record.stream.as_deref(), Some("books"));
+        assert_eq!(record.emitted_at, Some(1700000000000));
+    }
+
+    #[test]
+    fn keeps_a_row_with_its_own_type_column() {
+        let row = json!({"id": 1, "type": "record", "state": "draft"});
+        assert_eq!(Value::Object(parse_record(row.clone()).data), row);
+        let wrapped = parse_record(json!({"_airbyte_data": {"type": "STATE"}}));
+        assert_eq!(Value::Object(wrapped.data), json!({"type": "STATE"}));
+    }
+
+    #[test]
+    fn parses_protocol_messages_with_their_envelope() {
+        let message = json!({
+            "type": "RECORD",
+            "record": {"stream": "books", "data": {"id": 1}, "emitted_at": 1},
+        });
+        let record = parse_record(message);
+        assert_eq!(record.stream.as_deref(), Some("books"));
+        assert_eq!(Value::Object(record.data), json!({"id": 1}));
+        let state = json!({"type": "STATE", "state": {"type": "GLOBAL", "global": {"cursor": 2}}});
+        match AirbyteMessage::parse(&state.to_string()).unwrap() {
+            AirbyteMessage::State { state } => assert_eq!(state.checkpoint(), json!({"cursor": 2})),
+            other => panic!("Expected a state, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn marks_ingested_records_only_when_wrapped() {
+        let ingested =
+            parse_record(json!({"_airbyte_data": {"id": 1}, INGESTED_RECORD_FIELD: true}));
+        assert!(ingested.ingested);
+        assert!(!parse_record(json!({"id": 1, INGESTED_RECORD_FIELD: true})).ingested);
+    }
+
+    #[test]
+    fn rejects_messages_that_are_not_objects() {
+        assert!(AirbyteMessage::parse("[1, 2]").is_err());
+        assert!(AirbyteMessage::parse("not json").is_err());
+    }
+}
@@ -1,3 +1,4 @@
+pub mod airbyte;
 pub mod models;
 pub mod tasks;
 pub mod task_handoff;
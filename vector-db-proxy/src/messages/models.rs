@@ -4,16 +4,27 @@ use amqprs::channel::Channel;
 use crossbeam::channel::Sender;
 use google_cloud_pubsub::subscription::MessageStream;
 use mongodb::Database;
+use rdkafka::producer::FutureProducer;
+use redis::aio::MultiplexedConnection;
 use tokio::sync::{Mutex, RwLock};
+use tracing::Span;
 
-use crate::adaptors::gcp::models::pubsub_consume;
+use crate::adaptors::gcp::models::{pubsub_consume, pubsub_requeue};
+use crate::adaptors::kafka::models::{
+    kafka_ack, kafka_close, kafka_consume, kafka_requeue, KafkaConsumer,
+};
 use crate::adaptors::mongo::models::DataSources;
-use crate::adaptors::rabbitmq::models::rabbit_consume;
+use crate::adaptors::rabbitmq::models::{rabbit_consume, rabbit_requeue};
+use crate::adaptors::redis_streams::models::{
+    redis_ack, redis_consume, redis_release, redis_requeue,
+};
 
 #[derive(Clone, Copy, Debug)]
 pub enum MessageQueueProvider {
     PUBSUB,
     RABBITMQ,
+    KAFKA,
+    REDIS,
     UNKNOWN,
 }
 
@@ -22,6 +33,8 @@ impl From<String> for MessageQueueProvider {
         match value.as_str() {
             "google" => MessageQueueProvider::PUBSUB,
             "rabbitmq" => MessageQueueProvider::RABBITMQ,
+            "kafka" => MessageQueueProvider::KAFKA,
+            "redis" => MessageQueueProvider::REDIS,
             _ => MessageQueueProvider::UNKNOWN,
         }
     }
@@ -29,6 +42,52 @@ impl From<String> for MessageQueueProvider {
 pub enum QueueConnectionTypes {
     PubSub(Arc<Mutex<MessageStream>>),
     RabbitMQ(Channel),
+    Kafka(Arc<KafkaConsumer>, FutureProducer),
+    Redis(redis::Client, MultiplexedConnection),
+}
+
+/// Acknowledges the message a task was made from, once a worker has processed the task. Queues
+/// whose consumers acknowledge messages as soon as they are on the work queue send None.
+pub enum MessageAck {
+    Kafka {
+        consumer: Arc<KafkaConsumer>,
+        topic: String,
+        partition: i32,
+        offset: i64,
+    },
+    Redis {
+        connection: MultiplexedConnection,
+        stream: String,
+        group: String,
+        id: String,
+    },
+}
+
+impl MessageAck {
+    pub async fn ack(self) {
+        match self {
+            MessageAck::Kafka {
+                consumer,
+                topic,
+                partition,
+                offset,
+            } => kafka_ack(&consumer, topic.as_str(), partition, offset),
+            MessageAck::Redis {
+                connection,
+                stream,
+                group,
+                id,
+            } => redis_ack(connection, stream.as_str(), group.as_str(), id.as_str()).await,
+        }
+    }
+
+    /// Gives up on a message that was not processed, leaving it to be delivered again. Kafka
+    /// delivers it again once the partition is assigned anew, as its offset is not committed.
+    pub fn release(self) {
+        if let MessageAck::Redis { id, .. } = self {
+            redis_release(id.as_str());
+        }
+    }
 }
 
 impl Clone for QueueConnectionTypes {
@@ -40,6 +99,12 @@ impl Clone for QueueConnectionTypes {
             QueueConnectionTypes::RabbitMQ(channel) => {
                 QueueConnectionTypes::RabbitMQ(channel.clone())
             }
+            QueueConnectionTypes::Kafka(consumer, producer) => {
+                QueueConnectionTypes::Kafka(Arc::clone(consumer), producer.clone())
+            }
+            QueueConnectionTypes::Redis(client, connection) => {
+                QueueConnectionTypes::Redis(client.clone(), connection.clone())
+            }
         }
     }
 }
@@ -51,7 +116,13 @@ impl MessageQueue for QueueConnectionTypes {
         streaming_queue: Self::Queue,
         //vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
         mongo_client: Arc<RwLock<Database>>,
-        sender: Sender<(DataSources, Option<String>, String)>,
+        sender: Sender<(
+            DataSources,
+            Option<String>,
+            String,
+            Span,
+            Option<MessageAck>,
+        )>,
     ) {
         match streaming_queue {
             QueueConnectionTypes::PubSub(stream) => {
@@ -60,6 +131,27 @@ impl MessageQueue for QueueConnectionTypes {
             QueueConnectionTypes::RabbitMQ(channel) => {
                 rabbit_consume(&channel, mongo_client, sender).await;
             }
+            QueueConnectionTypes::Kafka(consumer, _) => {
+                kafka_consume(&consumer, mongo_client, sender).await;
+            }
+            QueueConnectionTypes::Redis(client, connection) => {
+                redis_consume(&client, &connection, mongo_client, sender).await;
+            }
+        }
+    }
+
+    async fn requeue(&self, tasks: Vec<(DataSources, Option<String>, String)>) -> usize {
+        match self {
+            QueueConnectionTypes::PubSub(_) => pubsub_requeue(tasks).await,
+            QueueConnectionTypes::RabbitMQ(channel) => rabbit_requeue(channel, tasks).await,
+            QueueConnectionTypes::Kafka(_, producer) => kafka_requeue(producer, tasks).await,
+            QueueConnectionTypes::Redis(_, connection) => redis_requeue(connection, tasks).await,
+        }
+    }
+
+    async fn close(&self) {
+        if let QueueConnectionTypes::Kafka(consumer, _) = self {
+            kafka_close(consumer);
         }
     }
 }
@@ -74,6 +166,17 @@ pub trait MessageQueue {
         streaming_queue: Self::Queue,
         //vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
         mongo_client: Arc<RwLock<Database>>,
-        sender: Sender<(DataSources, Option<String>, String)>,
+        sender: Sender<(
+            DataSources,
+            Option<String>,
+            String,
+            Span,
+            Option<MessageAck>,
+        )>,
     );
+    /// Puts tasks that were consumed but not processed back on the queue, returning how many
+    /// were requeued
+    async fn requeue(&self, tasks: Vec<(DataSources, Option<String>, String)>) -> usize;
+    /// Settles the work that was processed with the queue once the workers have stopped
+    async fn close(&self);
 }
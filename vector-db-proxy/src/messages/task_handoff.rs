@@ -1,14 +1,202 @@
 use crate::adaptors::mongo::models::DataSources;
-use crossbeam::channel::Sender;
+use crate::messages::models::MessageAck;
+use crate::utils::metrics::WORK_QUEUE_DEPTH;
+use crossbeam::channel::{Receiver, Select, Sender, TryRecvError};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::Span;
 
-/// Adds the incoming task to the execution Queue to be processes when threads are available
+#[derive(Default)]
+struct InFlightTasks {
+    // Set once shutdown has taken the unfinished tasks, after which workers take no more
+    taken: bool,
+    tasks: HashMap<usize, (DataSources, Option<String>, String)>,
+}
+
+// The task each worker is processing, so that it can be requeued if the worker does not finish it
+// before shutdown. Tasks that carry an acknowledgement are left out, as the message they came from
+// is still pending on the message queue.
+static IN_FLIGHT_TASKS: Lazy<Mutex<InFlightTasks>> =
+    Lazy::new(|| Mutex::new(InFlightTasks::default()));
+
+// File ingests that are still loading their file onto the work queue
+static PENDING_INGESTS: Lazy<Mutex<Vec<JoinHandle<()>>>> = Lazy::new(|| Mutex::new(vec![]));
+
+/// Adds the incoming task to the execution Queue to be processes when threads are available.
+/// The current span travels with the task so that its processing is traced under the message it
+/// came from, and so does the acknowledgement of that message.
 pub async fn send_task(
-    sender: Sender<(DataSources, Option<String>, String)>,
+    sender: Sender<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
     params: (DataSources, Option<String>, String),
+    ack: Option<MessageAck>,
 ) {
     let (dataset_id, stream_config_key, message) = params;
     // Instantiate a new instance of the MyQueue
     let _ = sender
-        .send((dataset_id, stream_config_key, message))
+        .send((dataset_id, stream_config_key, message, Span::current(), ack))
         .map_err(|err| log::error!("An error occurred while sending task to channel: {}", err));
+    WORK_QUEUE_DEPTH.set(sender.len() as i64);
+}
+
+/// Takes the next task off the work queue and marks it in flight for the worker in one step, so
+/// that shutdown can not take the unfinished tasks between the two and miss it. Returns None once
+/// the work queue is empty with every sender gone, or once shutdown has taken the unfinished tasks.
+pub fn receive_task(
+    worker: usize,
+    receiver: &Receiver<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
+) -> Option<(
+    DataSources,
+    Option<String>,
+    String,
+    Span,
+    Option<MessageAck>,
+)> {
+    loop {
+        // Waits for a task without taking it, as the lock can not be held while blocking
+        let mut select = Select::new();
+        select.recv(receiver);
+        select.ready();
+        let mut in_flight_tasks = IN_FLIGHT_TASKS.lock().ok()?;
+        if in_flight_tasks.taken {
+            return None;
+        }
+        match receiver.try_recv() {
+            Ok(task) => {
+                let (datasource, stream_config_key, message, _, ack) = &task;
+                if ack.is_none() {
+                    in_flight_tasks.tasks.insert(
+                        worker,
+                        (
+                            datasource.clone(),
+                            stream_config_key.clone(),
+                            message.clone(),
+                        ),
+                    );
+                }
+                return Some(task);
+            }
+            // Another worker took it first
+            Err(TryRecvError::Empty) => continue,
+            Err(TryRecvError::Disconnected) => return None,
+        }
+    }
+}
+
+pub fn finish_task(worker: usize) {
+    if let Ok(mut in_flight_tasks) = IN_FLIGHT_TASKS.lock() {
+        in_flight_tasks.tasks.remove(&worker);
+    }
+}
+
+/// Takes the tasks that are still queued and those that workers have started but not finished,
+/// to be requeued. Workers take no more tasks afterwards. Tasks that carry an acknowledgement are
+/// dropped instead, as the Kafka or Redis message they came from stays pending and is delivered
+/// again, so requeueing them would have them processed twice.
+pub fn take_unfinished_tasks(
+    receiver: &Receiver<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
+) -> Vec<(DataSources, Option<String>, String)> {
+    let Ok(mut in_flight_tasks) = IN_FLIGHT_TASKS.lock() else {
+        return vec![];
+    };
+    in_flight_tasks.taken = true;
+    let mut unfinished_tasks: Vec<(DataSources, Option<String>, String)> = receiver
+        .try_iter()
+        .filter_map(|(datasource, stream_config_key, message, _, ack)| {
+            ack.is_none()
+                .then_some((datasource, stream_config_key, message))
+        })
+        .collect();
+    unfinished_tasks.extend(in_flight_tasks.tasks.drain().map(|(_, task)| task));
+    unfinished_tasks
+}
+
+/// Keeps hold of a file ingest, so that shutdown waits for its file to reach the work queue
+pub fn track_ingest(handle: JoinHandle<()>) {
+    if let Ok(mut pending_ingests) = PENDING_INGESTS.lock() {
+        pending_ingests.retain(|handle| !handle.is_finished());
+        pending_ingests.push(handle);
+    }
+}
+
+/// Takes the file ingests that have not finished yet
+pub fn take_pending_ingests() -> Vec<JoinHandle<()>> {
+    match PENDING_INGESTS.lock() {
+        Ok(mut pending_ingests) => pending_ingests
+            .drain(..)
+            .filter(|handle| !handle.is_finished())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+// Sender of the work queue for tasks that do not come off the message queue. It is taken away on
+// shutdown, so that the workers stop once the work queue is drained.
+static WORK_QUEUE: Lazy<
+    Mutex<
+        Option<
+            Sender<(
+                DataSources,
+                Option<String>,
+                String,
+                Span,
+                Option<MessageAck>,
+            )>,
+        >,
+    >,
+> = Lazy::new(|| Mutex::new(None));
+
+pub fn open_work_queue(
+    sender: Sender<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
+) {
+    if let Ok(mut work_queue) = WORK_QUEUE.lock() {
+        *work_queue = Some(sender);
+    }
+}
+
+pub fn close_work_queue() {
+    if let Ok(mut work_queue) = WORK_QUEUE.lock() {
+        work_queue.take();
+    }
+}
+
+/// A sender for the work queue, or None once shutdown has started
+pub fn work_queue() -> Option<
+    Sender<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
+> {
+    WORK_QUEUE
+        .lock()
+        .ok()
+        .and_then(|work_queue| work_queue.clone())
 }
@@ -1,22 +1,27 @@
 use crate::adaptors::gcp::models::PubSubConnect;
-use crate::adaptors::mongo::models::{DataSources, UnstructuredChunkingConfig};
+use crate::adaptors::kafka::models::KafkaConnect;
+use crate::adaptors::mongo::models::{DataSources, Model, UnstructuredChunkingConfig};
 use crate::adaptors::mongo::queries::{get_datasource, get_model};
 use crate::adaptors::rabbitmq::models::RabbitConnect;
+use crate::adaptors::redis_streams::models::RedisConnect;
+use crate::data::models::SourceFile;
+use crate::data::quotas::{check_datasource_quota, record_usage, UsageRequest};
 use crate::data::unstructuredio::apis::chunk_text;
 use crate::embeddings::utils::embed_bulk_insert_unstructured_response;
 use crate::init::env_variables::GLOBAL_DATA;
-use crate::messages::models::{MessageQueueConnection, MessageQueueProvider, QueueConnectionTypes};
+use crate::messages::models::{
+    MessageAck, MessageQueueConnection, MessageQueueProvider, QueueConnectionTypes,
+};
 use crate::messages::task_handoff::send_task;
 use crate::utils::file_operations;
-use crate::utils::file_operations::determine_file_type;
-use crate::utils::webhook::send_webapp_embed_ready;
+use crate::utils::webhook::{dispatch_event, WebhookEvent};
 use crate::vector_databases::models::SearchType;
 use crossbeam::channel::Sender;
 use mongodb::Database;
-use serde_json::Value;
-use std::io::Cursor;
+use serde_json::{json, Value};
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tracing::Span;
 
 pub async fn get_message_queue(
     message_queue_provider: MessageQueueProvider,
@@ -41,12 +46,32 @@ pub async fn get_message_queue(
             };
             pubsub_connection.connect().await.unwrap()
         }
+        MessageQueueProvider::KAFKA => {
+            log::info!("Using Kafka as the streaming Queue!");
+            let kafka_connection = KafkaConnect {
+                bootstrap_servers: global_data.kafka_bootstrap_servers.clone(),
+                topic: global_data.kafka_topic.clone(),
+                group_id: global_data.kafka_group_id.clone(),
+            };
+            kafka_connection.connect().await.unwrap()
+        }
+        MessageQueueProvider::REDIS => {
+            log::info!("Using Redis Streams as the streaming Queue!");
+            let redis_connection = RedisConnect {
+                host: global_data.redis_host.clone(),
+                port: global_data.redis_port,
+                password: global_data.redis_password.clone(),
+            };
+            redis_connection.connect().await.unwrap()
+        }
         MessageQueueProvider::UNKNOWN => {
             panic!("Unknown message Queue provider specified. Aborting application!");
         }
     }
 }
 
+/// Embeds the files a message points at, or hands an Airbyte message to the work queue. The
+/// message is acknowledged once it is done with, which for Airbyte messages is left to the worker.
 pub async fn process_message(
     message_string: String,
     stream_type: Option<String>,
@@ -54,10 +79,16 @@ pub async fn process_message(
     stream_config_key: Option<String>,
     //vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
     mongo_client: Arc<RwLock<Database>>,
-    sender: Sender<(DataSources, Option<String>, String)>,
+    sender: Sender<(
+        DataSources,
+        Option<String>,
+        String,
+        Span,
+        Option<MessageAck>,
+    )>,
+    mut ack: Option<MessageAck>,
 ) {
     let mongodb_connection = mongo_client.read().await;
-    let global_data = GLOBAL_DATA.read().await.clone();
     log::debug!("Datasource ID: {}", datasource_id);
     match get_datasource(&mongodb_connection, datasource_id).await {
         Ok(datasource) => {
@@ -74,50 +105,16 @@ pub async fn process_message(
                             )
                             .await
                             {
-                                Some((_, file, file_path)) => {
-                                    let buffer = Cursor::new(file);
-                                    let file_type = determine_file_type(file_path.as_str());
-                                    let unstructuredio_url = global_data.unstructuredio_url;
-                                    let unstructuredio_api_key =
-                                        Some(global_data.unstructuredio_api_key)
-                                            .filter(|s| !s.is_empty());
-                                    let chunking_strategy: Option<UnstructuredChunkingConfig> =
-                                        ds.clone().chunking_config;
-                                    let handle = tokio::task::spawn_blocking(move || {
-                                        let response = chunk_text(
-                                            unstructuredio_url,
-                                            unstructuredio_api_key,
-                                            buffer,
-                                            Some(file_path),
-                                            chunking_strategy,
-                                            Some(file_type),
-                                        );
-                                        response
-                                    });
-                                    // dynamically get user's chunking strategy of choice from the database
-                                    let model_obj_clone = model_parameters.clone();
-                                    match handle.await.unwrap() {
-                                        Ok(documents) => {
-                                            embed_bulk_insert_unstructured_response(
-                                                documents,
-                                                ds,
-                                                //vector_database_client.clone(),
-                                                mongo_client.clone(),
-                                                model_obj_clone,
-                                                None,
-                                                SearchType::default(),
-                                            )
-                                            .await;
-                                        }
-                                        Err(e) => log::error!(
-                                            "An error occurred while retrieving 
-                                         results from Unstructured IO response. Error : {}",
-                                            e
-                                        ),
+                                Some(files) => {
+                                    for file in files {
+                                        embed_file(
+                                            file,
+                                            ds.clone(),
+                                            model_parameters.clone(),
+                                            Arc::clone(&mongo_client),
+                                        )
+                                        .await;
                                     }
-                                    let _ = send_webapp_embed_ready(datasource_id)
-                                        .await
-                                        .map_err(|e| log::error!("{}", e));
                                 }
                                 None => {
                                     log::warn!(
@@ -128,8 +125,12 @@ pub async fn process_message(
                         }
                     } else {
                         // This is where data is coming from airbyte rather than a direct file upload
-                        let _ = send_task(sender, (ds.clone(), stream_config_key, message_string))
-                            .await;
+                        let _ = send_task(
+                            sender,
+                            (ds.clone(), stream_config_key, message_string),
+                            ack.take(),
+                        )
+                        .await;
                     }
                 }
             } else {
@@ -140,7 +141,120 @@ pub async fn process_message(
             }
         }
         Err(e) => {
-            log::error!("Could not find associated datasource: {}", e)
+            log::error!("Could not find associated datasource: {}", e);
+            // Left unacknowledged to be delivered again once Mongo answers
+            if let Some(ack) = ack {
+                ack.release();
+            }
+            return;
         }
     }
+    if let Some(ack) = ack {
+        ack.ack().await;
+    }
+}
+
+/// Chunks a file read from a source and embeds the chunks, reporting progress through webhooks
+async fn embed_file(
+    file: SourceFile,
+    ds: DataSources,
+    model_parameters: Model,
+    mongo_client: Arc<RwLock<Database>>,
+) {
+    let mongodb_connection = mongo_client.read().await;
+    let global_data = GLOBAL_DATA.read().await.clone();
+    let datasource_id = ds.id.to_string();
+    let SourceFile {
+        file_type,
+        content,
+        path: file_path,
+        in_archive,
+    } = file;
+    let _ = dispatch_event(
+        &mongodb_connection,
+        datasource_id.as_str(),
+        WebhookEvent::EmbedStarted,
+        json!({"filePath": file_path}),
+    )
+    .await
+    .map_err(|e| log::error!("{}", e));
+    let unstructuredio_url = global_data.unstructuredio_url;
+    let unstructuredio_api_key = Some(global_data.unstructuredio_api_key).filter(|s| !s.is_empty());
+    let chunking_strategy: Option<UnstructuredChunkingConfig> = ds.clone().chunking_config;
+    let file_name = file_path.clone();
+    let handle = tokio::task::spawn_blocking(move || {
+        let response = chunk_text(
+            unstructuredio_url,
+            unstructuredio_api_key,
+            content,
+            Some(file_name),
+            chunking_strategy,
+            Some(file_type),
+        );
+        response
+    });
+    // dynamically get user's chunking strategy of choice from the database
+    let model_obj_clone = model_parameters.clone();
+    let (event, payload) = match handle.await.unwrap() {
+        Ok(mut documents) => {
+            // Unstructured only keeps the base name, while entries are told apart by their path
+            // inside the archive
+            if in_archive {
+                for document in documents.iter_mut() {
+                    document.metadata.filename = file_path.clone();
+                }
+            }
+            let number_of_documents = documents.len();
+            let texts: Vec<&str> = documents.iter().map(|d| d.text.as_str()).collect();
+            let usage = UsageRequest::embedding(&texts, model_obj_clone.embeddingLength as usize);
+            match check_datasource_quota(&mongodb_connection, &ds, &usage).await {
+                Some(quota_exceeded) => {
+                    log::warn!(
+                        "Not embedding file of datasource {}. Error: {}",
+                        datasource_id,
+                        quota_exceeded
+                    );
+                    (
+                        WebhookEvent::EmbedFailed,
+                        json!({
+                            "filePath": file_path,
+                            "lastError": quota_exceeded.to_string()
+                        }),
+                    )
+                }
+                None => {
+                    let team_id = ds.team_id.to_string();
+                    embed_bulk_insert_unstructured_response(
+                        documents,
+                        ds,
+                        //vector_database_client.clone(),
+                        mongo_client.clone(),
+                        model_obj_clone,
+                        None,
+                        SearchType::default(),
+                    )
+                    .await;
+                    record_usage(&mongodb_connection, team_id.as_str(), &usage).await;
+                    (
+                        WebhookEvent::EmbedCompleted,
+                        json!({"filePath": file_path, "chunks": number_of_documents}),
+                    )
+                }
+            }
+        }
+        Err(e) => {
+            log::error!(
+                "An error occurred while retrieving 
+                                         results from Unstructured IO response. Error : {}",
+                e
+            );
+            (
+                WebhookEvent::EmbedFailed,
+                json!({"filePath": file_path, "lastError": e.to_string()}),
+            )
+        }
+    };
+    let _ = dispatch_event(&mongodb_connection, datasource_id.as_str(), event, payload)
+        .await
+        .map_err(|e| log::error!("{}", e));
 }
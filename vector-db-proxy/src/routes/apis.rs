@@ -3,28 +3,54 @@ use actix_web::http::header::ContentType;
 use actix_web::web::Data;
 use actix_web::*;
 use actix_web_lab::extract::Path;
+use actix_web_lab::sse;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::adaptors::mongo::error::Result;
 use crate::routes;
+use crate::utils;
 
 use crate::adaptors::mongo::client::start_mongo_connection;
 use crate::adaptors::mongo::models::Model;
-use crate::adaptors::mongo::queries::{get_datasource, get_model, get_team_datasources};
-use crate::routes::helpers::format_error_message;
-use crate::routes::models::CollectionStorageSizeResponse;
+use crate::adaptors::mongo::queries::{get_datasource, get_latest_sync_run, get_model};
+use crate::data::models::{ImportRequest, IngestFile, IngestRequest};
+use crate::data::point_import::import_points;
+use crate::data::progress;
+use crate::data::progress::{ProgressEvent, ProgressEventType};
+use crate::data::quotas::{
+    check_quota, record_usage, team_storage_size, QuotaExceeded, UsageRequest,
+};
+use crate::data::sync_runs::{complete_sync_run, start_records};
+use crate::messages::airbyte::INGESTED_RECORD_FIELD;
+use crate::messages::task_handoff::{send_task, track_ingest, work_queue};
+use crate::messages::tasks::process_message;
+use crate::routes::auth::AuthenticatedTeam;
+use crate::routes::helpers::{
+    forbidden_collection_response, format_error_message, quota_exceeded_response,
+    team_owns_collection,
+};
+use crate::utils::file_operations::is_team_object;
+use crate::utils::models::FileSources;
 use crate::vector_databases::error::VectorDatabaseError;
 use crate::vector_databases::helpers::check_byo_vector_database;
+use crate::vector_databases::migration::migrate_collection;
 use crate::vector_databases::models::{
-    CollectionCreate, Point, Region, SearchRequest, SearchType, VectorDatabaseStatus,
+    CollectionCreate, MigrationRequest, Point, Region, SearchRequest, SearchType,
+    VectorDatabaseStatus,
 };
 use crate::vector_databases::vector_database::{default_vector_db_client, VectorDatabase};
+use mongodb::Database;
 use routes::models::{ResponseBody, Status};
 use serde_json::json;
-use std::vec;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::RwLock;
+use tracing::Instrument;
 use wherr::wherr;
 
+// Comment lines are sent on idle event streams so that proxies do not close them
+const SSE_KEEP_ALIVE_SECS: u64 = 15;
+
 ///
 ///
 /// # Arguments
@@ -44,6 +70,44 @@ pub async fn health_check() -> Result<impl Responder> {
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Liveness probe, answering as long as the HTTP server is running. Served outside of `/api/v1`
+/// so that it can be probed without credentials.
+#[wherr]
+#[get("/livez")]
+pub async fn liveness() -> Result<impl Responder> {
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .json(json!({"status": "alive"})))
+}
+
+/// Readiness probe, checking Mongo, the message queue consumer, the default vector database and
+/// the embedding backends. Answers 503 with the state of each dependency when one of them is
+/// unhealthy or the proxy is shutting down. OpenAI is reported without affecting the answer.
+#[wherr]
+#[get("/readyz")]
+pub async fn readiness(mongo_client: Data<Arc<RwLock<Database>>>) -> Result<impl Responder> {
+    let readiness = utils::health::readiness(&*mongo_client.read().await).await;
+    let response = if readiness.ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    }
+    .content_type(ContentType::json())
+    .json(json!(readiness));
+    Ok(response)
+}
+
+/// Exposes the operational metrics of the proxy in the Prometheus text format. Served outside of
+/// `/api/v1` so that it can be scraped without credentials.
+#[wherr]
+#[get("/metrics")]
+pub async fn metrics() -> Result<impl Responder> {
+    let body = utils::metrics::render()?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
+
 ///
 ///
 /// # Arguments
@@ -59,9 +123,15 @@ pub async fn health_check() -> Result<impl Responder> {
 /// ```
 #[wherr]
 #[get("/list-collections/{collection_name}")]
-pub async fn list_collections(Path(collection_name): Path<String>) -> Result<impl Responder> {
+pub async fn list_collections(
+    team: AuthenticatedTeam,
+    Path(collection_name): Path<String>,
+) -> Result<impl Responder> {
     let datasource_id = collection_name.clone();
     let mongodb_connection = start_mongo_connection().await?;
+    if !team_owns_collection(&mongodb_connection, &team, datasource_id.as_str()).await? {
+        return Ok(forbidden_collection_response(datasource_id.as_str()));
+    }
     match get_datasource(&mongodb_connection, datasource_id.as_str()).await {
         Ok(option) => match option {
             Some(datasource) => {
@@ -131,10 +201,14 @@ pub async fn list_collections(Path(collection_name): Path<String>) -> Result<imp
 #[get("/check-collection-exists/{collection_name}")]
 pub async fn check_collection_exists(
     //app_data: Data<Arc<RwLock<dyn VectorDatabase>>>,
+    team: AuthenticatedTeam,
     Path(collection_name): Path<String>, // Datasource ID
 ) -> Result<HttpResponse> {
     let collection_id = collection_name.clone();
     let mongodb_connection = start_mongo_connection().await?;
+    if !team_owns_collection(&mongodb_connection, &team, collection_id.as_str()).await? {
+        return Ok(forbidden_collection_response(collection_id.as_str()));
+    }
     let mut search_request = SearchRequest::new(SearchType::Collection, collection_id.clone());
     match get_datasource(&mongodb_connection, collection_id.as_str()).await {
         Ok(option) => match option {
@@ -245,10 +319,14 @@ pub async fn check_collection_exists(
 #[post("/create-collection/")]
 pub async fn create_collection(
     //app_data: Data<Arc<RwLock<dyn VectorDatabase>>>,
+    team: AuthenticatedTeam,
     data: web::Json<CollectionCreate>,
 ) -> Result<HttpResponse> {
     let collection_id = data.clone().collection_name;
     let mongodb_connection = start_mongo_connection().await?;
+    if !team_owns_collection(&mongodb_connection, &team, collection_id.as_str()).await? {
+        return Ok(forbidden_collection_response(collection_id.as_str()));
+    }
     match get_datasource(&mongodb_connection, collection_id.as_str()).await {
         Ok(option) => match option {
             Some(datasource) => {
@@ -360,9 +438,18 @@ pub async fn create_collection(
 #[post("/upsert-data-point/{collection_name}")]
 pub async fn upsert_data_point_to_collection(
     app_data: Data<Arc<RwLock<dyn VectorDatabase>>>,
+    team: AuthenticatedTeam,
     Path(collection_name): Path<String>,
     data: web::Json<Point>,
 ) -> Result<impl Responder> {
+    let mongodb_connection = start_mongo_connection().await?;
+    if !team_owns_collection(&mongodb_connection, &team, collection_name.as_str()).await? {
+        return Ok(forbidden_collection_response(collection_name.as_str()));
+    }
+    let usage = UsageRequest::storage(1, data.vector.len());
+    if let Some(quota_exceeded) = check_quota(&mongodb_connection, &team.team_id, &usage).await {
+        return Ok(quota_exceeded_response(quota_exceeded));
+    }
     let vector_database = app_data.get_ref().clone();
     let vector_database_client = vector_database.read().await;
     let search_request = SearchRequest::new(SearchType::Collection, collection_name.clone());
@@ -371,6 +458,7 @@ pub async fn upsert_data_point_to_collection(
         .await?;
     match upsert_results {
         VectorDatabaseStatus::Ok => {
+            record_usage(&mongodb_connection, &team.team_id, &usage).await;
             Ok(HttpResponse::Ok()
                 .content_type(ContentType::json())
                 .json(json!(ResponseBody {
@@ -441,22 +529,32 @@ pub async fn upsert_data_point_to_collection(
 #[post("/bulk-upsert-data/{collection_name}")]
 pub async fn bulk_upsert_data_to_collection(
     app_data: Data<Arc<RwLock<dyn VectorDatabase>>>,
+    team: AuthenticatedTeam,
     Path(collection_name): Path<String>,
     data: web::Json<Vec<Point>>,
 ) -> Result<impl Responder> {
+    let mongodb_connection = start_mongo_connection().await?;
+    if !team_owns_collection(&mongodb_connection, &team, collection_name.as_str()).await? {
+        return Ok(forbidden_collection_response(collection_name.as_str()));
+    }
     let vector_database = app_data.get_ref().clone();
     let vector_database_client = vector_database.read().await;
-    let mongodb_connection = start_mongo_connection().await?;
     let collection_name_clone_2 = collection_name.clone();
     let _model_parameters: Model = get_model(&mongodb_connection, collection_name_clone_2.as_str())
         .await?
         .unwrap();
+    // Every point of a collection has the same number of dimensions
+    let usage = UsageRequest::storage(data.len(), data.first().map_or(0, |p| p.vector.len()));
+    if let Some(quota_exceeded) = check_quota(&mongodb_connection, &team.team_id, &usage).await {
+        return Ok(quota_exceeded_response(quota_exceeded));
+    }
     let search_request = SearchRequest::new(SearchType::Collection, collection_name.clone());
     let bulk_upsert_results = vector_database_client
         .bulk_insert_points(search_request, data.0)
         .await?;
     match bulk_upsert_results {
         VectorDatabaseStatus::Ok => {
+            record_usage(&mongodb_connection, &team.team_id, &usage).await;
             Ok(HttpResponse::Ok()
                 .content_type(ContentType::json())
                 .json(json!(ResponseBody {
@@ -528,9 +626,17 @@ pub async fn bulk_upsert_data_to_collection(
 #[get("/scroll/{dataset_id}")]
 pub async fn scroll_data(
     app_data: Data<Arc<RwLock<dyn VectorDatabase>>>,
-    Path(_dataset_id): Path<String>,
+    team: AuthenticatedTeam,
+    Path(dataset_id): Path<String>,
     data: web::Query<SearchRequest>,
 ) -> Result<impl Responder> {
+    let mongodb_connection = start_mongo_connection().await?;
+    // The collection scrolled is the one named in the query, so both have to belong to the team
+    for collection_id in [&dataset_id, &data.collection] {
+        if !team_owns_collection(&mongodb_connection, &team, collection_id.as_str()).await? {
+            return Ok(forbidden_collection_response(collection_id.as_str()));
+        }
+    }
     let vector_database = app_data.get_ref().clone();
     let vector_database_client = vector_database.read().await;
     let results = vector_database_client.scroll_points(data.0).await.unwrap();
@@ -547,10 +653,14 @@ pub async fn scroll_data(
 #[delete("/collection/{dataset_id}")]
 pub async fn delete_collection(
     //app_data: Data<Arc<RwLock<dyn VectorDatabase>>>,
+    team: AuthenticatedTeam,
     Path(dataset_id): Path<String>,
 ) -> Result<impl Responder> {
     let collection_id = dataset_id.clone();
     let mongodb_connection = start_mongo_connection().await?;
+    if !team_owns_collection(&mongodb_connection, &team, collection_id.as_str()).await? {
+        return Ok(forbidden_collection_response(collection_id.as_str()));
+    }
     match get_datasource(&mongodb_connection, collection_id.as_str()).await {
         Ok(option) => match option {
             Some(datasource) => {
@@ -645,10 +755,14 @@ pub async fn delete_collection(
 #[get("/collection-info/{dataset_id}")]
 pub async fn get_collection_info(
     //app_data: Data<Arc<RwLock<dyn VectorDatabase>>>,
+    team: AuthenticatedTeam,
     Path(dataset_id): Path<String>,
 ) -> Result<impl Responder> {
     let collection_id = dataset_id.clone();
     let mongodb_connection = start_mongo_connection().await?;
+    if !team_owns_collection(&mongodb_connection, &team, collection_id.as_str()).await? {
+        return Ok(forbidden_collection_response(collection_id.as_str()));
+    }
     match get_datasource(&mongodb_connection, collection_id.as_str()).await {
         Ok(option) => match option {
             Some(datasource) => {
@@ -731,63 +845,515 @@ pub async fn get_collection_info(
 
 #[wherr]
 #[get("/storage-size/{team_id}")]
-pub async fn get_storage_size(Path(team_id): Path<String>) -> Result<impl Responder> {
-    let mut collection_size_response = CollectionStorageSizeResponse {
-        list_of_datasources: vec![],
-        total_size: 0.0,
-        total_points: 0,
-    };
-    let team_id = team_id.clone();
+pub async fn get_storage_size(
+    team: AuthenticatedTeam,
+    Path(team_id): Path<String>,
+) -> Result<impl Responder> {
+    if team.team_id != team_id {
+        return Ok(HttpResponse::Forbidden()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::Failure,
+                data: None,
+                error_message: Some(json!({
+                    "errorMessage": format!("Not allowed to read the storage size of team: '{}'",
+                        team_id)
+                }))
+            })));
+    }
     let mongodb_connection = start_mongo_connection().await?;
+    let collection_size_response = team_storage_size(&mongodb_connection, team_id.as_str()).await?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .json(json!(ResponseBody {
+            status: Status::Success,
+            data: Some(json!(collection_size_response)),
+            error_message: None
+        })))
+}
 
-    let list_of_team_datasources =
-        get_team_datasources(&mongodb_connection, team_id.as_str()).await?;
-    for datasource in list_of_team_datasources {
-        let vector_database_client =
-            check_byo_vector_database(datasource.clone(), &mongodb_connection)
-                .await
-                .unwrap_or(default_vector_db_client().await);
-        let vector_database_client = vector_database_client.read().await;
-        let model_result = get_model(&mongodb_connection, datasource.id.to_string().as_str()).await;
-        match model_result {
-            Ok(Some(embedding_model)) => {
-                let mut search_request =
-                    SearchRequest::new(SearchType::Collection, datasource.id.to_string());
-                search_request.byo_vector_db = Some(true);
-                search_request.collection = datasource
-                    .collection_name
-                    .map_or(datasource.id.to_string(), |d| d);
-                search_request.namespace = datasource.namespace;
-                if let Ok(Some(collection_storage_info)) = vector_database_client
-                    .get_storage_size(search_request, embedding_model.embeddingLength as usize)
-                    .await
-                {
-                    collection_size_response.total_points +=
-                        collection_storage_info.points_count.unwrap();
-                    collection_size_response.total_size += collection_storage_info.size.unwrap();
-                    collection_size_response
-                        .list_of_datasources
-                        .push(collection_storage_info);
+#[wherr]
+#[post("/import/{dataset_id}")]
+pub async fn import_points_to_collection(
+    team: AuthenticatedTeam,
+    Path(dataset_id): Path<String>,
+    data: web::Json<ImportRequest>,
+) -> Result<impl Responder> {
+    let collection_id = dataset_id.clone();
+    let mongodb_connection = start_mongo_connection().await?;
+    if !team_owns_collection(&mongodb_connection, &team, collection_id.as_str()).await? {
+        return Ok(forbidden_collection_response(collection_id.as_str()));
+    }
+    match get_datasource(&mongodb_connection, collection_id.as_str()).await {
+        Ok(option) => {
+            match option {
+                Some(datasource) => {
+                    let vector_database_client =
+                        check_byo_vector_database(datasource.clone(), &mongodb_connection)
+                            .await
+                            .unwrap_or(default_vector_db_client().await);
+                    let import_result = import_points(
+                        &mongodb_connection,
+                        datasource,
+                        vector_database_client,
+                        data.0,
+                    )
+                    .await;
+                    match import_result {
+                        Ok(summary) => Ok(HttpResponse::Ok()
+                            .content_type(ContentType::json())
+                            .json(json!(ResponseBody {
+                                status: Status::Success,
+                                data: Some(json!(summary)),
+                                error_message: None
+                            }))),
+                        Err(e) => match e.downcast::<QuotaExceeded>() {
+                            Ok(quota_exceeded) => Ok(quota_exceeded_response(quota_exceeded)),
+                            Err(e) => Ok(HttpResponse::BadRequest()
+                                .content_type(ContentType::json())
+                                .json(json!(ResponseBody {
+                                    status: Status::Failure,
+                                    data: None,
+                                    error_message: Some(json!({
+                                        "errorMessage": format!("An error occurred while \
+                                        importing points. Error: {}", e)
+                                    }))
+                                }))),
+                        },
+                    }
+                }
+                None => Ok(HttpResponse::NotFound()
+                    .content_type(ContentType::json())
+                    .json(json!(ResponseBody {
+                        status: Status::Failure,
+                        data: None,
+                        error_message: Some(json!({
+                            "errorMessage": format!("The datasource: '{}' does not exists in the \
+                            database",
+                                collection_id)
+                        }))
+                    }))),
+            }
+        }
+        Err(e) => Ok(HttpResponse::BadRequest()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::Failure,
+                data: None,
+                error_message: Some(json!({
+                    "errorMessage": format!("Could not import points into collection: '{}' \
+                    due to an unknown error. Error: {}", collection_id, e)
+                }))
+            }))),
+    }
+}
+
+#[wherr]
+#[post("/migrate/{dataset_id}")]
+pub async fn migrate_collection_to_vector_db(
+    team: AuthenticatedTeam,
+    Path(dataset_id): Path<String>,
+    data: web::Json<MigrationRequest>,
+) -> Result<impl Responder> {
+    let collection_id = dataset_id.clone();
+    let mongodb_connection = start_mongo_connection().await?;
+    if !team_owns_collection(&mongodb_connection, &team, collection_id.as_str()).await? {
+        return Ok(forbidden_collection_response(collection_id.as_str()));
+    }
+    match get_datasource(&mongodb_connection, collection_id.as_str()).await {
+        Ok(option) => {
+            match option {
+                Some(datasource) => {
+                    let migration_result =
+                        migrate_collection(&mongodb_connection, datasource, data.0).await;
+                    match migration_result {
+                        Ok(summary) => Ok(HttpResponse::Ok()
+                            .content_type(ContentType::json())
+                            .json(json!(ResponseBody {
+                                status: Status::Success,
+                                data: Some(json!(summary)),
+                                error_message: None
+                            }))),
+                        Err(e) => match e.downcast::<QuotaExceeded>() {
+                            Ok(quota_exceeded) => Ok(quota_exceeded_response(quota_exceeded)),
+                            Err(e) => Ok(HttpResponse::InternalServerError()
+                                .content_type(ContentType::json())
+                                .json(json!(ResponseBody {
+                                    status: Status::Failure,
+                                    data: None,
+                                    error_message: Some(json!({
+                                        "errorMessage": format!("An error occurred while \
+                                        migrating collection. Error: {}", e)
+                                    }))
+                                }))),
+                        },
+                    }
+                }
+                None => Ok(HttpResponse::NotFound()
+                    .content_type(ContentType::json())
+                    .json(json!(ResponseBody {
+                        status: Status::Failure,
+                        data: None,
+                        error_message: Some(json!({
+                            "errorMessage": format!("The datasource: '{}' does not exists in the \
+                            database",
+                                collection_id)
+                        }))
+                    }))),
+            }
+        }
+        Err(e) => Ok(HttpResponse::BadRequest()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::Failure,
+                data: None,
+                error_message: Some(json!({
+                    "errorMessage": format!("Could not migrate collection: '{}' due to an \
+                    unknown error. Error: {}", collection_id, e)
+                }))
+            }))),
+    }
+}
+
+#[wherr]
+#[post("/sync-complete/{dataset_id}")]
+pub async fn complete_datasource_sync(
+    team: AuthenticatedTeam,
+    Path(dataset_id): Path<String>,
+) -> Result<impl Responder> {
+    let collection_id = dataset_id.clone();
+    let mongodb_connection = start_mongo_connection().await?;
+    if !team_owns_collection(&mongodb_connection, &team, collection_id.as_str()).await? {
+        return Ok(forbidden_collection_response(collection_id.as_str()));
+    }
+    match get_datasource(&mongodb_connection, collection_id.as_str()).await {
+        Ok(option) => match option {
+            Some(datasource) => {
+                // Stale points of overwrite syncs are removed once the run has been drained
+                let sync_result = complete_sync_run(&mongodb_connection, datasource).await;
+                match sync_result {
+                    Ok(()) => Ok(HttpResponse::Ok()
+                        .content_type(ContentType::json())
+                        .json(json!(ResponseBody {
+                            status: Status::Success,
+                            data: None,
+                            error_message: None
+                        }))),
+                    Err(e) => Ok(HttpResponse::InternalServerError()
+                        .content_type(ContentType::json())
+                        .json(json!(ResponseBody {
+                            status: Status::Failure,
+                            data: None,
+                            error_message: Some(json!({
+                                "errorMessage": format!("An error occurred while completing \
+                                sync. Error: {}", e)
+                            }))
+                        }))),
                 }
             }
-            Ok(None) => {
-                continue;
+            None => Ok(HttpResponse::NotFound()
+                .content_type(ContentType::json())
+                .json(json!(ResponseBody {
+                    status: Status::Failure,
+                    data: None,
+                    error_message: Some(json!({
+                        "errorMessage": format!("The datasource: '{}' does not exists in the \
+                        database",
+                            collection_id)
+                    }))
+                }))),
+        },
+        Err(e) => Ok(HttpResponse::BadRequest()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::Failure,
+                data: None,
+                error_message: Some(json!({
+                    "errorMessage": format!("Could not complete sync for datasource: '{}' due \
+                    to an unknown error. Error: {}", collection_id, e)
+                }))
+            }))),
+    }
+}
+
+/// Why a file can not be ingested, if it can not. Files on the proxy's own disk are not for API
+/// callers to name, and objects in the buckets it reads from must belong to the team.
+fn ingest_file_error(file: &IngestFile, team_id: &str, datasource_id: &str) -> Option<String> {
+    let object_field = match FileSources::from(file.source.clone()) {
+        FileSources::GCS => "filename",
+        FileSources::S3 => "key",
+        FileSources::LOCAL | FileSources::UNKNOWN => {
+            return Some(format!(
+                "Files can not be ingested from source: '{}'",
+                file.source
+            ))
+        }
+    };
+    match file
+        .location
+        .get(object_field)
+        .and_then(|object| object.as_str())
+    {
+        Some(object) if is_team_object(object, team_id, datasource_id) => None,
+        Some(object) => Some(format!(
+            "The file: '{}' is not stored under the team or datasource prefix",
+            object
+        )),
+        None => Some(format!("The file is missing its '{}'", object_field)),
+    }
+}
+
+/// Takes records or a file reference from an application and puts them through the same
+/// pipeline as messages from the message queue, so that data can be written without setting up an
+/// Airbyte connection. Records are queued for the workers, and files are chunked and embedded in
+/// the background, so the response only says what was accepted.
+#[wherr]
+#[post("/ingest/{datasource_id}/{stream}")]
+pub async fn ingest_records(
+    team: AuthenticatedTeam,
+    Path((datasource_id, stream)): Path<(String, String)>,
+    data: web::Json<IngestRequest>,
+) -> Result<impl Responder> {
+    let mongodb_connection = start_mongo_connection().await?;
+    if !team_owns_collection(&mongodb_connection, &team, datasource_id.as_str()).await? {
+        return Ok(forbidden_collection_response(datasource_id.as_str()));
+    }
+    let datasource = match get_datasource(&mongodb_connection, datasource_id.as_str()).await {
+        Ok(Some(datasource)) => datasource,
+        Ok(None) => {
+            return Ok(HttpResponse::NotFound()
+                .content_type(ContentType::json())
+                .json(json!(ResponseBody {
+                    status: Status::Failure,
+                    data: None,
+                    error_message: Some(json!({
+                        "errorMessage": format!("The datasource: '{}' does not exists in the \
+                        database",
+                            datasource_id)
+                    }))
+                })))
+        }
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest()
+                .content_type(ContentType::json())
+                .json(json!(ResponseBody {
+                    status: Status::Failure,
+                    data: None,
+                    error_message: Some(json!({
+                        "errorMessage": format!("Could not ingest into datasource: '{}' due to \
+                        an unknown error. Error: {}", datasource_id, e)
+                    }))
+                })))
+        }
+    };
+    let configured_streams = datasource.stream_config.clone().unwrap_or_default();
+    let error_message =
+        if !configured_streams.is_empty() && !configured_streams.contains_key(&stream) {
+            Some(format!(
+                "The stream: '{}' is not configured on datasource: '{}'",
+                stream, datasource_id
+            ))
+        } else if !matches!(
+            get_model(&mongodb_connection, datasource_id.as_str()).await,
+            Ok(Some(_))
+        ) {
+            Some(format!(
+                "There is no embedding model associated with datasource: '{}'",
+                datasource_id
+            ))
+        } else if let IngestRequest::File { file } = &data.0 {
+            ingest_file_error(file, team.team_id.as_str(), datasource_id.as_str())
+        } else {
+            None
+        };
+    if let Some(error_message) = error_message {
+        return Ok(HttpResponse::BadRequest()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::Failure,
+                data: None,
+                error_message: Some(json!({ "errorMessage": error_message }))
+            })));
+    }
+    let Some(sender) = work_queue() else {
+        return Ok(HttpResponse::ServiceUnavailable()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::Failure,
+                data: None,
+                error_message: Some(json!({
+                    "errorMessage": "The proxy is shutting down and not accepting data"
+                }))
+            })));
+    };
+    let mongo_client = Arc::new(RwLock::new(mongodb_connection));
+    let span = tracing::info_span!("ingest", datasource_id = datasource_id.as_str());
+    let accepted = match data.0 {
+        IngestRequest::Records { records } => {
+            let number_of_records = records.len();
+            // The batch is counted against the sync run before it is queued, so that the run can
+            // be completed right after and becomes ready once the workers have processed it
+            if let Err(e) = start_records(
+                &*mongo_client.read().await,
+                datasource.clone(),
+                number_of_records as i64,
+            )
+            .await
+            {
+                return Ok(HttpResponse::InternalServerError()
+                    .content_type(ContentType::json())
+                    .json(json!(ResponseBody {
+                        status: Status::Failure,
+                        data: None,
+                        error_message: Some(json!({
+                            "errorMessage": format!("Could not start a sync run for datasource: \
+                            '{}'. Error: {}", datasource_id, e)
+                        }))
+                    })));
+            }
+            // The datasource and its model were loaded above, so records go straight to the work
+            // queue rather than through `process_message`, which would load them for every record
+            for record in records {
+                // Wrapped like records from PubSub, so that a record with a `type` field is not
+                // taken for an Airbyte protocol message
+                let message =
+                    json!({ "_airbyte_data": record, INGESTED_RECORD_FIELD: true }).to_string();
+                send_task(
+                    sender.clone(),
+                    (datasource.clone(), Some(stream.clone()), message),
+                    None,
+                )
+                .instrument(span.clone())
+                .await;
             }
-            Err(e) => {
+            if let Err(e) = complete_sync_run(&*mongo_client.read().await, datasource).await {
                 log::error!(
-                    "Error retrieving model for datasource {}: {:?}",
-                    datasource.id,
+                    "Could not complete the sync run of datasource {}. Error: {}",
+                    datasource_id,
                     e
                 );
-                continue;
             }
+            json!({ "records": number_of_records })
         }
-    }
-    Ok(HttpResponse::Ok()
+        IngestRequest::File { file } => {
+            let accepted = json!({ "file": file.location });
+            let (source, location) = (file.source, serde_json::Value::Object(file.location));
+            track_ingest(rt::spawn(
+                async move {
+                    process_message(
+                        location.to_string(),
+                        Some(source),
+                        datasource_id.as_str(),
+                        None,
+                        mongo_client,
+                        sender,
+                        None,
+                    )
+                    .await;
+                }
+                .instrument(span),
+            ));
+            accepted
+        }
+    };
+    Ok(HttpResponse::Accepted()
         .content_type(ContentType::json())
         .json(json!(ResponseBody {
             status: Status::Success,
-            data: Some(json!(collection_size_response)),
+            data: Some(accepted),
             error_message: None
         })))
 }
+
+#[wherr]
+#[get("/datasource/{datasource_id}/events")]
+pub async fn datasource_events(
+    team: AuthenticatedTeam,
+    Path(datasource_id): Path<String>,
+) -> Result<impl Responder> {
+    let mongodb_connection = start_mongo_connection().await?;
+    if !team_owns_collection(&mongodb_connection, &team, datasource_id.as_str()).await? {
+        return Ok(Either::Left(forbidden_collection_response(
+            datasource_id.as_str(),
+        )));
+    }
+    match get_datasource(&mongodb_connection, datasource_id.as_str()).await {
+        Ok(Some(_)) => {
+            // Subscribe before reading the current state so that no event falls in between
+            let mut events = progress::subscribe();
+            let (tx, rx) = tokio::sync::mpsc::channel::<sse::Event>(10);
+            if let Some(sync_run) =
+                get_latest_sync_run(&mongodb_connection, datasource_id.as_str()).await?
+            {
+                let event =
+                    ProgressEvent::new(datasource_id.clone(), ProgressEventType::Status, &sync_run);
+                let data =
+                    sse::Data::new_json(&event)?.event(ProgressEventType::as_str(&event.event));
+                let _ = tx.send(data.into()).await;
+            }
+            rt::spawn(async move {
+                loop {
+                    // Stop as soon as the client goes away, rather than on the next event of any
+                    // datasource, which may never come
+                    let received = tokio::select! {
+                        _ = tx.closed() => break,
+                        received = events.recv() => received,
+                    };
+                    match received {
+                        Ok(event) if event.datasource_id == datasource_id => {
+                            let data = match sse::Data::new_json(&event) {
+                                Ok(data) => data.event(ProgressEventType::as_str(&event.event)),
+                                Err(e) => {
+                                    log::error!("Error: {}", e);
+                                    continue;
+                                }
+                            };
+                            // The client has gone away
+                            if tx.send(data.into()).await.is_err() {
+                                break;
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(RecvError::Lagged(skipped)) => {
+                            log::warn!(
+                                "Event stream of datasource {} skipped {} events",
+                                datasource_id,
+                                skipped
+                            );
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            });
+            Ok(Either::Right(
+                sse::Sse::from_infallible_receiver(rx)
+                    .with_keep_alive(Duration::from_secs(SSE_KEEP_ALIVE_SECS)),
+            ))
+        }
+        Ok(None) => Ok(Either::Left(
+            HttpResponse::NotFound()
+                .content_type(ContentType::json())
+                .json(json!(ResponseBody {
+                    status: Status::Failure,
+                    data: None,
+                    error_message: Some(json!({
+                        "errorMessage": format!("The datasource: '{}' does not exists in the \
+                        database",
+                            datasource_id)
+                    }))
+                })),
+        )),
+        Err(e) => Ok(Either::Left(
+            HttpResponse::BadRequest()
+                .content_type(ContentType::json())
+                .json(json!(ResponseBody {
+                    status: Status::Failure,
+                    data: None,
+                    error_message: Some(json!({
+                        "errorMessage": format!("Could not stream events for datasource: '{}' \
+                        due to an unknown error. Error: {}", datasource_id, e)
+                    }))
+                })),
+        )),
+    }
+}
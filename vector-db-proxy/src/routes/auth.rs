@@ -0,0 +1,219 @@
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::routes::models::{ResponseBody, Status};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::ErrorUnauthorized;
+use actix_web::http::header::{ContentType, AUTHORIZATION};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use std::future::{ready, Ready};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Header naming the team a request made with the API key acts on behalf of
+pub const TEAM_ID_HEADER: &str = "X-Team-Id";
+
+// Paths reachable without credentials, such as the health check used by the ingress
+const PUBLIC_PATHS: [&str; 2] = ["/api/v1", "/api/v1/"];
+
+/// The team a request was authenticated for. Inserted into the request extensions by
+/// [`authenticate`] and extracted by handlers to scope what they touch.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedTeam {
+    pub team_id: String,
+}
+
+impl FromRequest for AuthenticatedTeam {
+    type Error = actix_web::Error;
+    type Future = Ready<std::result::Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<AuthenticatedTeam>()
+                .cloned()
+                .ok_or_else(|| ErrorUnauthorized("Request is not authenticated")),
+        )
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ServiceTokenClaims {
+    team_id: String,
+    // Expiry as seconds since the unix epoch
+    exp: u64,
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Verifies a service token of the form `<base64url claims>.<hex HMAC-SHA256 of the claims>`
+/// signed with the API key, returning the team it was issued for
+fn verify_service_token(api_key: &str, token: &str) -> Result<String> {
+    let (claims, signature) = token
+        .split_once('.')
+        .ok_or(anyhow!("Malformed service token"))?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(api_key.as_bytes())
+        .map_err(|e| anyhow!("Invalid API key. Error: {}", e))?;
+    mac.update(claims.as_bytes());
+    mac.verify_slice(&hex::decode(signature)?)
+        .map_err(|_| anyhow!("Service token signature does not match"))?;
+    let claims: ServiceTokenClaims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims)?)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    if claims.exp < now {
+        return Err(anyhow!("Service token has expired"));
+    }
+    Ok(claims.team_id)
+}
+
+async fn authenticate_request(req: &HttpRequest) -> Result<AuthenticatedTeam> {
+    let api_key = GLOBAL_DATA.read().await.api_key.clone();
+    if api_key.is_empty() {
+        return Err(anyhow!(
+            "VECTOR_PROXY_API_KEY is not set, refusing all requests"
+        ));
+    }
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .ok_or(anyhow!("Missing bearer token"))?
+        .trim();
+    let team_id = if constant_time_eq(token.as_bytes(), api_key.as_bytes()) {
+        req.headers()
+            .get(TEAM_ID_HEADER)
+            .and_then(|h| h.to_str().ok())
+            .filter(|t| !t.is_empty())
+            .map(|t| t.to_string())
+            .ok_or(anyhow!(
+                "Requests made with the API key must set {}",
+                TEAM_ID_HEADER
+            ))?
+    } else {
+        verify_service_token(api_key.as_str(), token)?
+    };
+    Ok(AuthenticatedTeam { team_id })
+}
+
+/// Middleware rejecting requests that do not carry either the API key (together with the team
+/// it acts for) or a service token signed with it
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> std::result::Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if req.method() == Method::OPTIONS || PUBLIC_PATHS.contains(&req.path()) {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+    match authenticate_request(req.request()).await {
+        Ok(team) => {
+            req.extensions_mut().insert(team);
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+        Err(e) => {
+            log::warn!("Rejected request to {}. Error: {}", req.path(), e);
+            let response = HttpResponse::Unauthorized()
+                .content_type(ContentType::json())
+                .json(json!(ResponseBody {
+                    status: Status::Failure,
+                    data: None,
+                    error_message: Some(json!({
+                        "errorMessage": format!("Unauthorized. Error: {}", e)
+                    }))
+                }));
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const API_KEY: &str = "test-api-key";
+
+    // Signs claims the way the webapp does when it issues a service token
+    fn sign(api_key: &str, claims: serde_json::Value) -> String {
+        let claims = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let mut mac = Hmac::<Sha256>::new_from_slice(api_key.as_bytes()).unwrap();
+        mac.update(claims.as_bytes());
+        format!("{}.{}", claims, hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn expiring_in(seconds: i64) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        now.saturating_add_signed(seconds)
+    }
+
+    #[test]
+    fn accepts_a_token_signed_with_the_api_key() {
+        let token = sign(API_KEY, json!({"teamId": "team-1", "exp": expiring_in(60)}));
+        assert_eq!(verify_service_token(API_KEY, &token).unwrap(), "team-1");
+    }
+
+    #[test]
+    fn rejects_a_token_signed_with_another_key() {
+        let token = sign(
+            "other-key",
+            json!({"teamId": "team-1", "exp": expiring_in(60)}),
+        );
+        assert_eq!(
+            verify_service_token(API_KEY, &token)
+                .unwrap_err()
+                .to_string(),
+            "Service token signature does not match"
+        );
+    }
+
+    #[test]
+    fn rejects_claims_swapped_under_a_signature() {
+        let token = sign(API_KEY, json!({"teamId": "team-1", "exp": expiring_in(60)}));
+        let other = sign(API_KEY, json!({"teamId": "team-2", "exp": expiring_in(60)}));
+        let (_, signature) = token.split_once('.').unwrap();
+        let (claims, _) = other.split_once('.').unwrap();
+        let forged = format!("{}.{}", claims, signature);
+        assert!(verify_service_token(API_KEY, &forged).is_err());
+    }
+
+    #[test]
+    fn rejects_an_expired_token() {
+        let token = sign(
+            API_KEY,
+            json!({"teamId": "team-1", "exp": expiring_in(-60)}),
+        );
+        assert_eq!(
+            verify_service_token(API_KEY, &token)
+                .unwrap_err()
+                .to_string(),
+            "Service token has expired"
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_tokens() {
+        assert_eq!(
+            verify_service_token(API_KEY, "no-signature")
+                .unwrap_err()
+                .to_string(),
+            "Malformed service token"
+        );
+        assert!(verify_service_token(API_KEY, "claims.not-hex").is_err());
+    }
+}
@@ -1,5 +1,12 @@
+use crate::adaptors::mongo::queries::get_team_datasources;
+use crate::data::quotas::QuotaExceeded;
+use crate::routes::auth::AuthenticatedTeam;
+use crate::routes::models::{ResponseBody, Status};
 use crate::vector_databases::error::VectorDatabaseError;
-use serde_json::Value;
+use actix_web::http::header::ContentType;
+use actix_web::HttpResponse;
+use mongodb::Database;
+use serde_json::{json, Value};
 
 pub fn format_error_message(msg: VectorDatabaseError) -> Option<Value> {
     let error_message_str = format!("{}", msg);
@@ -9,3 +16,42 @@ pub fn format_error_message(msg: VectorDatabaseError) -> Option<Value> {
         .and_then(|json_part| serde_json::from_str(json_part).ok());
     error_message_json
 }
+
+/// Checks that the collection, addressed either by datasource ID or by its collection name,
+/// belongs to one of the datasources of the team the request was authenticated for
+pub async fn team_owns_collection(
+    mongo: &Database,
+    team: &AuthenticatedTeam,
+    collection_id: &str,
+) -> anyhow::Result<bool> {
+    let team_datasources = get_team_datasources(mongo, team.team_id.as_str()).await?;
+    Ok(team_datasources.iter().any(|datasource| {
+        datasource.id.to_string() == collection_id
+            || datasource.collection_name.as_deref() == Some(collection_id)
+    }))
+}
+
+pub fn forbidden_collection_response(collection_id: &str) -> HttpResponse {
+    HttpResponse::Forbidden()
+        .content_type(ContentType::json())
+        .json(json!(ResponseBody {
+            status: Status::Failure,
+            data: None,
+            error_message: Some(json!({
+                "errorMessage": format!("The collection: '{}' does not belong to the team",
+                    collection_id)
+            }))
+        }))
+}
+
+pub fn quota_exceeded_response(quota_exceeded: QuotaExceeded) -> HttpResponse {
+    HttpResponse::build(quota_exceeded.status_code())
+        .content_type(ContentType::json())
+        .json(json!(ResponseBody {
+            status: Status::Failure,
+            data: None,
+            error_message: Some(json!({
+                "errorMessage": quota_exceeded.to_string()
+            }))
+        }))
+}
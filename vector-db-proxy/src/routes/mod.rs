@@ -1,3 +1,4 @@
 pub mod apis;
+pub mod auth;
 mod helpers;
 pub mod models;
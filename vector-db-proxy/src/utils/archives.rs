@@ -0,0 +1,303 @@
+use crate::data::models::{FileContent, FileType, SourceFile};
+use crate::utils::file_operations::determine_file_type;
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+use std::io::{copy, Cursor, Read, Seek};
+use tar::Archive;
+use tempfile::NamedTempFile;
+use zip::ZipArchive;
+
+// A tar header is one block, with the `ustar` magic at offset 257
+const TAR_BLOCK_SIZE: u64 = 512;
+const TAR_MAGIC_OFFSET: usize = 257;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ArchiveFormat {
+    ZIP,
+    TAR,
+    TARGZ,
+}
+
+pub struct ArchiveLimits {
+    pub max_entries: usize,
+    pub max_uncompressed_bytes: u64,
+}
+
+fn is_tar_header(header: &[u8]) -> bool {
+    header
+        .get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5)
+        .is_some_and(|magic| magic == b"ustar")
+}
+
+pub fn zip_entry_names(file: &FileContent) -> Result<Vec<String>> {
+    let names = match file {
+        FileContent::Memory(bytes) => ZipArchive::new(Cursor::new(bytes.clone()))?
+            .file_names()
+            .map(|name| name.to_string())
+            .collect(),
+        FileContent::Spooled(spooled) => ZipArchive::new(spooled.reopen()?)?
+            .file_names()
+            .map(|name| name.to_string())
+            .collect(),
+    };
+    Ok(names)
+}
+
+/// Office documents and EPUBs are zips as well, marked by their `[Content_Types].xml` or
+/// `mimetype` entry
+pub fn is_zip_document(file: &FileContent) -> Result<bool> {
+    Ok(zip_entry_names(file)?
+        .iter()
+        .any(|name| name == "[Content_Types].xml" || name == "mimetype"))
+}
+
+/// Tells archives apart from other files by their magic bytes. Gzipped files only count as an
+/// archive when they hold a tar.
+pub fn detect_archive(file: &FileContent) -> Result<Option<ArchiveFormat>> {
+    let mut header = vec![];
+    file.reader()?
+        .take(TAR_BLOCK_SIZE)
+        .read_to_end(&mut header)?;
+    if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+        return Ok((!is_zip_document(file)?).then_some(ArchiveFormat::ZIP));
+    }
+    if header.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = vec![];
+        // Not a gzipped tar if the start does not even decompress
+        let _ = GzDecoder::new(file.reader()?)
+            .take(TAR_BLOCK_SIZE)
+            .read_to_end(&mut decompressed);
+        return Ok(is_tar_header(&decompressed).then_some(ArchiveFormat::TARGZ));
+    }
+    Ok(is_tar_header(&header).then_some(ArchiveFormat::TAR))
+}
+
+/// Directories are skipped, as are the resource forks macOS adds to zips
+fn is_skipped_entry(path: &str) -> bool {
+    path.ends_with('/') || path.starts_with("__MACOSX/")
+}
+
+/// The entry as a file to embed, unless it is of a type that can not be chunked, including
+/// archives nested in the archive
+fn source_file(path: String, content: FileContent) -> Option<SourceFile> {
+    match determine_file_type(&path, &content) {
+        FileType::UNKNOWN => None,
+        file_type => Some(SourceFile {
+            file_type,
+            content,
+            path,
+            in_archive: true,
+        }),
+    }
+}
+
+/// Writes an entry to a temporary file, failing once the archive as a whole expands beyond the
+/// limit. The limit is enforced on the bytes read rather than the sizes the archive claims, and
+/// counts entries that turn out not to be chunkable as well.
+fn spool_entry(
+    entry: impl Read,
+    remaining_bytes: &mut u64,
+    limits: &ArchiveLimits,
+) -> Result<FileContent> {
+    let mut spooled = NamedTempFile::new()?;
+    let written = copy(&mut entry.take(*remaining_bytes + 1), spooled.as_file_mut())?;
+    if written > *remaining_bytes {
+        return Err(anyhow!(
+            "The archive expands to more than {} bytes",
+            limits.max_uncompressed_bytes
+        ));
+    }
+    *remaining_bytes -= written;
+    Ok(FileContent::Spooled(spooled))
+}
+
+fn too_many_entries(limits: &ArchiveLimits) -> anyhow::Error {
+    anyhow!("The archive has more than {} entries", limits.max_entries)
+}
+
+fn extract_zip(reader: impl Read + Seek, limits: &ArchiveLimits) -> Result<Vec<SourceFile>> {
+    let mut archive = ZipArchive::new(reader)?;
+    if archive.len() > limits.max_entries {
+        return Err(too_many_entries(limits));
+    }
+    let mut remaining_bytes = limits.max_uncompressed_bytes;
+    let mut files = vec![];
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index)?;
+        if !entry.is_file() {
+            continue;
+        }
+        // Entries whose path would escape the archive are left out
+        let Some(path) = entry.enclosed_name() else {
+            log::warn!("Skipping archive entry with unsafe path {}", entry.name());
+            continue;
+        };
+        let path = path.to_string_lossy().to_string();
+        if is_skipped_entry(&path) {
+            continue;
+        }
+        let content = spool_entry(entry, &mut remaining_bytes, limits)?;
+        files.extend(source_file(path, content));
+    }
+    Ok(files)
+}
+
+fn extract_tar(reader: impl Read, limits: &ArchiveLimits) -> Result<Vec<SourceFile>> {
+    let mut archive = Archive::new(reader);
+    let mut remaining_bytes = limits.max_uncompressed_bytes;
+    let mut files = vec![];
+    for (index, entry) in archive.entries()?.enumerate() {
+        if index >= limits.max_entries {
+            return Err(too_many_entries(limits));
+        }
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.to_string_lossy().to_string();
+        if is_skipped_entry(&path) {
+            continue;
+        }
+        let content = spool_entry(entry, &mut remaining_bytes, limits)?;
+        files.extend(source_file(path, content));
+    }
+    Ok(files)
+}
+
+/// Extracts the entries of an archive that can be chunked, each into a temporary file
+pub fn extract_archive(
+    file: &FileContent,
+    format: ArchiveFormat,
+    limits: &ArchiveLimits,
+) -> Result<Vec<SourceFile>> {
+    match (format, file) {
+        (ArchiveFormat::ZIP, FileContent::Memory(bytes)) => {
+            extract_zip(Cursor::new(bytes.clone()), limits)
+        }
+        (ArchiveFormat::ZIP, FileContent::Spooled(spooled)) => {
+            extract_zip(spooled.reopen()?, limits)
+        }
+        (ArchiveFormat::TAR, _) => extract_tar(file.reader()?, limits),
+        (ArchiveFormat::TARGZ, _) => extract_tar(GzDecoder::new(file.reader()?), limits),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    const LIMITS: ArchiveLimits = ArchiveLimits {
+        max_entries: 3,
+        max_uncompressed_bytes: 64,
+    };
+
+    fn zip(entries: &[(&str, &[u8])]) -> FileContent {
+        let mut writer = ZipWriter::new(Cursor::new(vec![]));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        for (name, content) in entries {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        FileContent::from(writer.finish().unwrap().into_inner())
+    }
+
+    fn tar(entries: &[(&str, &[u8])]) -> FileContent {
+        let mut builder = tar::Builder::new(vec![]);
+        for (name, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+        FileContent::from(builder.into_inner().unwrap())
+    }
+
+    fn paths(files: Vec<SourceFile>) -> Vec<String> {
+        files.into_iter().map(|file| file.path).collect()
+    }
+
+    #[test]
+    fn extracts_the_entries_that_can_be_chunked() {
+        let archive = zip(&[
+            ("notes/a.txt", b"first"),
+            ("__MACOSX/notes/._a.txt", b"fork"),
+            ("blob", b"\0\x01"),
+        ]);
+        let files = extract_archive(&archive, ArchiveFormat::ZIP, &LIMITS).unwrap();
+        assert!(files.iter().all(|file| file.in_archive));
+        assert_eq!(paths(files), vec!["notes/a.txt"]);
+    }
+
+    #[test]
+    fn rejects_archives_with_too_many_entries() {
+        let entries: [(&str, &[u8]); 4] = [
+            ("a.txt", b"a"),
+            ("b.txt", b"b"),
+            ("c.txt", b"c"),
+            ("d.txt", b"d"),
+        ];
+        for (archive, format) in [
+            (zip(&entries), ArchiveFormat::ZIP),
+            (tar(&entries), ArchiveFormat::TAR),
+        ] {
+            assert_eq!(
+                extract_archive(&archive, format, &LIMITS)
+                    .err()
+                    .unwrap()
+                    .to_string(),
+                "The archive has more than 3 entries"
+            );
+        }
+        let within_limit = tar(&entries[..3]);
+        assert_eq!(
+            paths(extract_archive(&within_limit, ArchiveFormat::TAR, &LIMITS).unwrap()),
+            vec!["a.txt", "b.txt", "c.txt"]
+        );
+    }
+
+    #[test]
+    fn rejects_archives_expanding_beyond_the_byte_limit() {
+        let large = [b'a'; 40];
+        let entries: [(&str, &[u8]); 2] = [("a.txt", &large), ("b.txt", &large)];
+        for (archive, format) in [
+            (zip(&entries), ArchiveFormat::ZIP),
+            (tar(&entries), ArchiveFormat::TAR),
+        ] {
+            assert_eq!(
+                extract_archive(&archive, format, &LIMITS)
+                    .err()
+                    .unwrap()
+                    .to_string(),
+                "The archive expands to more than 64 bytes"
+            );
+        }
+        let within_limit = zip(&entries[..1]);
+        assert_eq!(
+            paths(extract_archive(&within_limit, ArchiveFormat::ZIP, &LIMITS).unwrap()),
+            vec!["a.txt"]
+        );
+    }
+
+    #[test]
+    fn detects_archives_by_their_magic_bytes() {
+        let entries: [(&str, &[u8]); 1] = [("a.txt", b"a")];
+        assert_eq!(
+            detect_archive(&zip(&entries)).unwrap(),
+            Some(ArchiveFormat::ZIP)
+        );
+        assert_eq!(
+            detect_archive(&tar(&entries)).unwrap(),
+            Some(ArchiveFormat::TAR)
+        );
+        let document = zip(&[("[Content_Types].xml", b"<Types/>")]);
+        assert_eq!(detect_archive(&document).unwrap(), None);
+        assert_eq!(
+            detect_archive(&FileContent::from(b"plain".to_vec())).unwrap(),
+            None
+        );
+    }
+}
@@ -1,11 +1,31 @@
 use crate::adaptors::gcp::gcs::get_object_from_gcs;
-use crate::data::models::FileType;
+use crate::adaptors::s3::client::get_object_from_s3;
+use crate::data::models::{FileContent, FileType, SourceFile};
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::utils::archives::{detect_archive, extract_archive, zip_entry_names, ArchiveLimits};
 use crate::utils::models::FileSources;
 use serde_json::Value;
 use std::fs::File;
-use std::io::Write;
+use std::io::{Read, Write};
+use std::path::Path;
 use tokio::fs;
 
+// Enough of a file to find the signatures below, including the `mimetype` entry of EPUBs
+const SNIFF_BYTES: u64 = 1024;
+// Brands in the `ftyp` box of HEIC images
+const HEIC_BRANDS: [&[u8]; 6] = [b"heic", b"heix", b"hevc", b"hevx", b"heim", b"mif1"];
+// Headers an email usually starts with
+const EMAIL_HEADERS: [&str; 8] = [
+    "received:",
+    "return-path:",
+    "delivered-to:",
+    "mime-version:",
+    "message-id:",
+    "from:",
+    "date:",
+    "x-",
+];
+
 pub async fn save_file_to_disk(content: Vec<u8>, file_name: &str) -> anyhow::Result<()> {
     let file_path = file_name.trim_matches('"');
     log::info!("File path : {}", file_path);
@@ -14,21 +34,130 @@ pub async fn save_file_to_disk(content: Vec<u8>, file_name: &str) -> anyhow::Res
     Ok(())
 }
 
-pub fn determine_file_type(file_path: &str) -> FileType {
-    let file_path = format!("{}", file_path);
-    let file_path_split: Vec<&str> = file_path.split(".").collect();
-    let file_extension = file_path_split.to_vec()[1]
-        .to_string()
-        .trim_end_matches('"')
-        .to_string();
-    let file_type = FileType::from(file_extension);
-    file_type
+/// Whether an object in a shared bucket belongs to a team, i.e. is stored under the team's or the
+/// datasource's prefix, e.g. `<team id>/exports/points.jsonl`, or is the file uploaded for the
+/// datasource itself, `<datasource id>.pdf`
+pub fn is_team_object(object: &str, team_id: &str, datasource_id: &str) -> bool {
+    let object = object.trim_matches('"').trim_start_matches('/');
+    if object.split('/').any(|segment| segment == "..") {
+        return false;
+    }
+    object.starts_with(&format!("{}/", team_id))
+        || object.starts_with(&format!("{}/", datasource_id))
+        || object
+            .strip_prefix(datasource_id)
+            .is_some_and(|rest| rest.starts_with('.'))
 }
 
-pub async fn read_file_from_source(
+/// The extension of a path, if it has one. Only the last dot counts, so `reports/q1.2024.pdf` is a
+/// pdf.
+fn file_extension(file_path: &str) -> Option<String> {
+    Path::new(file_path.trim_matches('"'))
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase())
+}
+
+/// Tells office documents and EPUBs apart from each other and from plain zips
+fn sniff_zip_type(header: &[u8], file: &FileContent) -> Option<FileType> {
+    // EPUBs and OpenDocument files start with an uncompressed `mimetype` entry
+    if header.get(30..38) == Some(&b"mimetype"[..]) {
+        let mimetype = header.get(38..)?;
+        if mimetype.starts_with(b"application/epub+zip") {
+            return Some(FileType::EPUB);
+        }
+        if mimetype.starts_with(b"application/vnd.oasis.opendocument.text") {
+            return Some(FileType::ODT);
+        }
+        // Spreadsheets and presentations, which Unstructured does not chunk
+        if mimetype.starts_with(b"application/vnd.oasis.opendocument") {
+            return Some(FileType::UNKNOWN);
+        }
+    }
+    let names = zip_entry_names(file).ok()?;
+    let has_folder = |folder: &str| names.iter().any(|name| name.starts_with(folder));
+    if has_folder("word/") {
+        Some(FileType::DOCX)
+    } else if has_folder("ppt/") {
+        Some(FileType::PPTX)
+    } else if has_folder("xl/") {
+        Some(FileType::XLSX)
+    } else {
+        None
+    }
+}
+
+/// Formats with a signature in their first bytes
+fn sniff_binary_type(header: &[u8], file: &FileContent) -> Option<FileType> {
+    if header.starts_with(b"%PDF-") {
+        Some(FileType::PDF)
+    } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(FileType::PNG)
+    } else if header.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some(FileType::JPEG)
+    } else if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") {
+        Some(FileType::TIFF)
+    } else if header.starts_with(b"BM") && header.get(6..10) == Some(&[0u8; 4][..]) {
+        Some(FileType::BMP)
+    } else if header.get(4..8) == Some(&b"ftyp"[..])
+        && header
+            .get(8..12)
+            .is_some_and(|brand| HEIC_BRANDS.contains(&brand))
+    {
+        Some(FileType::HEIC)
+    } else if header.starts_with(b"PK\x03\x04") {
+        sniff_zip_type(header, file)
+    } else {
+        None
+    }
+}
+
+/// Text formats that can be recognised from their first lines, for files without a known extension
+fn sniff_text_type(header: &[u8]) -> FileType {
+    if header.is_empty() || header.contains(&0) {
+        return FileType::UNKNOWN;
+    }
+    let text = String::from_utf8_lossy(header);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    let lowercase = text.to_lowercase();
+    if lowercase.starts_with("<!doctype html") || lowercase.starts_with("<html") {
+        FileType::HTML
+    } else if text.starts_with('{') || text.starts_with('[') {
+        FileType::JSON
+    } else if EMAIL_HEADERS
+        .iter()
+        .any(|email_header| lowercase.starts_with(email_header))
+        && (lowercase.starts_with("from:") || lowercase.contains("\nfrom:"))
+    {
+        FileType::EML
+    } else {
+        FileType::TXT
+    }
+}
+
+/// Works out the type of a file from its first bytes. Text formats have no signature, so those
+/// come from the extension, and files without a known extension are sniffed for a few of them.
+pub fn determine_file_type(file_path: &str, file: &FileContent) -> FileType {
+    let mut header = vec![];
+    if let Err(e) = file
+        .reader()
+        .and_then(|reader| reader.take(SNIFF_BYTES).read_to_end(&mut header))
+    {
+        log::warn!("Could not read the start of {}. Error: {}", file_path, e);
+    }
+    if let Some(file_type) = sniff_binary_type(&header, file) {
+        return file_type;
+    }
+    match file_extension(file_path).map(FileType::from) {
+        Some(FileType::UNKNOWN) | None => sniff_text_type(&header),
+        Some(file_type) => file_type,
+    }
+}
+
+async fn read_file(
     stream_type: Option<String>,
     message_data: Value,
-) -> Option<(FileType, Vec<u8>, String)> {
+) -> Option<(FileContent, String)> {
     // If the type field is present in the headers then we assume it is a file of sorts
     match stream_type {
         Some(t) => match FileSources::from(t) {
@@ -37,11 +166,7 @@ pub async fn read_file_from_source(
                     if let Some(file_name) = message_data.get("filename") {
                         match get_object_from_gcs(bucket_name.as_str()?, file_name.as_str()?).await
                         {
-                            Ok(file) => {
-                                let file_type = determine_file_type(file_name.as_str()?);
-                                let result = (file_type, file, file_name.to_string());
-                                Some(result)
-                            }
+                            Ok(file) => Some((file.into(), file_name.to_string())),
                             Err(e) => {
                                 log::error!("An error occurred while reading file from GCS: {}", e);
                                 None
@@ -59,11 +184,7 @@ pub async fn read_file_from_source(
             FileSources::LOCAL => {
                 if let Some(file_path) = message_data.get("file") {
                     match fs::read(file_path.as_str()?).await {
-                        Ok(file) => {
-                            let file_type = determine_file_type(file_path.as_str()?);
-                            let results = (file_type, file, file_path.to_string());
-                            Some(results)
-                        }
+                        Ok(file) => Some((file.into(), file_path.to_string())),
                         Err(e) => {
                             log::error!("An error occurred while reading file from DISK, {}", e);
                             None
@@ -74,6 +195,23 @@ pub async fn read_file_from_source(
                     None
                 }
             }
+            FileSources::S3 => {
+                let Some(bucket) = message_data.get("bucket").and_then(|b| b.as_str()) else {
+                    log::warn!("bucket not in message");
+                    return None;
+                };
+                let Some(key) = message_data.get("key").and_then(|k| k.as_str()) else {
+                    log::warn!("key not in message");
+                    return None;
+                };
+                match get_object_from_s3(bucket, key).await {
+                    Ok(file) => Some((file, key.to_string())),
+                    Err(e) => {
+                        log::error!("An error occurred while reading file from S3: {}", e);
+                        None
+                    }
+                }
+            }
             FileSources::UNKNOWN => {
                 log::warn!("File source unknown");
                 None
@@ -82,3 +220,137 @@ pub async fn read_file_from_source(
         None => None,
     }
 }
+
+/// Reads the file a message points to. Archives are expanded into the entries that can be chunked,
+/// so that each of them is embedded separately.
+pub async fn read_file_from_source(
+    stream_type: Option<String>,
+    message_data: Value,
+) -> Option<Vec<SourceFile>> {
+    let (content, path) = read_file(stream_type, message_data).await?;
+    let limits = {
+        let global_data = GLOBAL_DATA.read().await;
+        ArchiveLimits {
+            max_entries: global_data.archive_max_entries,
+            max_uncompressed_bytes: global_data.archive_max_uncompressed_bytes,
+        }
+    };
+    let archive_path = path.clone();
+    let files = tokio::task::spawn_blocking(move || match detect_archive(&content)? {
+        Some(format) => extract_archive(&content, format, &limits),
+        None => Ok(vec![SourceFile {
+            file_type: determine_file_type(&path, &content),
+            content,
+            path,
+            in_archive: false,
+        }]),
+    })
+    .await;
+    match files {
+        Ok(Ok(files)) => Some(files),
+        Ok(Err(e)) => {
+            log::error!("Could not read archive {}. Error: {}", archive_path, e);
+            None
+        }
+        Err(e) => {
+            log::error!("Error: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEAM_ID: &str = "65a0c0ffee0000000000000a";
+    const DATASOURCE_ID: &str = "65a0c0ffee0000000000000b";
+
+    fn file_type(path: &str, content: &[u8]) -> FileType {
+        determine_file_type(path, &FileContent::from(content.to_vec()))
+    }
+
+    #[test]
+    fn allows_objects_under_the_team_or_datasource() {
+        assert!(is_team_object(
+            &format!("{}/reports/q1.pdf", TEAM_ID),
+            TEAM_ID,
+            DATASOURCE_ID
+        ));
+        assert!(is_team_object(
+            &format!("\"/{}/notes.txt\"", DATASOURCE_ID),
+            TEAM_ID,
+            DATASOURCE_ID
+        ));
+        assert!(is_team_object(
+            &format!("{}.pdf", DATASOURCE_ID),
+            TEAM_ID,
+            DATASOURCE_ID
+        ));
+    }
+
+    #[test]
+    fn refuses_objects_of_other_teams() {
+        let other_team = "65a0c0ffee0000000000000c";
+        assert!(!is_team_object(
+            &format!("{}/reports/q1.pdf", other_team),
+            TEAM_ID,
+            DATASOURCE_ID
+        ));
+        // A prefix of the ID is not the ID
+        assert!(!is_team_object(
+            &format!("{}0/q1.pdf", TEAM_ID),
+            TEAM_ID,
+            DATASOURCE_ID
+        ));
+        assert!(!is_team_object(
+            &format!("{}-copy.pdf", DATASOURCE_ID),
+            TEAM_ID,
+            DATASOURCE_ID
+        ));
+        assert!(!is_team_object("reports/q1.pdf", TEAM_ID, DATASOURCE_ID));
+    }
+
+    #[test]
+    fn refuses_paths_that_climb_out_of_the_prefix() {
+        assert!(!is_team_object(
+            &format!("{}/../{}/q1.pdf", TEAM_ID, "65a0c0ffee0000000000000c"),
+            TEAM_ID,
+            DATASOURCE_ID
+        ));
+        assert!(!is_team_object(
+            &format!("{}/..", TEAM_ID),
+            TEAM_ID,
+            DATASOURCE_ID
+        ));
+    }
+
+    #[test]
+    fn takes_the_type_from_the_last_extension() {
+        assert_eq!(file_type("reports/q1.2024.pdf", b"%PDF-1.7"), FileType::PDF);
+        assert_eq!(file_type("\"notes.MD\"", b"# Notes"), FileType::MARKDOWN);
+        assert_eq!(file_type("table.csv", b"id,title\n1,Dune"), FileType::CSV);
+    }
+
+    #[test]
+    fn prefers_the_signature_over_the_extension() {
+        assert_eq!(file_type("scan.txt", b"%PDF-1.7"), FileType::PDF);
+        assert_eq!(
+            file_type("photo", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            FileType::PNG
+        );
+    }
+
+    #[test]
+    fn sniffs_text_files_without_an_extension() {
+        assert_eq!(file_type("README", b"Plain text"), FileType::TXT);
+        assert_eq!(file_type("page", b"<!DOCTYPE html><html>"), FileType::HTML);
+        assert_eq!(file_type("export", b"  [{\"id\": 1}]"), FileType::JSON);
+        assert_eq!(
+            file_type("message", b"From: a@example.com\nSubject: Hello\n\nHi"),
+            FileType::EML
+        );
+        assert_eq!(file_type("blob", b"\0\x01\x02"), FileType::UNKNOWN);
+        assert_eq!(file_type("empty", b""), FileType::UNKNOWN);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::embeddings::models::EmbeddingModels;
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::utils::shutdown::is_shutting_down;
+use crate::vector_databases::vector_database::default_vector_db_client;
+use anyhow::{anyhow, Result};
+use mongodb::bson::doc;
+use mongodb::Database;
+use reqwest::{Client, Url};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use tokio::time::{timeout, Duration};
+
+// How long a single dependency may take to answer before it is reported as unhealthy
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+const OPENAI_API_URL: &str = "https://api.openai.com/v1/models";
+
+// Whether the consumer is currently attached to the message queue
+static CONSUMER_ATTACHED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_consumer_attached(attached: bool) {
+    CONSUMER_ATTACHED.store(attached, Ordering::SeqCst);
+}
+
+pub fn is_consumer_attached() -> bool {
+    CONSUMER_ATTACHED.load(Ordering::SeqCst)
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyStatus {
+    pub healthy: bool,
+    pub latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Readiness {
+    pub ready: bool,
+    pub shutting_down: bool,
+    pub dependencies: BTreeMap<&'static str, DependencyStatus>,
+}
+
+async fn check<F>(future: F) -> DependencyStatus
+where
+    F: Future<Output = Result<()>>,
+{
+    let start = Instant::now();
+    let result = match timeout(CHECK_TIMEOUT, future).await {
+        Ok(result) => result,
+        Err(_) => Err(anyhow!("Timed out after {}s", CHECK_TIMEOUT.as_secs())),
+    };
+    DependencyStatus {
+        healthy: result.is_ok(),
+        latency_ms: start.elapsed().as_millis(),
+        error: result.err().map(|e| e.to_string()),
+    }
+}
+
+async fn check_mongo(mongo: &Database) -> Result<()> {
+    mongo.run_command(doc! {"ping": 1}, None).await?;
+    Ok(())
+}
+
+async fn check_message_queue() -> Result<()> {
+    if is_consumer_attached() {
+        Ok(())
+    } else {
+        Err(anyhow!("The consumer is not attached to the message queue"))
+    }
+}
+
+async fn check_vector_database() -> Result<()> {
+    let vector_database_client = default_vector_db_client().await;
+    let vector_database_client = vector_database_client.read().await;
+    vector_database_client.get_list_of_collections().await?;
+    Ok(())
+}
+
+/// Unstructured chunks files before they are embedded, and serves a health check next to its API
+async fn check_unstructured(client: &Client, unstructuredio_url: &str) -> Result<()> {
+    let healthcheck_url = Url::parse(unstructuredio_url)?.join("/healthcheck")?;
+    let response = client.get(healthcheck_url).send().await?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(anyhow!("Health check returned {}", response.status()))
+    }
+}
+
+/// Only reachability is checked, since the API keys belong to the models of each team
+async fn check_openai(client: &Client) -> Result<()> {
+    let response = client.get(OPENAI_API_URL).send().await?;
+    if response.status().is_server_error() {
+        Err(anyhow!("OpenAI returned {}", response.status()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether any model stored in Mongo is embedded by OpenAI
+async fn openai_models_configured(mongo: &Database) -> Result<bool> {
+    let openai_models = [
+        EmbeddingModels::OAI_ADA,
+        EmbeddingModels::OAI_SMALL,
+        EmbeddingModels::OAI_LARGE,
+    ];
+    let openai_models: Vec<&str> = openai_models
+        .iter()
+        .filter_map(|model| model.to_str())
+        .collect();
+    let count = mongo
+        .collection::<bson::Document>("models")
+        .count_documents(doc! {"model": {"$in": openai_models}}, None)
+        .await?;
+    Ok(count > 0)
+}
+
+/// Checks every dependency the proxy needs to serve traffic and process messages, over the Mongo
+/// connection the proxy already holds
+pub async fn readiness(mongo: &Database) -> Readiness {
+    let unstructuredio_url = GLOBAL_DATA.read().await.unstructuredio_url.clone();
+    let client = Client::builder()
+        .timeout(CHECK_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+    let (mongo_status, message_queue_status, vector_database_status, unstructured_status) = tokio::join!(
+        check(check_mongo(mongo)),
+        check(check_message_queue()),
+        check(check_vector_database()),
+        check(check_unstructured(&client, unstructuredio_url.as_str())),
+    );
+    let mut dependencies = BTreeMap::from([
+        ("mongo", mongo_status),
+        ("messageQueue", message_queue_status),
+        ("vectorDatabase", vector_database_status),
+        ("unstructured", unstructured_status),
+    ]);
+    let shutting_down = is_shutting_down();
+    let ready = !shutting_down && dependencies.values().all(|status| status.healthy);
+    // OpenAI is reported but does not gate readiness, since an outage there would otherwise take
+    // every instance out of rotation, including for teams that embed with other models
+    if let Ok(Ok(true)) = timeout(CHECK_TIMEOUT, openai_models_configured(mongo)).await {
+        dependencies.insert("openai", check(check_openai(&client)).await);
+    }
+    Readiness {
+        ready,
+        shutting_down,
+        dependencies,
+    }
+}
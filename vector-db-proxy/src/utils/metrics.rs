@@ -0,0 +1,124 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, Encoder, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+    TextEncoder,
+};
+use std::future::Future;
+use std::time::Instant;
+use tracing::Instrument;
+
+// Embedding and Unstructured calls take far longer than vector database operations
+const SLOW_CALL_BUCKETS: [f64; 12] = [
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+pub static MESSAGES_CONSUMED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "vector_proxy_messages_consumed_total",
+        "Messages consumed from the message queue",
+        &["provider"]
+    )
+    .unwrap()
+});
+
+pub static WORK_QUEUE_DEPTH: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "vector_proxy_work_queue_depth",
+        "Messages waiting in the work queue for a worker"
+    )
+    .unwrap()
+});
+
+pub static EMBEDDING_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "vector_proxy_embedding_duration_seconds",
+        "Time taken to embed a batch of texts",
+        &["model"],
+        SLOW_CALL_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+pub static EMBEDDING_TOKENS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "vector_proxy_embedding_tokens_total",
+        "Tokens embedded, as reported by the model provider or estimated for local models",
+        &["model"]
+    )
+    .unwrap()
+});
+
+pub static VECTOR_DB_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "vector_proxy_vector_db_operation_duration_seconds",
+        "Time taken by vector database operations",
+        &["backend", "operation"]
+    )
+    .unwrap()
+});
+
+pub static VECTOR_DB_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "vector_proxy_vector_db_operation_errors_total",
+        "Vector database operations that returned an error",
+        &["backend", "operation"]
+    )
+    .unwrap()
+});
+
+pub static UNSTRUCTURED_DURATION: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "vector_proxy_unstructured_request_duration_seconds",
+        "Time taken to chunk a document with Unstructured, including retries",
+        SLOW_CALL_BUCKETS.to_vec()
+    )
+    .unwrap()
+});
+
+pub static UNSTRUCTURED_RETRIES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "vector_proxy_unstructured_retries_total",
+        "Requests to Unstructured that were retried"
+    )
+    .unwrap()
+});
+
+/// Times and traces a vector database operation, counting it as an error if it fails
+pub async fn observe_vector_db_operation<T, E, F>(
+    backend: &str,
+    operation: &str,
+    future: F,
+) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let start = Instant::now();
+    let result = future
+        .instrument(tracing::info_span!(
+            "vector_db_operation",
+            backend,
+            operation
+        ))
+        .await;
+    VECTOR_DB_DURATION
+        .with_label_values(&[backend, operation])
+        .observe(start.elapsed().as_secs_f64());
+    if result.is_err() {
+        count_vector_db_error(backend, operation);
+    }
+    result
+}
+
+pub fn count_vector_db_error(backend: &str, operation: &str) {
+    VECTOR_DB_ERRORS
+        .with_label_values(&[backend, operation])
+        .inc();
+}
+
+/// Renders every registered metric in the Prometheus text format
+pub fn render() -> anyhow::Result<String> {
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
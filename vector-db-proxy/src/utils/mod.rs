@@ -1,6 +1,12 @@
+pub mod archives;
 pub mod conversions;
 pub mod maths;
 pub mod macros;
+pub mod health;
+pub mod metrics;
+pub mod secret;
+pub mod shutdown;
+pub mod telemetry;
 pub mod webhook;
 pub mod file_operations;
 pub mod models;
@@ -1,6 +1,7 @@
 pub enum FileSources {
     GCS,
     LOCAL,
+    S3,
     UNKNOWN,
 }
 
@@ -9,6 +10,7 @@ impl From<String> for FileSources {
         match value.as_str() {
             "google" => FileSources::GCS,
             "local" => FileSources::LOCAL,
+            "s3" => FileSources::S3,
             _ => FileSources::UNKNOWN
         }
     }
@@ -0,0 +1,45 @@
+use once_cell::sync::Lazy;
+use tokio::signal;
+use tokio::sync::watch;
+
+static SHUTDOWN: Lazy<watch::Sender<bool>> = Lazy::new(|| watch::channel(false).0);
+
+/// Waits for SIGTERM, as sent by Kubernetes on rollouts, or SIGINT
+pub async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => log::info!("Received SIGTERM"),
+                    _ = signal::ctrl_c() => log::info!("Received SIGINT"),
+                }
+            }
+            Err(e) => {
+                log::error!("Could not listen for SIGTERM. Error: {}", e);
+                let _ = signal::ctrl_c().await;
+                log::info!("Received SIGINT");
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = signal::ctrl_c().await;
+        log::info!("Received Ctrl+C");
+    }
+}
+
+/// Tells the consumers to stop taking messages from the queue
+pub fn request_shutdown() {
+    SHUTDOWN.send_replace(true);
+}
+
+pub fn is_shutting_down() -> bool {
+    *SHUTDOWN.borrow()
+}
+
+/// Completes once shutdown has been requested
+pub async fn shutdown_requested() {
+    let mut receiver = SHUTDOWN.subscribe();
+    let _ = receiver.wait_for(|shutting_down| *shutting_down).await;
+}
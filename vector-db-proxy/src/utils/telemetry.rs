@@ -0,0 +1,59 @@
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::Config;
+use opentelemetry_sdk::{runtime, Resource};
+use std::collections::HashMap;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+
+// Headers carrying the W3C trace context of the producer of a message
+pub const TRACE_CONTEXT_HEADERS: [&str; 2] = ["traceparent", "tracestate"];
+
+/// Exports the spans of this crate over OTLP/HTTP. The collector is taken from the standard
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` variable, and nothing is exported when it is not set.
+pub fn init_tracing(otlp_endpoint: &str, service_name: &str) -> anyhow::Result<()> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    if otlp_endpoint.is_empty() {
+        log::info!("OTEL_EXPORTER_OTLP_ENDPOINT is not set, traces will not be exported");
+        return Ok(());
+    }
+    let tracer_provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().http())
+        .with_trace_config(
+            Config::default().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name.to_string(),
+            )])),
+        )
+        .install_batch(runtime::Tokio)?;
+    global::set_tracer_provider(tracer_provider.clone());
+    let tracer = tracer_provider.tracer(service_name.to_string());
+    // Only spans of the proxy itself, not the ones of the drivers and clients it uses
+    let subscriber = tracing_subscriber::registry()
+        .with(Targets::new().with_target("vector_db_proxy", tracing::Level::INFO))
+        .with(tracing_opentelemetry::layer().with_tracer(tracer));
+    tracing::subscriber::set_global_default(subscriber)?;
+    log::info!("Exporting traces to {}", otlp_endpoint);
+    Ok(())
+}
+
+/// Flushes the spans that have not been exported yet
+pub fn shutdown_tracing() {
+    global::shutdown_tracer_provider();
+}
+
+/// Makes the span a child of the trace context found in the headers of a queue message, if any
+pub fn set_parent_from_headers(span: &Span, headers: &HashMap<String, String>) {
+    if TRACE_CONTEXT_HEADERS
+        .iter()
+        .any(|header| headers.contains_key(*header))
+    {
+        let context = TraceContextPropagator::new().extract(headers);
+        span.set_parent(context);
+    }
+}
@@ -1,30 +1,203 @@
-use reqwest::{Client};
-use anyhow::{anyhow};
-use serde_json::json;
+use crate::adaptors::mongo::models::WebhookOutboxEvent;
+use crate::adaptors::mongo::queries::{
+    claim_due_webhook_event, insert_webhook_event, mark_webhook_event_delivered,
+    reschedule_webhook_event,
+};
 use crate::init::env_variables::GLOBAL_DATA;
+use crate::utils::shutdown::is_shutting_down;
+use anyhow::anyhow;
+use hmac::{Hmac, Mac};
+use mongodb::Database;
+use reqwest::Client;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
 
-pub async fn send_webapp_embed_ready(datasource_id: &str) -> Result<(), anyhow::Error> {
-    let global_data = GLOBAL_DATA.read().await;
-    let url = format!("http://{}:{}{}", global_data.webapp_host, global_data.webapp_port, "/webhook/embed-successful");
+// How long a claimed event is hidden from other dispatchers while it is being delivered
+const DELIVERY_LEASE_MILLIS: i64 = 60_000;
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const INITIAL_RETRY_DELAY_SECS: i64 = 5;
+const MAX_RETRY_DELAY_SECS: i64 = 3600;
 
-    // Prepare the POST request body
-    let body = json!({
-        "datasourceId": datasource_id
-    });
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebhookEvent {
+    EmbedStarted,
+    EmbedProgress,
+    EmbedCompleted,
+    EmbedFailed,
+}
+
+impl WebhookEvent {
+    pub fn as_str<'a>(event: &Self) -> &'a str {
+        match event {
+            Self::EmbedStarted => "embed.started",
+            Self::EmbedProgress => "embed.progress",
+            Self::EmbedCompleted => "embed.completed",
+            Self::EmbedFailed => "embed.failed",
+        }
+    }
+
+    // Completed events keep going to the endpoint the webapp already handles
+    fn path<'a>(event: &str) -> &'a str {
+        match event {
+            "embed.completed" => "/webhook/embed-successful",
+            _ => "/webhook/embed-event",
+        }
+    }
+}
 
-    // Create a client instance
-    let client = Client::new();
+/// Adds an event to the webhook outbox. Delivery happens asynchronously in the dispatcher so that
+/// the event survives the webapp being unavailable or the proxy restarting.
+pub async fn dispatch_event(
+    mongo: &Database,
+    datasource_id: &str,
+    event: WebhookEvent,
+    payload: Value,
+) -> Result<(), anyhow::Error> {
+    let payload = match bson::to_bson(&payload)? {
+        bson::Bson::Document(document) => document,
+        _ => return Err(anyhow!("Webhook payload must be a JSON object")),
+    };
+    insert_webhook_event(mongo, datasource_id, WebhookEvent::as_str(&event), payload).await
+}
 
-    // Make the POST request
-    let res = client.post(&url)
-        .json(&body)
-        .send()
-        .await?;
+/// Signs `<timestamp>.<body>` with HMAC-SHA256 using the shared webhook secret
+pub fn sign_payload(secret: &str, timestamp: u64, body: &str) -> Result<String, anyhow::Error> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|e| anyhow!("Invalid webhook secret. Error: {}", e))?;
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
 
-    // Check if the request was successful
+async fn deliver_event(client: &Client, event: &WebhookOutboxEvent) -> Result<(), anyhow::Error> {
+    let global_data = GLOBAL_DATA.read().await;
+    let url = format!(
+        "http://{}:{}{}",
+        global_data.webapp_host,
+        global_data.webapp_port,
+        WebhookEvent::path(event.event.as_str())
+    );
+    let body = json!({
+        "id": event.id.to_hex(),
+        "event": event.event,
+        "datasourceId": event.datasource_id.to_hex(),
+        "data": event.payload,
+        "createdDate": event.created_date.timestamp_millis(),
+    })
+    .to_string();
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut request = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header("X-Webhook-Id", event.id.to_hex())
+        .header("X-Webhook-Timestamp", timestamp.to_string());
+    if !global_data.webhook_secret.is_empty() {
+        let signature = sign_payload(global_data.webhook_secret.as_str(), timestamp, &body)?;
+        request = request.header("X-Webhook-Signature", format!("sha256={}", signature));
+    }
+    let res = request.body(body).send().await?;
     if res.status().is_success() {
         Ok(())
     } else {
         Err(anyhow!("Failed to notify webapp. Status: {}", res.status()))
     }
 }
+
+fn webhook_client() -> Client {
+    Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_default()
+}
+
+/// Claims and delivers the next due event, returning whether there was one. Failed deliveries are
+/// retried with exponential backoff until `WEBHOOK_MAX_ATTEMPTS` is reached.
+async fn dispatch_due_event(
+    client: &Client,
+    mongo: &Database,
+    max_attempts: i32,
+) -> anyhow::Result<bool> {
+    let event = match claim_due_webhook_event(mongo, DELIVERY_LEASE_MILLIS).await? {
+        Some(event) => event,
+        None => return Ok(false),
+    };
+    match deliver_event(client, &event).await {
+        Ok(()) => {
+            log::debug!("Delivered webhook event {} ({})", event.id, event.event);
+            if let Err(e) = mark_webhook_event_delivered(mongo, event.id).await {
+                log::error!("{}", e)
+            }
+        }
+        Err(e) => {
+            let attempts = event.attempts + 1;
+            let next_attempt_date = if attempts >= max_attempts {
+                log::error!(
+                    "Giving up on webhook event {} after {} attempts. Error: {}",
+                    event.id,
+                    attempts,
+                    e
+                );
+                None
+            } else {
+                let delay =
+                    (INITIAL_RETRY_DELAY_SECS << (attempts - 1).min(20)).min(MAX_RETRY_DELAY_SECS);
+                log::warn!(
+                    "Delivery of webhook event {} failed, retrying in {}s. Error: {}",
+                    event.id,
+                    delay,
+                    e
+                );
+                Some(bson::DateTime::from_millis(
+                    bson::DateTime::now().timestamp_millis() + delay * 1000,
+                ))
+            };
+            if let Err(e) =
+                reschedule_webhook_event(mongo, event.id, next_attempt_date, e.to_string()).await
+            {
+                log::error!("{}", e)
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Delivers due events from the outbox until shutdown is requested
+pub async fn run_webhook_dispatcher(mongo_client: Arc<RwLock<Database>>) {
+    let client = webhook_client();
+    let max_attempts = GLOBAL_DATA.read().await.webhook_max_attempts;
+    while !is_shutting_down() {
+        let mongo = mongo_client.read().await;
+        match dispatch_due_event(&client, &mongo, max_attempts).await {
+            Ok(true) => {}
+            Ok(false) => {
+                drop(mongo);
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                drop(mongo);
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// Delivers every event that is due, for use on shutdown once the workers have stopped adding
+/// events. Events that fail are left in the outbox for the next instance to retry.
+pub async fn flush_webhook_events(mongo_client: Arc<RwLock<Database>>) {
+    let client = webhook_client();
+    let max_attempts = GLOBAL_DATA.read().await.webhook_max_attempts;
+    let mongo = mongo_client.read().await;
+    loop {
+        match dispatch_due_event(&client, &mongo, max_attempts).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                log::error!("{}", e);
+                break;
+            }
+        }
+    }
+}
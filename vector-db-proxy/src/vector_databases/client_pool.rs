@@ -0,0 +1,103 @@
+use crate::adaptors::mongo::models::VectorDbs;
+use crate::adaptors::mongo::queries::get_vector_db_details;
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::vector_databases::credentials::{rotate_credential, CredentialKeys};
+use crate::vector_databases::vector_database::{VectorDatabase, VectorDbClient};
+use anyhow::Result;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use mongodb::Database;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+struct PooledClient {
+    client: Arc<RwLock<dyn VectorDatabase>>,
+    // `updatedAt` of the `vectordbs` record the client was built from
+    updated_at: DateTime,
+    checked_at: Instant,
+}
+
+// Clients of BYO vector databases, by `vector_db_id`
+static BYO_VECTOR_DB_CLIENTS: Lazy<RwLock<HashMap<ObjectId, PooledClient>>> =
+    Lazy::new(|| RwLock::new(HashMap::new()));
+
+/// Builds a client with the decrypted API key, which is only ever held in memory. A key that is
+/// still plaintext or encrypted with an old key is encrypted with the current one.
+async fn build_client(
+    mongo: &Database,
+    vector_db: &VectorDbs,
+) -> Result<Arc<RwLock<dyn VectorDatabase>>> {
+    let keys = CredentialKeys::from_config().await?;
+    let api_key = match vector_db.apiKey.as_ref() {
+        Some(stored_api_key) => Some(keys.decrypt(stored_api_key)?),
+        None => None,
+    };
+    if let Err(e) = rotate_credential(mongo, &keys, vector_db).await {
+        log::warn!(
+            "Could not re-encrypt the credentials of vector DB {}. Error: {}",
+            vector_db._id,
+            e
+        );
+    }
+    let vector_db_config = VectorDbClient {
+        vector_db_type: vector_db.r#type.clone(),
+        url: vector_db.url.clone(),
+        api_key,
+    };
+    log::debug!("New credentials: {:?}", vector_db_config);
+    Ok(vector_db_config.build_vector_db_client().await)
+}
+
+/// Returns the client of a BYO vector database. A pooled client is reused without looking at
+/// Mongo until it is older than `VECTOR_DB_CLIENT_TTL_SECS`. After that the `vectordbs` record is
+/// looked up again, and the client is only rebuilt if the record has been updated since.
+pub async fn get_pooled_vector_db_client(
+    mongo: &Database,
+    vector_db_id: ObjectId,
+) -> Option<Arc<RwLock<dyn VectorDatabase>>> {
+    let ttl = Duration::from_secs(GLOBAL_DATA.read().await.vector_db_client_ttl_secs);
+    if let Some(pooled_client) = BYO_VECTOR_DB_CLIENTS.read().await.get(&vector_db_id) {
+        if pooled_client.checked_at.elapsed() < ttl {
+            return Some(Arc::clone(&pooled_client.client));
+        }
+    }
+    let vector_db = match get_vector_db_details(mongo, vector_db_id).await {
+        Some(vector_db) => vector_db,
+        None => {
+            log::error!("There was an error looking up vector DB config in database");
+            BYO_VECTOR_DB_CLIENTS.write().await.remove(&vector_db_id);
+            return None;
+        }
+    };
+    if let Some(pooled_client) = BYO_VECTOR_DB_CLIENTS.write().await.get_mut(&vector_db_id) {
+        if pooled_client.updated_at == vector_db.updatedAt {
+            pooled_client.checked_at = Instant::now();
+            return Some(Arc::clone(&pooled_client.client));
+        }
+    }
+    log::debug!("Building client for vector DB {}", vector_db_id);
+    let client = match build_client(mongo, &vector_db).await {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!(
+                "Could not build client for vector DB {}. Error: {}",
+                vector_db_id,
+                e
+            );
+            BYO_VECTOR_DB_CLIENTS.write().await.remove(&vector_db_id);
+            return None;
+        }
+    };
+    BYO_VECTOR_DB_CLIENTS.write().await.insert(
+        vector_db_id,
+        PooledClient {
+            client: Arc::clone(&client),
+            updated_at: vector_db.updatedAt,
+            checked_at: Instant::now(),
+        },
+    );
+    Some(client)
+}
@@ -0,0 +1,177 @@
+use crate::adaptors::mongo::models::VectorDbs;
+use crate::adaptors::mongo::queries::{get_vector_dbs, replace_vector_db_api_key};
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::utils::secret::SecretString;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use mongodb::Database;
+
+// Encrypted credentials are stored as `enc:v1:<key id>:<base64 of nonce and ciphertext>`, which
+// is also what the webapp writes
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const NONCE_LENGTH: usize = 12;
+const KEY_LENGTH: usize = 32;
+
+/// The keys BYO vector DB credentials are encrypted with, parsed from `VECTOR_DB_ENCRYPTION_KEYS`
+/// (`<key id>:<base64 AES-256 key>,...`). The first key encrypts, and every key can decrypt, so a
+/// key is rotated by putting a new key first and keeping the old one until
+/// `--rotate-credentials` has re-encrypted every record.
+pub struct CredentialKeys {
+    keys: Vec<(String, Aes256Gcm)>,
+}
+
+impl CredentialKeys {
+    pub fn parse(keys: &str) -> Result<Self> {
+        let mut parsed_keys: Vec<(String, Aes256Gcm)> = vec![];
+        for key in keys.split(',').map(|k| k.trim()).filter(|k| !k.is_empty()) {
+            let (key_id, encoded_key) = key.split_once(':').ok_or(anyhow!(
+                "expected <key id>:<base64 key>, got a key without an id"
+            ))?;
+            if key_id.is_empty() {
+                return Err(anyhow!("key ids can not be empty"));
+            }
+            if parsed_keys.iter().any(|(id, _)| id == key_id) {
+                return Err(anyhow!("key id `{}` is used more than once", key_id));
+            }
+            let key_bytes = STANDARD
+                .decode(encoded_key)
+                .map_err(|e| anyhow!("key `{}` is not valid base64: {}", key_id, e))?;
+            if key_bytes.len() != KEY_LENGTH {
+                return Err(anyhow!(
+                    "key `{}` must be {} bytes, got {}",
+                    key_id,
+                    KEY_LENGTH,
+                    key_bytes.len()
+                ));
+            }
+            let cipher = Aes256Gcm::new_from_slice(&key_bytes)
+                .map_err(|_| anyhow!("key `{}` is not a valid AES-256 key", key_id))?;
+            parsed_keys.push((key_id.to_string(), cipher));
+        }
+        Ok(CredentialKeys { keys: parsed_keys })
+    }
+
+    pub async fn from_config() -> Result<Self> {
+        Self::parse(GLOBAL_DATA.read().await.vector_db_encryption_keys.as_str())
+    }
+
+    pub fn is_configured(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    pub fn encrypt(&self, credential: &SecretString) -> Result<String> {
+        let (key_id, cipher) = self
+            .keys
+            .first()
+            .ok_or(anyhow!("No encryption key is configured"))?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, credential.expose().as_bytes())
+            .map_err(|_| anyhow!("Could not encrypt credential with key `{}`", key_id))?;
+        let mut payload = nonce.to_vec();
+        payload.extend(ciphertext);
+        Ok(format!(
+            "{}{}:{}",
+            ENCRYPTED_PREFIX,
+            key_id,
+            STANDARD.encode(payload)
+        ))
+    }
+
+    /// Decrypts a stored credential. Credentials stored before encryption was configured are
+    /// returned as they are.
+    pub fn decrypt(&self, stored_credential: &SecretString) -> Result<SecretString> {
+        let Some(encrypted) = stored_credential.expose().strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored_credential.clone());
+        };
+        let (key_id, encoded_payload) = encrypted
+            .split_once(':')
+            .ok_or(anyhow!("Encrypted credential is malformed"))?;
+        let (_, cipher) = self
+            .keys
+            .iter()
+            .find(|(id, _)| id == key_id)
+            .ok_or(anyhow!(
+                "Credential is encrypted with key `{}`, which is not configured",
+                key_id
+            ))?;
+        let payload = STANDARD
+            .decode(encoded_payload)
+            .map_err(|_| anyhow!("Encrypted credential is malformed"))?;
+        if payload.len() <= NONCE_LENGTH {
+            return Err(anyhow!("Encrypted credential is malformed"));
+        }
+        let (nonce, ciphertext) = payload.split_at(NONCE_LENGTH);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Could not decrypt credential with key `{}`", key_id))?;
+        let plaintext = String::from_utf8(plaintext)
+            .map_err(|_| anyhow!("Decrypted credential is not valid UTF-8"))?;
+        Ok(SecretString::new(plaintext))
+    }
+
+    /// Whether a stored credential should be encrypted again: it is still plaintext, or it is
+    /// encrypted with a key other than the current one
+    pub fn needs_rotation(&self, stored_credential: &SecretString) -> bool {
+        match self.keys.first() {
+            Some((key_id, _)) => !stored_credential
+                .expose()
+                .starts_with(format!("{}{}:", ENCRYPTED_PREFIX, key_id).as_str()),
+            None => false,
+        }
+    }
+}
+
+/// Encrypts the API key of a vector DB with the current key if it is not already. The record is
+/// only written if its key has not been changed in the meantime.
+pub async fn rotate_credential(
+    mongo: &Database,
+    keys: &CredentialKeys,
+    vector_db: &VectorDbs,
+) -> Result<bool> {
+    let Some(stored_api_key) = vector_db.apiKey.as_ref().filter(|k| !k.is_empty()) else {
+        return Ok(false);
+    };
+    if !keys.needs_rotation(stored_api_key) {
+        return Ok(false);
+    }
+    let api_key = keys.decrypt(stored_api_key)?;
+    let encrypted_api_key = keys.encrypt(&api_key)?;
+    replace_vector_db_api_key(
+        mongo,
+        vector_db._id,
+        stored_api_key.expose(),
+        encrypted_api_key.as_str(),
+    )
+    .await
+}
+
+/// Encrypts the API key of every vector DB with the current key. Returns how many records were
+/// re-encrypted and how many could not be.
+pub async fn rotate_vector_db_credentials(mongo: &Database) -> Result<(usize, usize)> {
+    let keys = CredentialKeys::from_config().await?;
+    if !keys.is_configured() {
+        return Err(anyhow!(
+            "VECTOR_DB_ENCRYPTION_KEYS must be set to rotate credentials"
+        ));
+    }
+    let (mut rotated, mut failed) = (0, 0);
+    for vector_db in get_vector_dbs(mongo).await? {
+        match rotate_credential(mongo, &keys, &vector_db).await {
+            Ok(true) => rotated += 1,
+            Ok(false) => {}
+            Err(e) => {
+                log::error!(
+                    "Could not rotate the credentials of vector DB {}. Error: {}",
+                    vector_db._id,
+                    e
+                );
+                failed += 1;
+            }
+        }
+    }
+    Ok((rotated, failed))
+}
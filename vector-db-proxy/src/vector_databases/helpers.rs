@@ -1,6 +1,7 @@
 use crate::adaptors::mongo::models::DataSources;
-use crate::adaptors::mongo::queries::get_vector_db_details;
-use crate::vector_databases::vector_database::{VectorDatabase, VectorDbClient};
+use crate::vector_databases::client_pool::get_pooled_vector_db_client;
+use crate::vector_databases::vector_database::VectorDatabase;
+use mongodb::bson::oid::ObjectId;
 use mongodb::Database;
 use prost_types::value::Kind;
 use prost_types::{ListValue, Struct, Value as ProstValue};
@@ -100,6 +101,13 @@ fn serde_to_prost(serde_value: &Value) -> ProstValue {
     ProstValue { kind }
 }
 
+pub async fn get_vector_db_client(
+    mongo: &Database,
+    vector_db_id: ObjectId,
+) -> Option<Arc<RwLock<dyn VectorDatabase>>> {
+    get_pooled_vector_db_client(mongo, vector_db_id).await
+}
+
 pub async fn check_byo_vector_database(
     datasource: DataSources,
     mongo: &Database,
@@ -109,19 +117,8 @@ pub async fn check_byo_vector_database(
             "There's a BYO vector DB associated with the Datasource: {}",
             datasource.id
         );
-        log::debug!("Updating vector DB credentials with BYO creds...");
-        if let Some(vector_db) = get_vector_db_details(&mongo, vector_db_id).await {
-            let vector_db_config = VectorDbClient {
-                vector_db_type: vector_db.r#type,
-                url: vector_db.url,
-                api_key: vector_db.apiKey,
-            };
-            log::debug!("New credentials: {:?}", vector_db_config);
-            Some(vector_db_config.build_vector_db_client().await)
-        } else {
-            log::error!("There was an error looking up vector DB config in database");
-            None
-        }
+        log::debug!("Using the client of the BYO vector DB...");
+        get_vector_db_client(mongo, vector_db_id).await
     } else {
         log::warn!(
             "There was no vector DB ID associated with the datasource: {}",
@@ -0,0 +1,215 @@
+use crate::utils::metrics::{count_vector_db_error, observe_vector_db_operation};
+use crate::vector_databases::error::VectorDatabaseError;
+use crate::vector_databases::models::*;
+use crate::vector_databases::vector_database::VectorDatabase;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+
+/// Wraps a vector database client, recording the latency and errors of every operation against
+/// the name of its backend
+pub struct MeteredVectorDatabase<T: VectorDatabase> {
+    backend: &'static str,
+    inner: T,
+}
+
+impl<T: VectorDatabase> MeteredVectorDatabase<T> {
+    pub fn new(backend: &'static str, inner: T) -> Self {
+        MeteredVectorDatabase { backend, inner }
+    }
+}
+
+/// Backends report some failures as a status rather than an error, which count as errors as well
+async fn observe_status_operation<F>(
+    backend: &str,
+    operation: &str,
+    future: F,
+) -> Result<VectorDatabaseStatus, VectorDatabaseError>
+where
+    F: Future<Output = Result<VectorDatabaseStatus, VectorDatabaseError>>,
+{
+    let result = observe_vector_db_operation(backend, operation, future).await;
+    if matches!(&result, Ok(status) if !matches!(status, VectorDatabaseStatus::Ok)) {
+        count_vector_db_error(backend, operation);
+    }
+    result
+}
+
+#[async_trait]
+impl<T: VectorDatabase> VectorDatabase for MeteredVectorDatabase<T> {
+    async fn get_list_of_collections(&self) -> Result<Vec<String>, VectorDatabaseError> {
+        observe_vector_db_operation(
+            self.backend,
+            "get_list_of_collections",
+            self.inner.get_list_of_collections(),
+        )
+        .await
+    }
+
+    async fn check_collection_exists(
+        &self,
+        search_request: SearchRequest,
+    ) -> Result<CollectionsResult, VectorDatabaseError> {
+        observe_vector_db_operation(
+            self.backend,
+            "check_collection_exists",
+            self.inner.check_collection_exists(search_request),
+        )
+        .await
+    }
+
+    async fn create_collection(
+        &self,
+        collection_create: CollectionCreate,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError> {
+        observe_status_operation(
+            self.backend,
+            "create_collection",
+            self.inner.create_collection(collection_create),
+        )
+        .await
+    }
+
+    async fn delete_collection(
+        &self,
+        search_request: SearchRequest,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError> {
+        observe_status_operation(
+            self.backend,
+            "delete_collection",
+            self.inner.delete_collection(search_request),
+        )
+        .await
+    }
+
+    async fn insert_point(
+        &self,
+        search_request: SearchRequest,
+        point: Point,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError> {
+        observe_status_operation(
+            self.backend,
+            "insert_point",
+            self.inner.insert_point(search_request, point),
+        )
+        .await
+    }
+
+    async fn delete_point(
+        &self,
+        search_request: SearchRequest,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError> {
+        observe_status_operation(
+            self.backend,
+            "delete_point",
+            self.inner.delete_point(search_request),
+        )
+        .await
+    }
+
+    async fn delete_points_by_id(
+        &self,
+        search_request: SearchRequest,
+        ids: Vec<String>,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError> {
+        observe_status_operation(
+            self.backend,
+            "delete_points_by_id",
+            self.inner.delete_points_by_id(search_request, ids),
+        )
+        .await
+    }
+
+    async fn bulk_insert_points(
+        &self,
+        search_request: SearchRequest,
+        points: Vec<Point>,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError> {
+        observe_status_operation(
+            self.backend,
+            "bulk_insert_points",
+            self.inner.bulk_insert_points(search_request, points),
+        )
+        .await
+    }
+
+    async fn get_collection_info(
+        &self,
+        search_request: SearchRequest,
+    ) -> Result<Option<CollectionMetadata>, VectorDatabaseError> {
+        observe_vector_db_operation(
+            self.backend,
+            "get_collection_info",
+            self.inner.get_collection_info(search_request),
+        )
+        .await
+    }
+
+    async fn get_storage_size(
+        &self,
+        search_request: SearchRequest,
+        vector_length: usize,
+    ) -> Result<Option<StorageSize>, VectorDatabaseError> {
+        observe_vector_db_operation(
+            self.backend,
+            "get_storage_size",
+            self.inner.get_storage_size(search_request, vector_length),
+        )
+        .await
+    }
+
+    async fn scroll_points(
+        &self,
+        search_request: SearchRequest,
+    ) -> Result<Vec<ScrollResults>, VectorDatabaseError> {
+        observe_vector_db_operation(
+            self.backend,
+            "scroll_points",
+            self.inner.scroll_points(search_request),
+        )
+        .await
+    }
+
+    async fn get_points_page(
+        &self,
+        search_request: SearchRequest,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<(Vec<Point>, Option<String>), VectorDatabaseError> {
+        observe_vector_db_operation(
+            self.backend,
+            "get_points_page",
+            self.inner.get_points_page(search_request, offset, limit),
+        )
+        .await
+    }
+
+    async fn get_point_payload(
+        &self,
+        search_request: SearchRequest,
+    ) -> Result<Option<HashMap<String, Value>>, VectorDatabaseError> {
+        observe_vector_db_operation(
+            self.backend,
+            "get_point_payload",
+            self.inner.get_point_payload(search_request),
+        )
+        .await
+    }
+
+    async fn similarity_search(
+        &self,
+        search_request: SearchRequest,
+    ) -> Result<Vec<SearchResult>, VectorDatabaseError> {
+        observe_vector_db_operation(
+            self.backend,
+            "similarity_search",
+            self.inner.similarity_search(search_request),
+        )
+        .await
+    }
+
+    async fn display_config(&self) {
+        self.inner.display_config().await
+    }
+}
@@ -0,0 +1,419 @@
+use crate::adaptors::mongo::models::{DataSources, VectorDatabaseType};
+use crate::adaptors::mongo::queries::{get_model, get_vector_db_details, set_datasource_vector_db};
+use crate::data::helpers::hash_string_to_uuid;
+use crate::data::quotas::{check_quota, record_usage, UsageRequest};
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::vector_databases::helpers::get_vector_db_client;
+use crate::vector_databases::models::{
+    CollectionCreate, Distance, MigrationRequest, MigrationSummary, Point, Region, SearchRequest,
+    SearchType, VectorDatabaseStatus,
+};
+use crate::vector_databases::vector_database::{default_vector_db_client, VectorDatabase};
+use anyhow::{anyhow, Result};
+use mongodb::bson::oid::ObjectId;
+use mongodb::Database;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+const DEFAULT_MIGRATION_BATCH_SIZE: u32 = 100;
+
+async fn resolve_vector_db(
+    mongo: &Database,
+    vector_db_id: Option<ObjectId>,
+) -> Result<(VectorDatabaseType, Arc<RwLock<dyn VectorDatabase>>)> {
+    match vector_db_id {
+        Some(id) => {
+            let vector_db = get_vector_db_details(mongo, id)
+                .await
+                .ok_or(anyhow!("No vector DB found with the ID: {}", id))?;
+            let client = get_vector_db_client(mongo, id)
+                .await
+                .ok_or(anyhow!("Could not build a client for vector DB: {}", id))?;
+            Ok((vector_db.r#type, client))
+        }
+        None => {
+            let vector_database = GLOBAL_DATA.read().await.vector_database.clone();
+            Ok((
+                VectorDatabaseType::from(vector_database),
+                default_vector_db_client().await,
+            ))
+        }
+    }
+}
+
+// Pinecone only supports cosine, euclidean and dot product so anything else can not be migrated
+fn map_distance(distance: Distance, target: &VectorDatabaseType) -> Result<Distance> {
+    match (target, distance) {
+        (_, Distance::UnknownDistance) => Ok(Distance::default()),
+        (VectorDatabaseType::pinecone, Distance::Manhattan) => Err(anyhow!(
+            "Manhattan distance is not supported by Pinecone. Can not migrate collection"
+        )),
+        (_, d) => Ok(d),
+    }
+}
+
+// Qdrant only accepts UUIDs as point IDs, so any other ID (e.g. one chosen by a Pinecone client)
+// is hashed into a stable UUID and the original is kept in the payload
+fn prepare_point_for_target(mut point: Point, target: &VectorDatabaseType, salt: &str) -> Point {
+    if let VectorDatabaseType::qdrant = target {
+        if let Some(Value::String(id)) = point.index.clone() {
+            let id = id.trim_matches('"').to_string();
+            if Uuid::parse_str(id.as_str()).is_err() {
+                point.index = Some(Value::String(hash_string_to_uuid(salt, id.as_str())));
+                point
+                    .payload
+                    .get_or_insert_with(Default::default)
+                    .insert("ac_source_id".to_string(), Value::String(id));
+            }
+        }
+    }
+    point
+}
+
+// The ID a point is stored under in the target, as set by `prepare_point_for_target`
+fn point_id(point: &Point) -> Option<String> {
+    match &point.index {
+        Some(Value::String(id)) => Some(id.trim_matches('"').to_string()),
+        Some(Value::Number(id)) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+struct CopyRequest<'a> {
+    source_request: &'a SearchRequest,
+    target_request: &'a SearchRequest,
+    target_type: &'a VectorDatabaseType,
+    batch_size: u32,
+    hashing_salt: &'a str,
+}
+
+/// Copies every point of the source collection into the target one, page by page, returning how
+/// many were copied and the IDs they were stored under. The clients are only locked for a page at
+/// a time, so that they are not held up for the whole copy.
+async fn copy_points(
+    source_client: &Arc<RwLock<dyn VectorDatabase>>,
+    target_client: &Arc<RwLock<dyn VectorDatabase>>,
+    copy_request: &CopyRequest<'_>,
+    datasource_id: &str,
+) -> Result<(usize, HashSet<String>)> {
+    let mut copied_points = 0;
+    let mut copied_ids = HashSet::new();
+    let mut offset: Option<String> = None;
+    loop {
+        let (points, next_offset) = source_client
+            .read()
+            .await
+            .get_points_page(
+                copy_request.source_request.clone(),
+                offset.clone(),
+                copy_request.batch_size,
+            )
+            .await?;
+        if !points.is_empty() {
+            let number_of_points = points.len();
+            let points: Vec<Point> = points
+                .into_iter()
+                .map(|p| {
+                    prepare_point_for_target(p, copy_request.target_type, copy_request.hashing_salt)
+                })
+                .collect();
+            let ids: Vec<String> = points.iter().filter_map(point_id).collect();
+            match target_client
+                .read()
+                .await
+                .bulk_insert_points(copy_request.target_request.clone(), points)
+                .await?
+            {
+                VectorDatabaseStatus::Ok => {
+                    copied_points += number_of_points;
+                    copied_ids.extend(ids);
+                }
+                status => {
+                    return Err(anyhow!(
+                        "Migration of datasource {} failed after {} points. Status: {:?}",
+                        datasource_id,
+                        copied_points,
+                        status
+                    ))
+                }
+            }
+            log::debug!(
+                "Migrated {} points for datasource {}",
+                copied_points,
+                datasource_id
+            );
+        }
+        match next_offset {
+            Some(next) => offset = Some(next),
+            None => break,
+        }
+    }
+    Ok((copied_points, copied_ids))
+}
+
+/// Deletes from the target the points of the first copy that the catch up copy no longer found in
+/// the source, because they were deleted while the first copy ran
+async fn delete_stale_points(
+    target_client: &Arc<RwLock<dyn VectorDatabase>>,
+    copy_request: &CopyRequest<'_>,
+    stale_ids: Vec<String>,
+) -> Result<usize> {
+    for ids in stale_ids.chunks(copy_request.batch_size as usize) {
+        match target_client
+            .read()
+            .await
+            .delete_points_by_id(copy_request.target_request.clone(), ids.to_vec())
+            .await?
+        {
+            VectorDatabaseStatus::Ok => {}
+            status => {
+                return Err(anyhow!(
+                    "Could not delete points removed from the source. Status: {:?}",
+                    status
+                ))
+            }
+        }
+    }
+    Ok(stale_ids.len())
+}
+
+/// Copies every point of a datasource's collection from the vector database it currently lives
+/// in to another one, then points the datasource at the new vector database and copies once more
+/// to catch up with the records embedded or deleted in the meantime. The datasource is left
+/// untouched if any batch of the first copy fails to be written.
+pub async fn migrate_collection(
+    mongo: &Database,
+    datasource: DataSources,
+    migration_request: MigrationRequest,
+) -> Result<MigrationSummary> {
+    let datasource_id = datasource.id.to_string();
+    let target_vector_db_id = migration_request
+        .target_vector_db_id
+        .as_deref()
+        .map(ObjectId::from_str)
+        .transpose()?;
+    if target_vector_db_id == datasource.vector_db_id {
+        return Err(anyhow!(
+            "Datasource {} is already stored in the target vector database",
+            datasource_id
+        ));
+    }
+    let (source_type, source_client) = resolve_vector_db(mongo, datasource.vector_db_id).await?;
+    let (target_type, target_client) = resolve_vector_db(mongo, target_vector_db_id).await?;
+    log::info!(
+        "Migrating datasource {} from {} to {}",
+        datasource_id,
+        source_type,
+        target_type
+    );
+
+    let mut source_request = SearchRequest::new(
+        SearchType::Collection,
+        datasource
+            .collection_name
+            .clone()
+            .unwrap_or(datasource_id.clone()),
+    );
+    source_request.byo_vector_db = datasource.byo_vector_db;
+    source_request.namespace = datasource.namespace.clone();
+    source_request.region = datasource.region.clone().map(|r| Region::from_str(&r));
+
+    let model = get_model(mongo, datasource_id.as_str())
+        .await?
+        .ok_or(anyhow!(
+            "There was no embedding model associated with datasource: {}",
+            datasource_id
+        ))?;
+    let dimensions = model.embeddingLength as usize;
+    let (source_metric, source_points) = {
+        let source_client = source_client.read().await;
+        let source_metric = match source_client
+            .check_collection_exists(source_request.clone())
+            .await
+            .ok()
+            .and_then(|result| result.collection_metadata)
+        {
+            Some(metadata) => metadata.metric,
+            None => source_client
+                .get_collection_info(source_request.clone())
+                .await?
+                .and_then(|info| info.metric),
+        };
+        let source_points = source_client
+            .get_storage_size(source_request.clone(), dimensions)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|storage_size| storage_size.points_count)
+            .unwrap_or(0) as usize;
+        (source_metric, source_points)
+    };
+    let distance = map_distance(source_metric.unwrap_or_default(), &target_type)?;
+
+    // The points are stored twice until the source collection is dropped. Checked before the
+    // target collection is created, so that a migration over quota leaves nothing behind, and
+    // surfaced as a `QuotaExceeded` error so that the route can answer with the quota status.
+    let team_id = datasource.team_id.to_string();
+    let usage = UsageRequest::storage(source_points, dimensions);
+    if let Some(quota_exceeded) = check_quota(mongo, team_id.as_str(), &usage).await {
+        return Err(quota_exceeded.into());
+    }
+
+    // For Pinecone a collection maps to a namespace inside an index. BYO indexes are addressed by
+    // name while managed ones are named after their region.
+    let target_is_byo = target_vector_db_id.is_some();
+    let target_collection = migration_request
+        .collection_name
+        .clone()
+        .or(datasource.collection_name.clone())
+        .unwrap_or(datasource_id.clone());
+    let target_namespace = match target_type {
+        VectorDatabaseType::pinecone => Some(
+            migration_request
+                .namespace
+                .clone()
+                .or(datasource.namespace.clone())
+                .unwrap_or(datasource_id.clone()),
+        ),
+        _ => None,
+    };
+    let region = migration_request
+        .region
+        .clone()
+        .or(datasource.region.clone())
+        .unwrap_or(Region::to_str(Region::default()).to_string());
+    let cloud = migration_request
+        .cloud
+        .clone()
+        .or(datasource.cloud.clone())
+        .unwrap_or("aws".to_string());
+
+    let mut target_request = SearchRequest::new(SearchType::Collection, target_collection.clone());
+    target_request.byo_vector_db = Some(target_is_byo);
+    target_request.namespace = target_namespace.clone();
+    target_request.region = Some(Region::from_str(region.as_str()));
+
+    let mut collection_create = CollectionCreate::new(
+        target_collection.clone(),
+        model.embeddingLength as usize,
+        distance,
+        region,
+        cloud,
+        if target_is_byo {
+            target_collection.clone()
+        } else {
+            String::new()
+        },
+    );
+    collection_create.namespace = target_namespace.clone();
+    let create_status = {
+        let target_client = target_client.read().await;
+        let target_exists = match target_client
+            .check_collection_exists(target_request.clone())
+            .await
+        {
+            Ok(result) => matches!(result.status, VectorDatabaseStatus::Ok),
+            Err(_) => false,
+        };
+        if target_exists {
+            VectorDatabaseStatus::Ok
+        } else {
+            target_client.create_collection(collection_create).await?
+        }
+    };
+    match create_status {
+        VectorDatabaseStatus::Ok => {}
+        status => {
+            return Err(anyhow!(
+                "Could not create target collection '{}'. Status: {:?}",
+                target_collection,
+                status
+            ))
+        }
+    }
+
+    let hashing_salt = GLOBAL_DATA.read().await.hashing_salt.clone();
+    let batch_size = migration_request
+        .batch_size
+        .filter(|s| *s > 0)
+        .unwrap_or(DEFAULT_MIGRATION_BATCH_SIZE);
+    let copy_request = CopyRequest {
+        source_request: &source_request,
+        target_request: &target_request,
+        target_type: &target_type,
+        batch_size,
+        hashing_salt: hashing_salt.as_str(),
+    };
+    let (migrated_points, migrated_ids) = copy_points(
+        &source_client,
+        &target_client,
+        &copy_request,
+        datasource_id.as_str(),
+    )
+    .await?;
+    record_usage(
+        mongo,
+        team_id.as_str(),
+        &UsageRequest::storage(migrated_points, dimensions),
+    )
+    .await;
+
+    set_datasource_vector_db(
+        mongo,
+        datasource_id.as_str(),
+        target_vector_db_id,
+        target_collection.as_str(),
+        target_namespace.clone(),
+    )
+    .await?;
+    // Records embedded while the copy ran were still written to the source. Copying again once
+    // the datasource points at the target picks them up, and rewrites the points that were
+    // already copied under the same IDs. Points of the first copy that are no longer in the
+    // source were deleted from it meanwhile, so they are deleted from the target as well.
+    let catch_up = async {
+        let (caught_up_points, caught_up_ids) = copy_points(
+            &source_client,
+            &target_client,
+            &copy_request,
+            datasource_id.as_str(),
+        )
+        .await?;
+        let stale_ids: Vec<String> = migrated_ids.difference(&caught_up_ids).cloned().collect();
+        let deleted_points = delete_stale_points(&target_client, &copy_request, stale_ids).await?;
+        Ok::<(usize, usize), anyhow::Error>((caught_up_points, deleted_points))
+    };
+    let (caught_up_points, deleted_points) = catch_up.await.map_err(|e| {
+        log::error!(
+            "Datasource {} was moved but the catch up copy failed. Error: {}",
+            datasource_id,
+            e
+        );
+        anyhow!(
+            "Datasource {} was moved to the target vector database but copying the points \
+            written during the migration failed. Error: {}",
+            datasource_id,
+            e
+        )
+    })?;
+    log::info!(
+        "Finished migrating {} points for datasource {}, {} of them again after the switch and {} \
+        deleted from the source meanwhile",
+        migrated_points,
+        datasource_id,
+        caught_up_points,
+        deleted_points
+    );
+
+    Ok(MigrationSummary {
+        source_collection: source_request.collection,
+        target_collection,
+        target_namespace,
+        migrated_points,
+        caught_up_points,
+        deleted_points,
+    })
+}
@@ -1,5 +1,9 @@
+pub mod client_pool;
+pub mod credentials;
 pub mod error;
 pub mod helpers;
+pub mod metered;
+pub mod migration;
 pub mod models;
 pub mod utils;
 pub mod vector_database;
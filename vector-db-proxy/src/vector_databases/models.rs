@@ -412,7 +412,7 @@ impl From<qdrant_client::qdrant::CollectionInfo> for VectorDatabaseStatus {
 impl From<FilterConditions> for Metadata {
     fn from(value: FilterConditions) -> Self {
         let mut btree_map = BTreeMap::new();
-        for pattern in value.must.unwrap() {
+        for pattern in value.must.unwrap_or_default() {
             for (k, v) in pattern {
                 btree_map.insert(
                     k,
@@ -422,6 +422,23 @@ impl From<FilterConditions> for Metadata {
                 );
             }
         }
+        // Pinecone expresses negation through the `$ne` operator on the field
+        for pattern in value.must_not.unwrap_or_default() {
+            for (k, v) in pattern {
+                let not_equal = BTreeMap::from([(
+                    "$ne".to_string(),
+                    prost_types::Value {
+                        kind: Some(Kind::StringValue(clean_text(v))),
+                    },
+                )]);
+                btree_map.insert(
+                    k,
+                    prost_types::Value {
+                        kind: Some(Kind::StructValue(Metadata { fields: not_equal })),
+                    },
+                );
+            }
+        }
 
         Self { fields: btree_map }
     }
@@ -479,3 +496,26 @@ impl From<Point> for Vector {
         }
     }
 }
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MigrationRequest {
+    // `None` migrates to the default (managed) vector database
+    pub target_vector_db_id: Option<String>,
+    pub collection_name: Option<String>,
+    pub namespace: Option<String>,
+    pub region: Option<String>,
+    pub cloud: Option<String>,
+    pub batch_size: Option<u32>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct MigrationSummary {
+    pub source_collection: String,
+    pub target_collection: String,
+    pub target_namespace: Option<String>,
+    pub migrated_points: usize,
+    // Points copied again after the datasource was switched to the target
+    pub caught_up_points: usize,
+    // Points deleted from the source while the first copy ran, and so from the target afterwards
+    pub deleted_points: usize,
+}
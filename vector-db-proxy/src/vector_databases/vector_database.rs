@@ -1,13 +1,17 @@
 use crate::adaptors::mongo::models::VectorDatabaseType;
 use crate::adaptors::{pinecone, qdrant};
 use crate::init::env_variables::GLOBAL_DATA;
+use crate::utils::secret::SecretString;
 use crate::vector_databases::error::VectorDatabaseError;
+use crate::vector_databases::metered::MeteredVectorDatabase;
 use crate::vector_databases::models::*;
 use async_trait::async_trait;
 use pinecone_sdk::pinecone::PineconeClient;
 use qdrant_client::client::QdrantClient;
+use serde_json::Value;
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{OnceCell, RwLock};
 
 pub enum VectorDatabases {
     Qdrant(Arc<RwLock<QdrantClient>>),
@@ -50,6 +54,12 @@ pub trait VectorDatabase: Send + Sync {
         &self,
         search_request: SearchRequest,
     ) -> Result<VectorDatabaseStatus, VectorDatabaseError>;
+    /// Deletes the points with the given IDs, unlike `delete_point` which deletes by filter
+    async fn delete_points_by_id(
+        &self,
+        search_request: SearchRequest,
+        ids: Vec<String>,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError>;
     async fn bulk_insert_points(
         &self,
         search_request: SearchRequest,
@@ -68,6 +78,20 @@ pub trait VectorDatabase: Send + Sync {
         &self,
         search_request: SearchRequest,
     ) -> Result<Vec<ScrollResults>, VectorDatabaseError>;
+    /// Returns a single page of points (including vectors and untouched payloads) along with the
+    /// offset of the next page, which is `None` once the collection has been exhausted
+    async fn get_points_page(
+        &self,
+        search_request: SearchRequest,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<(Vec<Point>, Option<String>), VectorDatabaseError>;
+    /// Returns the payload of the point whose ID is set on the search request, or `None` if no
+    /// such point exists
+    async fn get_point_payload(
+        &self,
+        search_request: SearchRequest,
+    ) -> Result<Option<HashMap<String, Value>>, VectorDatabaseError>;
 
     async fn similarity_search(
         &self,
@@ -81,9 +105,19 @@ pub trait VectorDatabase: Send + Sync {
 pub struct VectorDbClient {
     pub vector_db_type: VectorDatabaseType,
     pub url: Option<String>,
-    pub api_key: Option<String>,
+    pub api_key: Option<SecretString>,
 }
+// The default vector database is configured once at startup, so its client is built once
+static DEFAULT_VECTOR_DB_CLIENT: OnceCell<Arc<RwLock<dyn VectorDatabase>>> = OnceCell::const_new();
+
 pub async fn default_vector_db_client() -> Arc<RwLock<dyn VectorDatabase>> {
+    let client = DEFAULT_VECTOR_DB_CLIENT
+        .get_or_init(build_default_vector_db_client)
+        .await;
+    Arc::clone(client)
+}
+
+async fn build_default_vector_db_client() -> Arc<RwLock<dyn VectorDatabase>> {
     let global_data = GLOBAL_DATA.read().await;
     let vector_db = global_data.vector_database.clone();
     let vector_db_type = VectorDatabaseType::from(vector_db);
@@ -93,13 +127,18 @@ pub async fn default_vector_db_client() -> Arc<RwLock<dyn VectorDatabase>> {
     let vector_db_config = VectorDbClient {
         vector_db_type,
         url: Some(vector_db_url),
-        api_key: Some(vector_db_api_key),
+        api_key: Some(SecretString::new(vector_db_api_key)),
     };
 
     vector_db_config.build_vector_db_client().await
 }
 
 impl VectorDbClient {
+    // The SDKs take the API key as a plain string
+    fn exposed_api_key(&self) -> Option<String> {
+        self.api_key.as_ref().map(|k| k.expose().to_string())
+    }
+
     pub async fn build_vector_db_client(&self) -> Arc<RwLock<dyn VectorDatabase>> {
         log::debug!("The incoming credentials are: {:?}", self);
         log::debug!("Building NEW {} vector client", self.vector_db_type);
@@ -110,19 +149,24 @@ impl VectorDbClient {
         {
             "qdrant" => {
                 log::info!("Using Qdrant Vector Database");
-                Arc::new(RwLock::new(
-                    qdrant::client::build_qdrant_client(self.url.clone(), self.api_key.clone())
+                Arc::new(RwLock::new(MeteredVectorDatabase::new(
+                    "qdrant",
+                    qdrant::client::build_qdrant_client(self.url.clone(), self.exposed_api_key())
                         .await
                         .unwrap(),
-                ))
+                )))
             }
             "pinecone" => {
                 log::info!("Using Pinecone Vector Database");
-                Arc::new(RwLock::new(
-                    pinecone::client::build_pinecone_client(self.url.clone(), self.api_key.clone())
-                        .await
-                        .unwrap(),
-                ))
+                Arc::new(RwLock::new(MeteredVectorDatabase::new(
+                    "pinecone",
+                    pinecone::client::build_pinecone_client(
+                        self.url.clone(),
+                        self.exposed_api_key(),
+                    )
+                    .await
+                    .unwrap(),
+                )))
             }
             _ => panic!(
                 "No valid vector database was chosen. Expected one of `qdrant` or `pinecone`.\
@@ -3,6 +3,7 @@
 import { dynamicResponse } from '@dr';
 import { io } from '@socketio';
 import getAirbyteApi, { AirbyteApiType } from 'airbyte/api';
+import { createHmac, timingSafeEqual } from 'crypto';
 import getAirbyteInternalApi from 'airbyte/internal';
 import {
 	getDatasourceByConnectionId,
@@ -16,6 +17,7 @@ import { addNotification } from 'db/notification';
 import * as airbyteSetup from 'lib/airbyte/setup';
 import posthog from 'lib/posthog';
 import { chainValidations } from 'lib/utils/validationutils';
+import VectorDBProxyClient from 'lib/vectorproxy/client';
 import toObjectId from 'misc/toobjectid';
 import { DatasourceStatus } from 'struct/datasource';
 import { CollectionName } from 'struct/db';
@@ -227,7 +229,10 @@ export async function handleSuccessfulSyncWebhook(req, res, next) {
 					datasourceId,
 					noDataToSync ? DatasourceStatus.READY : DatasourceStatus.EMBEDDING
 				),
-				incrementDatasourceTotalRecordCount(datasource.teamId, datasourceId, recordsLoaded)
+				incrementDatasourceTotalRecordCount(datasource.teamId, datasourceId, recordsLoaded),
+				// Ends the source side of the run, the proxy only cleans up after overwrite syncs once
+				// the run has processed as many records as it received
+				VectorDBProxyClient.completeSync(datasource.teamId, datasourceId).catch(e => log.error(e))
 			]);
 			io.to(datasource.teamId.toString()).emit('notification', notification);
 		}
@@ -300,10 +305,44 @@ export async function handleProblemWebhook(req, res, next) {
 	return dynamicResponse(req, res, 200, {});
 }
 
+// Webhook events with a timestamp further off than this are rejected as replays
+const WEBHOOK_TOLERANCE_SECONDS = 5 * 60;
+
+/**
+ * Verifies the HMAC signature the vector-db-proxy adds to its webhook events, computed over the
+ * raw request body. Events are rejected when WEBHOOK_SECRET is not set.
+ */
+function verifyVectorProxySignature(req): boolean {
+	const secret = process.env.WEBHOOK_SECRET;
+	if (!secret) {
+		log.error('WEBHOOK_SECRET is not set, rejecting vector-db-proxy webhook');
+		return false;
+	}
+	const timestamp = req.get('X-Webhook-Timestamp');
+	const signature = req.get('X-Webhook-Signature');
+	if (!timestamp || !signature || !req.rawBody) {
+		return false;
+	}
+	const age = Math.abs(Date.now() / 1000 - parseInt(timestamp, 10));
+	if (!(age <= WEBHOOK_TOLERANCE_SECONDS)) {
+		return false;
+	}
+	const expected = `sha256=${createHmac('sha256', secret)
+		.update(`${timestamp}.`)
+		.update(req.rawBody)
+		.digest('hex')}`;
+	return (
+		expected.length === signature.length &&
+		timingSafeEqual(Buffer.from(expected), Buffer.from(signature))
+	);
+}
+
 export async function handleSuccessfulEmbeddingWebhook(req, res, next) {
 	log.info('handleSuccessfulEmbeddingWebhook body %O', req.body);
 
-	//TODO: validate some kind of webhook key
+	if (!verifyVectorProxySignature(req)) {
+		return dynamicResponse(req, res, 401, { error: 'Invalid signature' });
+	}
 
 	// TODO: body validation
 	const { datasourceId } = req.body;
@@ -339,6 +378,51 @@ export async function handleSuccessfulEmbeddingWebhook(req, res, next) {
 	return dynamicResponse(req, res, 200, {});
 }
 
+export async function handleEmbeddingEventWebhook(req, res, next) {
+	log.info('handleEmbeddingEventWebhook body %O', req.body);
+
+	if (!verifyVectorProxySignature(req)) {
+		return dynamicResponse(req, res, 401, { error: 'Invalid signature' });
+	}
+
+	const { event, datasourceId, data } = req.body || {};
+	if (event !== 'embed.failed') {
+		return dynamicResponse(req, res, 200, {});
+	}
+
+	const datasource = await unsafeGetDatasourceById(datasourceId);
+	if (datasource) {
+		const notification = {
+			orgId: toObjectId(datasource.orgId.toString()),
+			teamId: toObjectId(datasource.teamId.toString()),
+			target: {
+				id: datasourceId,
+				collection: CollectionName.Notifications,
+				property: '_id',
+				objectId: true
+			},
+			title: 'Embedding Failed',
+			date: new Date(),
+			seen: false,
+			// stuff specific to notification type
+			description: data?.lastError
+				? `Embedding failed for datasource "${datasource.name}": ${data.lastError}`
+				: `Embedding failed for datasource "${datasource.name}".`,
+			type: NotificationType.Webhook,
+			details: {
+				webhookType: WebhookType.EmbeddingFailed
+			} as NotificationDetails
+		};
+		await Promise.all([
+			addNotification(notification),
+			setDatasourceStatus(datasource.teamId, datasourceId, DatasourceStatus.ERROR)
+		]);
+		io.to(datasource.teamId.toString()).emit('notification', notification);
+	}
+
+	return dynamicResponse(req, res, 200, {});
+}
+
 export async function checkAirbyteConnection(req, res, next) {
 	const status = await airbyteSetup.checkAirbyteStatus();
 
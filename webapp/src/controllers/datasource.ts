@@ -29,6 +29,7 @@ import { isVectorLimitReached } from 'lib/vectorproxy/limit';
 import getFileFormat from 'misc/getfileformat';
 import toObjectId from 'misc/toobjectid';
 import toSnakeCase from 'misc/tosnakecase';
+import validateEmbeddingTemplate from 'misc/validateembeddingtemplate';
 import { ObjectId } from 'mongodb';
 import path from 'path';
 import MessageQueueProviderFactory from 'queue/index';
@@ -438,6 +439,24 @@ export async function addDatasourceApi(req, res, next) {
 				error: `Stream configuration for "${streamName}" is incomplete or malformed.`
 			});
 		}
+		if (
+			'embeddingTemplate' in config &&
+			config.embeddingTemplate != null &&
+			typeof config.embeddingTemplate !== 'string'
+		) {
+			return dynamicResponse(req, res, 400, {
+				error: `Embedding template for "${streamName}" must be a string.`
+			});
+		}
+		const templateError =
+			typeof config.embeddingTemplate === 'string' &&
+			config.embeddingTemplate.trim().length > 0 &&
+			validateEmbeddingTemplate(config.embeddingTemplate);
+		if (templateError) {
+			return dynamicResponse(req, res, 400, {
+				error: `Embedding template for "${streamName}" is invalid: ${templateError}.`
+			});
+		}
 	}
 
 	const connectionsApi = await getAirbyteApi(AirbyteApiType.CONNECTIONS);
@@ -520,7 +539,7 @@ export async function addDatasourceApi(req, res, next) {
 	});
 
 	try {
-		await VectorDBProxyClient.createCollection(datasourceId, {
+		await VectorDBProxyClient.createCollection(req.params.resourceSlug, datasourceId, {
 			cloud,
 			region,
 			collection_name: collectionName,
@@ -709,6 +728,24 @@ export async function updateDatasourceStreamsApi(req, res, next) {
 				error: `Stream configuration for "${streamName}" is incomplete or malformed.`
 			});
 		}
+		if (
+			'embeddingTemplate' in config &&
+			config.embeddingTemplate != null &&
+			typeof config.embeddingTemplate !== 'string'
+		) {
+			return dynamicResponse(req, res, 400, {
+				error: `Embedding template for "${streamName}" must be a string.`
+			});
+		}
+		const templateError =
+			typeof config.embeddingTemplate === 'string' &&
+			config.embeddingTemplate.trim().length > 0 &&
+			validateEmbeddingTemplate(config.embeddingTemplate);
+		if (templateError) {
+			return dynamicResponse(req, res, 400, {
+				error: `Embedding template for "${streamName}" is invalid: ${templateError}.`
+			});
+		}
 	}
 
 	//update the metadata map of tools if found
@@ -776,7 +813,7 @@ export async function updateDatasourceStreamsApi(req, res, next) {
 	if (sync === true) {
 		// Create the collection in qdrant
 		try {
-			await VectorDBProxyClient.createCollection(datasourceId);
+			await VectorDBProxyClient.createCollection(req.params.resourceSlug, datasourceId);
 		} catch (e) {
 			console.error(e);
 			return dynamicResponse(req, res, 400, {
@@ -823,7 +860,7 @@ export async function syncDatasourceApi(req, res, next) {
 
 	// Create the collection in qdrant
 	try {
-		await VectorDBProxyClient.createCollection(datasourceId);
+		await VectorDBProxyClient.createCollection(req.params.resourceSlug, datasourceId);
 	} catch (e) {
 		console.error(e);
 		return dynamicResponse(req, res, 400, {
@@ -957,7 +994,10 @@ export async function deleteDatasourceApi(req, res, next) {
 
 	// Delete the points in qdrant
 	try {
-		await VectorDBProxyClient.deleteCollection(req.params.datasourceId);
+		await VectorDBProxyClient.deleteCollection(
+			req.params.resourceSlug,
+			req.params.datasourceId
+		);
 	} catch (e) {
 		console.error(e);
 		return dynamicResponse(req, res, 400, {
@@ -1137,7 +1177,7 @@ export async function uploadFileApi(req, res, next) {
 
 	// Create the collection in qdrant
 	try {
-		await VectorDBProxyClient.createCollection(newDatasourceId);
+		await VectorDBProxyClient.createCollection(req.params.resourceSlug, newDatasourceId);
 	} catch (e) {
 		console.error(e);
 		return dynamicResponse(req, res, 400, {
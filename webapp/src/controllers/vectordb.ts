@@ -9,6 +9,7 @@ import {
 	getVectorDbsByTeam,
 	updateVectorDb
 } from 'db/vectordb';
+import encryptCredential from 'misc/encryptcredential';
 import toObjectId from 'misc/toobjectid';
 import { VectorDb } from 'struct/vectordb';
 
@@ -77,7 +78,7 @@ export async function addVectorDbApi(req, res, next) {
 	const newVectorDb: VectorDb = {
 		orgId: toObjectId(res.locals.matchingOrg.id),
 		teamId: toObjectId(req.params.resourceSlug),
-		apiKey: cleanTextInput(req.body.apiKey),
+		apiKey: encryptCredential(cleanTextInput(req.body.apiKey)),
 		url: cleanTextInput(req.body.url),
 		type: cleanTextInput(req.body.type),
 		name: cleanTextInput(req.body.name)
@@ -98,11 +99,17 @@ export async function addVectorDbApi(req, res, next) {
 export async function editVectorDbApi(req, res, next) {
 	const updatedVectorDB: Partial<VectorDb> = {
 		type: cleanTextInput(req.body.type),
-		apiKey: cleanTextInput(req.body.apiKey),
 		url: cleanTextInput(req.body.url),
 		name: cleanTextInput(req.body.name)
 	};
 
+	// The form sends back the stored, already encrypted, key unless it was changed
+	const apiKey = cleanTextInput(req.body.apiKey);
+	const existingVectorDb = await getVectorDbById(req.params.vectorDbId);
+	if (apiKey !== existingVectorDb?.apiKey) {
+		updatedVectorDB.apiKey = encryptCredential(apiKey);
+	}
+
 	await updateVectorDb(req.params.vectorDbId, updatedVectorDB);
 	return dynamicResponse(req, res, 200, {});
 }
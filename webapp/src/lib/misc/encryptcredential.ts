@@ -0,0 +1,34 @@
+'use strict';
+
+import { createCipheriv, randomBytes } from 'crypto';
+
+// Same format the vector proxy decrypts: enc:v1:<key id>:<base64 of nonce, ciphertext and tag>
+const ENCRYPTED_PREFIX = 'enc:v1:';
+
+/**
+ * Encrypts a vector DB credential with the first key of VECTOR_DB_ENCRYPTION_KEYS
+ * (`<key id>:<base64 AES-256 key>,...`). Credentials are stored as they are when no key is configured.
+ */
+export default function encryptCredential(credential: string): string {
+	if (!credential) {
+		return credential;
+	}
+	const currentKey = (process.env.VECTOR_DB_ENCRYPTION_KEYS || '')
+		.split(',')
+		.map(k => k.trim())
+		.find(k => k.length > 0);
+	if (!currentKey) {
+		return credential;
+	}
+	const separator = currentKey.indexOf(':');
+	const keyId = currentKey.slice(0, separator);
+	const key = Buffer.from(currentKey.slice(separator + 1), 'base64');
+	if (separator < 1 || key.length !== 32) {
+		throw new Error('VECTOR_DB_ENCRYPTION_KEYS must be <key id>:<base64 32 byte key>,...');
+	}
+	const nonce = randomBytes(12);
+	const cipher = createCipheriv('aes-256-gcm', key, nonce);
+	const ciphertext = Buffer.concat([cipher.update(credential, 'utf8'), cipher.final()]);
+	const payload = Buffer.concat([nonce, ciphertext, cipher.getAuthTag()]);
+	return `${ENCRYPTED_PREFIX}${keyId}:${payload.toString('base64')}`;
+}
@@ -0,0 +1,30 @@
+import { describe, expect, test } from '@jest/globals';
+
+import validateEmbeddingTemplate from './validateembeddingtemplate';
+
+const testCases = [
+	{ template: '{title}\n{description}', expected: null },
+	{ template: '{author.name|Unknown author}', expected: null },
+	{ template: '{#category}Category: {category}{^tags}, untagged{/tags}{/category}', expected: null },
+	{ template: '{{literal}} braces', expected: null },
+	{ template: '{title', expected: 'Unclosed `{` in embedding template' },
+	{ template: 'title}', expected: 'Unexpected `}` in embedding template' },
+	{ template: '{|fallback}', expected: 'Empty field name in embedding template' },
+	{ template: '{/category}', expected: '`{/category}` closes no section in embedding template' },
+	{
+		template: '{#category}{#tags}{/category}{/tags}',
+		expected: '`{/category}` closes section `tags` in embedding template'
+	},
+	{
+		template: '{#category}{title}',
+		expected: 'Section `category` is never closed in embedding template'
+	}
+];
+
+describe('Test validateEmbeddingTemplate() util', () => {
+	for (let { template, expected } of testCases) {
+		test(`Test validateEmbeddingTemplate(${JSON.stringify(template)}) -> ${expected}`, () => {
+			expect(validateEmbeddingTemplate(template)).toBe(expected);
+		});
+	}
+});
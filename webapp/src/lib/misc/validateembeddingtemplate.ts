@@ -0,0 +1,45 @@
+// Mirrors EmbeddingTemplate::parse in vector-db-proxy/src/embeddings/template.rs, so that a
+// template the proxy can not parse is rejected when the stream config is saved rather than failing
+// every record of the sync. Returns the error, or null when the template is valid.
+export default function validateEmbeddingTemplate(template: string): string | null {
+	const openSections: string[] = [];
+	let i = 0;
+	while (i < template.length) {
+		const c = template[i];
+		if ((c === '{' || c === '}') && template[i + 1] === c) {
+			i += 2;
+			continue;
+		}
+		if (c === '}') {
+			return 'Unexpected `}` in embedding template';
+		}
+		if (c !== '{') {
+			i++;
+			continue;
+		}
+		const end = template.indexOf('}', i + 1);
+		if (end === -1) {
+			return 'Unclosed `{` in embedding template';
+		}
+		const tag = template.slice(i + 1, end).trim();
+		i = end + 1;
+		if (tag.startsWith('#') || tag.startsWith('^')) {
+			openSections.push(tag.slice(1).trim());
+		} else if (tag.startsWith('/')) {
+			const path = tag.slice(1).trim();
+			const openPath = openSections.pop();
+			if (openPath === undefined) {
+				return `\`{/${path}}\` closes no section in embedding template`;
+			}
+			if (openPath !== path) {
+				return `\`{/${path}}\` closes section \`${openPath}\` in embedding template`;
+			}
+		} else if (tag.split('|')[0].trim() === '') {
+			return 'Empty field name in embedding template';
+		}
+	}
+	if (openSections.length > 0) {
+		return `Section \`${openSections[openSections.length - 1]}\` is never closed in embedding template`;
+	}
+	return null;
+}
@@ -72,6 +72,9 @@ export type StreamConfig = {
 	syncMode: SyncMode;
 	cursorField: string[];
 	descriptionsMap: FieldDescriptionMap;
+	// e.g. "{title}\n{description}{#category}\nCategory: {category}{/category}", rendered from each
+	// record to the text that is embedded instead of the datasource's embeddingField
+	embeddingTemplate?: string;
 };
 
 export type StreamConfigMap = {
@@ -147,6 +150,7 @@ export interface Datasource {
 	createdDate: Date;
 	lastSyncedDate?: Date | null; //Note: null = never synced
 	status?: DatasourceStatus;
+	quotaError?: string; //Set by the vector proxy while the team is over one of its quotas
 	discoveredSchema?: any;
 	chunkingConfig?: UnstructuredChunkingConfig;
 	embeddingField?: string;
@@ -19,7 +19,8 @@ export enum WebhookType {
 	ConnectionUpdatesRequiringAction = 'ConnectionUpdatesRequiringAction',
 	WarningRepeatedFailures = 'WarningRepeatedFailures',
 	SyncDisabledRepeatedFailures = 'SyncDisabledRepeatedFailures',
-	EmbeddingCompleted = 'EmbeddingCompleted'
+	EmbeddingCompleted = 'EmbeddingCompleted',
+	EmbeddingFailed = 'EmbeddingFailed'
 }
 
 export type WebhookDetails = {
@@ -1,5 +1,6 @@
 'use strict';
 
+import { createHmac } from 'crypto';
 import dotenv from 'dotenv';
 dotenv.config({ path: '.env' });
 
@@ -11,14 +12,35 @@ import { createLogger } from 'utils/logger';
 
 const log = createLogger('webapp:vectordb:proxy');
 
+// Lifetime of the service tokens issued for requests to the vector proxy
+const SERVICE_TOKEN_TTL_SECONDS = 60;
+
+// Signs a short lived service token scoping the request to the team
+function authHeaders(teamId: IdOrStr): Record<string, string> {
+	const claims = Buffer.from(
+		JSON.stringify({
+			teamId: teamId.toString(),
+			exp: Math.floor(Date.now() / 1000) + SERVICE_TOKEN_TTL_SECONDS
+		})
+	).toString('base64url');
+	const signature = createHmac('sha256', process.env.VECTOR_PROXY_API_KEY || '')
+		.update(claims)
+		.digest('hex');
+	return { authorization: `Bearer ${claims}.${signature}` };
+}
+
 class VectorDBProxyClient {
 	static async createCollection(
+		teamId: IdOrStr,
 		collectionId: IdOrStr,
 		createOptions?: CollectionCreateBody
 	): Promise<any> {
 		log.info('createCollection %s %O', collectionId, createOptions);
 		// Note: Checks if the collection exists beforehand
-		const collectionExists: VectorResponseBody = await this.checkCollectionExists(collectionId);
+		const collectionExists: VectorResponseBody = await this.checkCollectionExists(
+			teamId,
+			collectionId
+		);
 		log.info('collectionExists res:', collectionExists);
 		if (collectionExists?.error_message) {
 			//TODO: have vector-db-poxy return a boolean or something logical for actually just knowing if the collection exists or not
@@ -51,7 +73,8 @@ class VectorDBProxyClient {
 		return fetch(`${process.env.VECTOR_APP_URL}/api/v1/create-collection/`, {
 			method: 'POST',
 			headers: {
-				'content-type': 'application/json'
+				'content-type': 'application/json',
+				...authHeaders(teamId)
 			},
 			body: JSON.stringify(createOptions)
 		}).then(res => {
@@ -61,11 +84,14 @@ class VectorDBProxyClient {
 	}
 
 	// Method to check collection exists
-	static async checkCollectionExists(collectionId: IdOrStr): Promise<VectorResponseBody> {
+	static async checkCollectionExists(
+		teamId: IdOrStr,
+		collectionId: IdOrStr
+	): Promise<VectorResponseBody> {
 		log.info('checkCollectionExists %s', collectionId);
-		return fetch(
-			`${process.env.VECTOR_APP_URL}/api/v1/check-collection-exists/${collectionId}`
-		).then(res => {
+		return fetch(`${process.env.VECTOR_APP_URL}/api/v1/check-collection-exists/${collectionId}`, {
+			headers: authHeaders(teamId)
+		}).then(res => {
 			return res.json();
 		});
 	}
@@ -73,16 +99,31 @@ class VectorDBProxyClient {
 	// Method to get the total storage size for the team
 	static async getVectorStorageForTeam(teamId: IdOrStr): Promise<VectorResponseBody> {
 		log.info('getVectorStorageForTeam %s', teamId);
-		return fetch(`${process.env.VECTOR_APP_URL}/api/v1/storage-size/${teamId}`).then(res => {
+		return fetch(`${process.env.VECTOR_APP_URL}/api/v1/storage-size/${teamId}`, {
+			headers: authHeaders(teamId)
+		}).then(res => {
 			return res.json();
 		});
 	}
 
 	// Method to delete a collection
-	static async deleteCollection(collectionId: IdOrStr): Promise<VectorResponseBody> {
+	static async deleteCollection(
+		teamId: IdOrStr,
+		collectionId: IdOrStr
+	): Promise<VectorResponseBody> {
 		log.info('deleteCollection %s', collectionId);
 		return fetch(`${process.env.VECTOR_APP_URL}/api/v1/collection/${collectionId}`, {
-			method: 'DELETE'
+			method: 'DELETE',
+			headers: authHeaders(teamId)
+		}).then(res => res.json());
+	}
+
+	// Method to signal that the source has finished sending records for a sync
+	static async completeSync(teamId: IdOrStr, datasourceId: IdOrStr): Promise<VectorResponseBody> {
+		log.info('completeSync %s', datasourceId);
+		return fetch(`${process.env.VECTOR_APP_URL}/api/v1/sync-complete/${datasourceId}`, {
+			method: 'POST',
+			headers: authHeaders(teamId)
 		}).then(res => res.json());
 	}
 }
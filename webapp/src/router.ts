@@ -108,7 +108,15 @@ export default function router(server, app) {
 
 	// Body and query parsing middleware
 	server.set('query parser', 'simple');
-	server.use(bodyParser.json({ limit: '10mb' }));
+	server.use(
+		bodyParser.json({
+			limit: '10mb',
+			// Kept for webhooks signed over the exact bytes that were sent
+			verify: (req: any, _res, buf) => {
+				req.rawBody = buf;
+			}
+		})
+	);
 	server.use(bodyParser.urlencoded({ extended: false }));
 	// Default options for express-fileupload
 	server.use(
@@ -347,6 +355,7 @@ export default function router(server, app) {
 	webhookRouter.use('/sync-successful', airbyteProxyController.handleSuccessfulSyncWebhook);
 	webhookRouter.use('/sync-problem', airbyteProxyController.handleProblemWebhook);
 	webhookRouter.use('/embed-successful', airbyteProxyController.handleSuccessfulEmbeddingWebhook); //TODO: move these to webhooks controller?
+	webhookRouter.use('/embed-event', airbyteProxyController.handleEmbeddingEventWebhook);
 	server.use('/webhook', webhookRouter);
 
 	const teamRouter = Router({ mergeParams: true, caseSensitive: true });